@@ -131,12 +131,12 @@ fn back_wall() -> Plane {
 }
 
 fn scene() -> World {
-    let spheres = sphere_in_a_sphere(0.0, 1.0, 1.0, col::RED);
+    let [outer, inner] = sphere_in_a_sphere(0.0, 1.0, 1.0, col::RED);
     World::default()
         .with_light(light_source())
         .with_object(floor())
-        .with_object(spheres[0])
-        .with_object(spheres[1])
+        .with_object(outer)
+        .with_object(inner)
         .with_object(solid_sphere(1.5, 1.0, -2.5, col::MAGENTA))
         .with_object(back_wall())
         .with_object(mirror_sphere(-2.0, 1.0, -1.8))
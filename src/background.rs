@@ -0,0 +1,291 @@
+use crate::canvas::Canvas;
+use crate::color::consts;
+use crate::color::Color;
+use crate::matrix::Mat4;
+use crate::tup::Tup;
+use std::f64::consts::PI;
+
+// What a ray sees when it misses every object in the world. Lookups only
+// depend on the direction of the ray, so the background behaves as if it
+// were infinitely far away
+#[derive(Clone, Debug)]
+pub struct Background {
+    background: Backgrounds,
+    transform: Mat4,
+}
+
+impl Background {
+    pub fn solid(color: Color) -> Self {
+        Self {
+            background: Backgrounds::Solid(color),
+            ..Self::default()
+        }
+    }
+
+    // Blends from `horizon` for horizontal rays to `zenith` for rays pointing
+    // straight up. Rays pointing below the horizon see the horizon color
+    pub fn gradient(horizon: Color, zenith: Color) -> Self {
+        Self {
+            background: Backgrounds::Gradient(GradientSky::new(horizon, zenith)),
+            ..Self::default()
+        }
+    }
+
+    // A latitude/longitude panorama. The center of the image is seen when
+    // looking down the negative z axis and the top row is straight up
+    pub fn equirectangular(image: Canvas) -> Self {
+        Self {
+            background: Backgrounds::Equirectangular(EquirectangularMap::new(image)),
+            ..Self::default()
+        }
+    }
+
+    // Faces are given in the order +x, -x, +y, -y, +z, -z
+    pub fn cube_map(faces: [Canvas; 6]) -> Self {
+        Self {
+            background: Backgrounds::CubeMap(CubeMap::new(faces)),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    pub fn color_at(&self, direction: Tup) -> Color {
+        let local_direction = (self.transform.inverse() * direction).normalize();
+        self.background.color(local_direction)
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            background: Backgrounds::Solid(consts::BLACK),
+            transform: Mat4::identity_matrix(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GradientSky {
+    horizon: Color,
+    zenith: Color,
+}
+
+impl GradientSky {
+    fn new(horizon: Color, zenith: Color) -> Self {
+        Self { horizon, zenith }
+    }
+
+    fn color_at(&self, direction: Tup) -> Color {
+        let fraction = direction.y.max(0.0);
+        self.horizon + (self.zenith - self.horizon) * fraction
+    }
+}
+
+#[derive(Clone, Debug)]
+struct EquirectangularMap {
+    image: Canvas,
+}
+
+impl EquirectangularMap {
+    fn new(image: Canvas) -> Self {
+        Self { image }
+    }
+
+    fn color_at(&self, direction: Tup) -> Color {
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        self.image.color_at_uv(u, v)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CubeMap {
+    faces: Box<[Canvas; 6]>,
+}
+
+impl CubeMap {
+    fn new(faces: [Canvas; 6]) -> Self {
+        Self {
+            faces: Box::new(faces),
+        }
+    }
+
+    // Picks the face along the dominant axis of `direction` and projects the
+    // remaining two components onto it, following the usual cube map layout
+    // where each face is viewed from the center of the cube
+    fn face_and_uv(direction: Tup) -> (usize, f64, f64) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+        let (face, major, sc, tc) = if abs_x >= abs_y && abs_x >= abs_z {
+            if x > 0.0 {
+                (0, abs_x, -z, -y)
+            } else {
+                (1, abs_x, z, -y)
+            }
+        } else if abs_y >= abs_z {
+            if y > 0.0 {
+                (2, abs_y, x, z)
+            } else {
+                (3, abs_y, x, -z)
+            }
+        } else if z > 0.0 {
+            (4, abs_z, x, -y)
+        } else {
+            (5, abs_z, -x, -y)
+        };
+        let u = (sc / major + 1.0) / 2.0;
+        let v = (tc / major + 1.0) / 2.0;
+        (face, u, v)
+    }
+
+    fn color_at(&self, direction: Tup) -> Color {
+        let (face, u, v) = Self::face_and_uv(direction);
+        let image = &self.faces[face];
+        let x = u.clamp(0.0, 1.0 - f64::EPSILON) * image.width() as f64;
+        let y = v.clamp(0.0, 1.0 - f64::EPSILON) * image.height() as f64;
+        image.pixel_at(x as usize, y as usize)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Backgrounds {
+    Solid(Color),
+    Gradient(GradientSky),
+    Equirectangular(EquirectangularMap),
+    CubeMap(CubeMap),
+}
+
+impl Backgrounds {
+    fn color(&self, direction: Tup) -> Color {
+        match self {
+            Backgrounds::Solid(color) => *color,
+            Backgrounds::Gradient(b) => b.color_at(direction),
+            Backgrounds::Equirectangular(b) => b.color_at(direction),
+            Backgrounds::CubeMap(b) => b.color_at(direction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod background_test {
+    use super::*;
+    use crate::color::consts as color;
+    use crate::transforms;
+
+    #[test]
+    fn the_default_background_is_black() {
+        let b = Background::default();
+        assert_eq!(color::BLACK, b.color_at(Tup::vector(0, 0, 1)));
+    }
+
+    #[test]
+    fn a_solid_background_is_the_same_in_every_direction() {
+        let b = Background::solid(color::CYAN);
+        assert_eq!(color::CYAN, b.color_at(Tup::vector(0, 0, 1)));
+        assert_eq!(color::CYAN, b.color_at(Tup::vector(1, -1, 0)));
+    }
+
+    #[test]
+    fn a_gradient_background_is_the_horizon_color_for_horizontal_rays() {
+        let b = Background::gradient(color::WHITE, color::BLUE);
+        assert_eq!(color::WHITE, b.color_at(Tup::vector(1, 0, 0)));
+        assert_eq!(color::WHITE, b.color_at(Tup::vector(0, -1, 1)));
+    }
+
+    #[test]
+    fn a_gradient_background_is_the_zenith_color_straight_up() {
+        let b = Background::gradient(color::WHITE, color::BLUE);
+        assert_eq!(color::BLUE, b.color_at(Tup::vector(0, 1, 0)));
+    }
+
+    #[test]
+    fn a_gradient_background_blends_between_horizon_and_zenith() {
+        let b = Background::gradient(color::WHITE, color::BLACK);
+        let c = b.color_at(Tup::vector(0.0, 0.5, 3.0_f64.sqrt() / 2.0));
+        assert_eq!(Color::new(0.5, 0.5, 0.5), c);
+    }
+
+    fn four_by_two_panorama() -> Canvas {
+        // Top row is sky, bottom row is ground
+        let mut image = Canvas::new(4, 2);
+        for (row, col, pixel) in image.enumerate_pixels_mut() {
+            *pixel = match (row, col) {
+                (0, _) => color::BLUE,
+                (_, 1) | (_, 2) => color::RED,
+                _ => color::GREEN,
+            };
+        }
+        image
+    }
+
+    #[test]
+    fn an_equirectangular_background_looks_up_the_sky_above_the_horizon() {
+        let b = Background::equirectangular(four_by_two_panorama());
+        assert_eq!(color::BLUE, b.color_at(Tup::vector(0, 1, 0)));
+    }
+
+    #[test]
+    fn an_equirectangular_background_centers_the_image_on_negative_z() {
+        let b = Background::equirectangular(four_by_two_panorama());
+        assert_eq!(color::RED, b.color_at(Tup::vector(0, -1, -1)));
+        assert_eq!(color::GREEN, b.color_at(Tup::vector(0, -1, 1)));
+    }
+
+    #[test]
+    fn a_background_can_be_rotated() {
+        let b = Background::equirectangular(four_by_two_panorama())
+            .with_transform(transforms::rotation_y(std::f64::consts::PI));
+        assert_eq!(color::GREEN, b.color_at(Tup::vector(0, -1, -1)));
+        assert_eq!(color::RED, b.color_at(Tup::vector(0, -1, 1)));
+    }
+
+    fn solid_face(color: Color) -> Canvas {
+        let mut face = Canvas::new(2, 2);
+        for pixel in face.pixels_mut() {
+            *pixel = color;
+        }
+        face
+    }
+
+    #[test]
+    fn a_cube_map_background_looks_up_the_face_along_the_dominant_axis() {
+        let b = Background::cube_map([
+            solid_face(color::RED),
+            solid_face(color::CYAN),
+            solid_face(color::GREEN),
+            solid_face(color::MAGENTA),
+            solid_face(color::BLUE),
+            solid_face(color::YELLOW),
+        ]);
+        assert_eq!(color::RED, b.color_at(Tup::vector(1.0, 0.2, -0.3)));
+        assert_eq!(color::CYAN, b.color_at(Tup::vector(-1.0, 0.5, 0.5)));
+        assert_eq!(color::GREEN, b.color_at(Tup::vector(0.1, 1.0, 0.1)));
+        assert_eq!(color::MAGENTA, b.color_at(Tup::vector(0.0, -1.0, 0.0)));
+        assert_eq!(color::BLUE, b.color_at(Tup::vector(0.0, 0.0, 1.0)));
+        assert_eq!(color::YELLOW, b.color_at(Tup::vector(0.3, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn a_cube_map_face_is_addressed_from_the_center_of_the_cube() {
+        let mut front = Canvas::new(2, 2);
+        front = front.write_pixel(0, 0, color::WHITE);
+        let b = Background::cube_map([
+            solid_face(color::BLACK),
+            solid_face(color::BLACK),
+            solid_face(color::BLACK),
+            solid_face(color::BLACK),
+            front,
+            solid_face(color::BLACK),
+        ]);
+        // Looking down +z the top left of the face is up and to the left
+        assert_eq!(color::WHITE, b.color_at(Tup::vector(-0.5, 0.5, 1.0)));
+        assert_eq!(color::BLACK, b.color_at(Tup::vector(0.5, 0.5, 1.0)));
+    }
+}
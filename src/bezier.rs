@@ -0,0 +1,787 @@
+use crate::bounds::Bounds;
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::sampling;
+use crate::shapes::Shape;
+use crate::triangles::SmoothTriangle;
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// The weights of the four control points along one direction of a cubic
+// patch, and how fast they change
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [
+        -3.0 * s * s,
+        3.0 * s * s - 6.0 * t * s,
+        6.0 * t * s - 3.0 * t * t,
+        3.0 * t * t,
+    ]
+}
+
+// A bicubic Bézier patch, a curved quadrilateral pulled towards a four by
+// four grid of control points. It passes through the four corner points
+// only. Points are given a row at a time, u runs along each row and v from
+// the first row to the last. The normal is the cross product of the
+// directions of increasing u and v, which points out of the classic teapot
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct BezierPatch {
+    points: [Tup; 16],
+}
+
+impl BezierPatch {
+    pub fn new(points: [Tup; 16]) -> Self {
+        Self { points }
+    }
+
+    pub fn points(&self) -> [Tup; 16] {
+        self.points
+    }
+
+    // The sum of the control points with the weights for u along each row
+    // and v down each column
+    fn blend(&self, along_u: [f64; 4], along_v: [f64; 4]) -> Tup {
+        let mut sum = Tup::vector(0, 0, 0);
+        for (row, wv) in along_v.iter().enumerate() {
+            for (column, wu) in along_u.iter().enumerate() {
+                let p = self.points[row * 4 + column];
+                sum = sum + Tup::vector(p.x, p.y, p.z) * (wu * wv);
+            }
+        }
+        sum
+    }
+
+    pub fn point_at(&self, u: f64, v: f64) -> Tup {
+        let sum = self.blend(bernstein(u), bernstein(v));
+        Tup::point(sum.x, sum.y, sum.z)
+    }
+
+    // How the surface moves as u and v increase
+    pub fn derivatives(&self, u: f64, v: f64) -> (Tup, Tup) {
+        (
+            self.blend(bernstein_derivative(u), bernstein(v)),
+            self.blend(bernstein(u), bernstein_derivative(v)),
+        )
+    }
+
+    // Where a row of control points collapses to one point, like the top of
+    // the teapot's lid, one derivative vanishes and the normal is taken from
+    // just beside it instead
+    pub fn normal_at(&self, u: f64, v: f64) -> Tup {
+        let (du, dv) = self.derivatives(u, v);
+        let mut normal = du.cross(&dv);
+        if normal.magnitude() < 1e-12 {
+            let nudge = |t: f64| t + (0.5 - t) * 1e-3;
+            let (du, dv) = self.derivatives(nudge(u), nudge(v));
+            normal = du.cross(&dv);
+        }
+        // The cross product of a tiny patch is below the tolerance of
+        // `normalize`, so its length is divided out directly
+        normal * (1.0 / normal.magnitude())
+    }
+
+    // The patch lies inside the hull of its control points
+    pub fn bounds(&self) -> Bounds {
+        Bounds::around(self.points)
+    }
+
+    // Smooth triangles across a grid of `level` by `level` steps in u and v,
+    // with normals taken from the patch
+    pub fn tessellate(&self, level: usize) -> Vec<SmoothTriangle> {
+        self.facets(level).into_iter().map(|f| f.triangle).collect()
+    }
+
+    fn facets(&self, level: usize) -> Vec<Facet> {
+        let level = level.max(1);
+        let step = 1.0 / level as f64;
+        let corner = |i: usize, j: usize| {
+            let (u, v) = (i as f64 * step, j as f64 * step);
+            (self.point_at(u, v), self.normal_at(u, v), (u, v))
+        };
+        let mut facets = Vec::with_capacity(2 * level * level);
+        for j in 0..level {
+            for i in 0..level {
+                let a = corner(i, j);
+                let b = corner(i + 1, j);
+                let c = corner(i + 1, j + 1);
+                let d = corner(i, j + 1);
+                for (p1, p2, p3) in [(a, b, c), (a, c, d)] {
+                    // Corners that collapse to a point leave slivers with no
+                    // area, which can never be hit
+                    if (p2.0 - p1.0).cross(&(p3.0 - p1.0)).magnitude() < 1e-12 {
+                        continue;
+                    }
+                    facets.push(Facet {
+                        triangle: SmoothTriangle::new(p1.0, p2.0, p3.0, p1.1, p2.1, p3.1),
+                        uvs: [p1.2, p2.2, p3.2],
+                    });
+                }
+            }
+        }
+        facets
+    }
+
+    // Newton's method on the point where the patch meets `ray`, starting
+    // from a guess of (u, v, t)
+    fn refine(&self, ray: &Ray, (mut u, mut v, mut t): (f64, f64, f64)) -> Option<(f64, f64, f64)> {
+        let d = ray.direction();
+        for _ in 0..NEWTON_STEPS {
+            let miss = self.point_at(u, v) - ray.position(t);
+            let (du, dv) = self.derivatives(u, v);
+            let det = du.dot(&dv.cross(&-d));
+            if det.abs() < 1e-14 {
+                return None;
+            }
+            let step_u = -miss.dot(&dv.cross(&-d)) / det;
+            let step_v = -du.dot(&miss.cross(&-d)) / det;
+            let step_t = -du.dot(&dv.cross(&miss)) / det;
+            u += step_u;
+            v += step_v;
+            t += step_t;
+            if !(-0.5..=1.5).contains(&u) || !(-0.5..=1.5).contains(&v) {
+                return None;
+            }
+            if step_u.abs() + step_v.abs() < 1e-12 {
+                break;
+            }
+        }
+        let on_patch = |x: f64| (-1e-9..=1.0 + 1e-9).contains(&x);
+        let miss = self.point_at(u, v) - ray.position(t);
+        if on_patch(u) && on_patch(v) && miss.magnitude() < 1e-7 {
+            Some((u.clamp(0.0, 1.0), v.clamp(0.0, 1.0), t))
+        } else {
+            None
+        }
+    }
+
+    // The (u, v) of the point on the patch nearest `point`, and its squared
+    // distance, found with Gauss and Newton's method from `guess`
+    fn nearest(&self, point: Tup, (mut u, mut v): (f64, f64)) -> (f64, f64, f64) {
+        for _ in 0..NEWTON_STEPS {
+            let miss = point - self.point_at(u, v);
+            let (du, dv) = self.derivatives(u, v);
+            let (a, b, c) = (du.dot(&du), du.dot(&dv), dv.dot(&dv));
+            let det = a * c - b * b;
+            if det.abs() < 1e-14 {
+                break;
+            }
+            let (ru, rv) = (du.dot(&miss), dv.dot(&miss));
+            u = (u + (c * ru - b * rv) / det).clamp(0.0, 1.0);
+            v = (v + (a * rv - b * ru) / det).clamp(0.0, 1.0);
+        }
+        let miss = point - self.point_at(u, v);
+        (u, v, miss.dot(&miss))
+    }
+}
+
+// Iterations of Newton's method, which usually settles in a handful
+const NEWTON_STEPS: usize = 24;
+
+// Subdivisions of each patch used to find starting points for Newton's
+// method when patches are intersected directly
+const GUESS_LEVEL: usize = 8;
+
+#[derive(PartialEq, Clone, Debug)]
+struct Facet {
+    triangle: SmoothTriangle,
+    // The patch's u and v at each corner
+    uvs: [(f64, f64); 3],
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    UnexpectedEnd,
+    InvalidNumber {
+        line: usize,
+        text: String,
+    },
+    WrongCount {
+        line: usize,
+        expected: usize,
+        actual: usize,
+    },
+    IndexOutOfRange {
+        line: usize,
+        index: usize,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnexpectedEnd => write!(f, "patch data ends early"),
+            PatchError::InvalidNumber { line, text } => {
+                write!(f, "line {line}: `{text}` is not a number")
+            }
+            PatchError::WrongCount {
+                line,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "line {line}: expected {expected} numbers but got {actual}"
+            ),
+            PatchError::IndexOutOfRange { line, index } => {
+                write!(f, "line {line}: there is no vertex {index}")
+            }
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+// Reads patches in the text format the Utah teapot is usually shared in:
+// the number of patches, then a line of sixteen vertex numbers for each,
+// counting from one, then the number of vertices and a line of x, y and z
+// for each. Numbers may be separated by commas or spaces
+pub fn read_patches(text: &str) -> Result<Vec<BezierPatch>, PatchError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty());
+    let mut next_numbers = |expected: usize| -> Result<(usize, Vec<f64>), PatchError> {
+        let (line, text) = lines.next().ok_or(PatchError::UnexpectedEnd)?;
+        let numbers = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                word.parse::<f64>().map_err(|_| PatchError::InvalidNumber {
+                    line,
+                    text: word.to_string(),
+                })
+            })
+            .collect::<Result<Vec<f64>, PatchError>>()?;
+        if numbers.len() != expected {
+            return Err(PatchError::WrongCount {
+                line,
+                expected,
+                actual: numbers.len(),
+            });
+        }
+        Ok((line, numbers))
+    };
+    // The counts aren't trusted to size anything up front, since the file
+    // may end long before them
+    let patch_count = next_numbers(1)?.1[0] as usize;
+    let mut indices = Vec::new();
+    for _ in 0..patch_count {
+        indices.push(next_numbers(16)?);
+    }
+    let vertex_count = next_numbers(1)?.1[0] as usize;
+    let mut vertices = Vec::new();
+    for _ in 0..vertex_count {
+        let (_, xyz) = next_numbers(3)?;
+        vertices.push(Tup::point(xyz[0], xyz[1], xyz[2]));
+    }
+    indices
+        .into_iter()
+        .map(|(line, numbers)| {
+            let mut points = [Tup::point(0, 0, 0); 16];
+            for (point, number) in points.iter_mut().zip(numbers) {
+                let index = number as usize;
+                *point = index
+                    .checked_sub(1)
+                    .and_then(|i| vertices.get(i))
+                    .copied()
+                    .ok_or(PatchError::IndexOutOfRange { line, index })?;
+            }
+            Ok(BezierPatch::new(points))
+        })
+        .collect()
+}
+
+// Patches with what's needed to intersect them, shared by every copy of
+// the surface
+struct PatchSet {
+    patches: Vec<BezierPatch>,
+    bounds: Vec<Bounds>,
+    // The triangles each patch is drawn with, or just used to find starting
+    // points for Newton's method
+    facets: Vec<Vec<Facet>>,
+    direct: bool,
+}
+
+impl fmt::Debug for PatchSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PatchSet {{ patches: {}, direct: {}, at: {:p} }}",
+            self.patches.len(),
+            self.direct,
+            self
+        )
+    }
+}
+
+// What was found out about the surface where a ray hit it, carried by the
+// copy of the surface in the intersection so it needn't be found again
+#[derive(Copy, Clone)]
+struct SurfaceHit {
+    point: Tup,
+    normal: Tup,
+    tangent: Tup,
+    uv: (f64, f64),
+}
+
+// A surface of Bézier patches, such as the teapot. It can be tessellated
+// into smooth triangles when it's made, which is fast to render, or each
+// patch can be intersected exactly with Newton's method, which is slower
+// but smooth however closely it's looked at
+#[derive(Clone)]
+pub struct BezierSurface {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    set: Arc<PatchSet>,
+    hit: Option<SurfaceHit>,
+}
+
+impl BezierSurface {
+    // `level` is the number of steps each patch is divided into along u and
+    // v, so each patch becomes 2 × level² triangles
+    pub fn tessellated(patches: Vec<BezierPatch>, level: usize) -> Self {
+        Self::with_set(patches, level, false)
+    }
+
+    pub fn direct(patches: Vec<BezierPatch>) -> Self {
+        Self::with_set(patches, GUESS_LEVEL, true)
+    }
+
+    fn with_set(patches: Vec<BezierPatch>, level: usize, direct: bool) -> Self {
+        let set = PatchSet {
+            bounds: patches.iter().map(|p| p.bounds()).collect(),
+            facets: patches.iter().map(|p| p.facets(level)).collect(),
+            patches,
+            direct,
+        };
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            set: Arc::new(set),
+            hit: None,
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn patches(&self) -> &[BezierPatch] {
+        &self.set.patches
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.set.direct
+    }
+
+    fn hit_on_facet(&self, facet: &Facet, u: f64, v: f64, point: Tup) -> SurfaceHit {
+        let [uv1, uv2, uv3] = facet.uvs;
+        let w = 1.0 - u - v;
+        let triangle = facet.triangle.triangle();
+        SurfaceHit {
+            point,
+            normal: facet.triangle.normal_at_uv(u, v),
+            tangent: (triangle.p2() - triangle.p1()).normalize(),
+            uv: (
+                uv1.0 * w + uv2.0 * u + uv3.0 * v,
+                uv1.1 * w + uv2.1 * u + uv3.1 * v,
+            ),
+        }
+    }
+
+    fn hit_on_patch(&self, patch: &BezierPatch, u: f64, v: f64, point: Tup) -> SurfaceHit {
+        let (du, _) = patch.derivatives(u, v);
+        let normal = patch.normal_at(u, v);
+        let tangent = if du.magnitude() > 1e-12 {
+            du.normalize()
+        } else {
+            sampling::orthonormal_basis(normal).0
+        };
+        SurfaceHit {
+            point,
+            normal,
+            tangent,
+            uv: (u, v),
+        }
+    }
+
+    fn intersect_patch(&self, index: usize, ray: &Ray, hits: &mut Vec<(f64, SurfaceHit)>) {
+        let patch = &self.set.patches[index];
+        let found = hits.len();
+        for facet in &self.set.facets[index] {
+            let Some((t, u, v)) = facet.triangle.triangle().barycentric_hit(ray) else {
+                continue;
+            };
+            let point = ray.position(t);
+            if !self.set.direct {
+                hits.push((t, self.hit_on_facet(facet, u, v, point)));
+                continue;
+            }
+            let [uv1, uv2, uv3] = facet.uvs;
+            let w = 1.0 - u - v;
+            let guess = (
+                uv1.0 * w + uv2.0 * u + uv3.0 * v,
+                uv1.1 * w + uv2.1 * u + uv3.1 * v,
+                t,
+            );
+            if let Some((u, v, t)) = patch.refine(ray, guess) {
+                // Neighbouring guesses often settle on the same point
+                if hits[found..]
+                    .iter()
+                    .all(|(other, _)| (other - t).abs() > 1e-6)
+                {
+                    hits.push((t, self.hit_on_patch(patch, u, v, ray.position(t))));
+                }
+            }
+        }
+    }
+
+    // Where a hit wasn't recorded, the nearest point on any patch
+    fn locate(&self, point: Tup) -> SurfaceHit {
+        let mut best: Option<(usize, f64, f64, f64)> = None;
+        for (index, patch) in self.set.patches.iter().enumerate() {
+            for facet in &self.set.facets[index] {
+                let (u, v, distance) = patch.nearest(point, facet.uvs[0]);
+                if best.is_none_or(|b| distance < b.3) {
+                    best = Some((index, u, v, distance));
+                }
+            }
+        }
+        match best {
+            Some((index, u, v, _)) => self.hit_on_patch(&self.set.patches[index], u, v, point),
+            // A surface without patches is never hit, but answers like an
+            // empty group if asked
+            None => SurfaceHit {
+                point,
+                normal: Tup::vector(0, 1, 0),
+                tangent: Tup::vector(1, 0, 0),
+                uv: (0.0, 0.0),
+            },
+        }
+    }
+
+    fn hit_at(&self, point: Tup) -> SurfaceHit {
+        match self.hit {
+            Some(hit) if (hit.point - point).magnitude() < 1e-4 => hit,
+            _ => self.locate(point),
+        }
+    }
+}
+
+// Surfaces are told apart by their id alone. The copies carried by
+// intersections remember where they were hit, but are still the same
+// surface
+impl fmt::Debug for BezierSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BezierSurface")
+            .field("id", &self.id)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .field("set", &self.set)
+            .finish()
+    }
+}
+
+impl PartialEq for BezierSurface {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Shape for BezierSurface {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let mut hits = Vec::new();
+        for (index, bounds) in self.set.bounds.iter().enumerate() {
+            if bounds.span(&local_ray).is_some() {
+                self.intersect_patch(index, &local_ray, &mut hits);
+            }
+        }
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let xs: Vec<Intersection> = hits
+            .into_iter()
+            .map(|(t, hit)| {
+                let surface = Self {
+                    hit: Some(hit),
+                    ..self.clone()
+                };
+                Intersection::new(t, surface)
+            })
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        self.hit_at(point).normal
+    }
+
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        self.hit_at(point).uv
+    }
+
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        self.hit_at(point).tangent
+    }
+}
+
+#[cfg(test)]
+mod bezier_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+
+    // A square from -1 to 1 in x and z with its middle raised into a dome,
+    // facing up
+    fn dome() -> BezierPatch {
+        let mut points = [Tup::point(0, 0, 0); 16];
+        for row in 0..4 {
+            for column in 0..4 {
+                let x = -1.0 + 2.0 * column as f64 / 3.0;
+                let z = 1.0 - 2.0 * row as f64 / 3.0;
+                let inner = (1..3).contains(&row) && (1..3).contains(&column);
+                let y = if inner { 1.0 } else { 0.0 };
+                points[row * 4 + column] = Tup::point(x, y, z);
+            }
+        }
+        BezierPatch::new(points)
+    }
+
+    const DOME: &str = "1
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16
+        16
+        -1.0, 0.0, 1.0
+        -0.3333333333333333, 0.0, 1.0
+        0.3333333333333333, 0.0, 1.0
+        1.0, 0.0, 1.0
+        -1.0, 0.0, 0.3333333333333333
+        -0.3333333333333333, 1.0, 0.3333333333333333
+        0.3333333333333333, 1.0, 0.3333333333333333
+        1.0, 0.0, 0.3333333333333333
+        -1.0, 0.0, -0.3333333333333333
+        -0.3333333333333333, 1.0, -0.3333333333333333
+        0.3333333333333333, 1.0, -0.3333333333333333
+        1.0, 0.0, -0.3333333333333333
+        -1.0, 0.0, -1.0
+        -0.3333333333333333, 0.0, -1.0
+        0.3333333333333333, 0.0, -1.0
+        1.0, 0.0, -1.0
+    ";
+
+    #[test]
+    fn a_patch_passes_through_its_corners() {
+        let p = dome();
+        assert_eq!(Tup::point(-1, 0, 1), p.point_at(0.0, 0.0));
+        assert_eq!(Tup::point(1, 0, 1), p.point_at(1.0, 0.0));
+        assert_eq!(Tup::point(-1, 0, -1), p.point_at(0.0, 1.0));
+        assert_eq!(Tup::point(1, 0, -1), p.point_at(1.0, 1.0));
+        assert_eq!(Tup::point(0.0, 0.5625, 0.0), p.point_at(0.5, 0.5));
+    }
+
+    #[test]
+    fn derivatives_match_small_steps_across_the_patch() {
+        let p = dome();
+        let (u, v, h) = (0.3, 0.6, 1e-6);
+        let (du, dv) = p.derivatives(u, v);
+        assert_eq!(
+            du,
+            (p.point_at(u + h, v) - p.point_at(u - h, v)) * (0.5 / h)
+        );
+        assert_eq!(
+            dv,
+            (p.point_at(u, v + h) - p.point_at(u, v - h)) * (0.5 / h)
+        );
+    }
+
+    #[test]
+    fn the_top_of_the_dome_faces_up() {
+        assert_eq!(Tup::vector(0, 1, 0), dome().normal_at(0.5, 0.5));
+        let n = dome().normal_at(0.1, 0.5);
+        assert!(n.x < 0.0 && n.y > 0.0);
+    }
+
+    #[test]
+    fn a_collapsed_row_of_control_points_still_has_a_normal() {
+        let mut points = dome().points();
+        for point in points.iter_mut().take(4) {
+            *point = Tup::point(0, 0, 1);
+        }
+        let n = BezierPatch::new(points).normal_at(0.5, 0.0);
+        assert!(n.magnitude().is_finite());
+        assert_nearly_eq(1.0, n.magnitude());
+    }
+
+    #[test]
+    fn tessellation_makes_two_triangles_per_step_on_the_surface() {
+        let p = dome();
+        let triangles = p.tessellate(4);
+        assert_eq!(32, triangles.len());
+        assert_eq!(p.point_at(0.25, 0.5), triangles[2 * 9].triangle().p1());
+        assert_eq!(p.normal_at(0.25, 0.5), triangles[2 * 9].n1());
+    }
+
+    #[test]
+    fn patches_are_read_from_text() {
+        let patches = read_patches(DOME).expect("Valid patch data");
+        assert_eq!(1, patches.len());
+        assert_eq!(dome(), patches[0]);
+    }
+
+    #[test]
+    fn bad_patch_data_is_an_error() {
+        assert!(matches!(
+            read_patches("1\n1 2 3\n"),
+            Err(PatchError::WrongCount {
+                line: 2,
+                expected: 16,
+                actual: 3
+            })
+        ));
+        let beyond = format!("1\n{}2\n1\n0 0 0\n", "1 ".repeat(15));
+        assert!(matches!(
+            read_patches(&beyond),
+            Err(PatchError::IndexOutOfRange { line: 2, index: 2 })
+        ));
+        assert!(matches!(
+            read_patches("1\n"),
+            Err(PatchError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            read_patches("one\n"),
+            Err(PatchError::InvalidNumber { line: 1, .. })
+        ));
+        assert!(matches!(
+            read_patches("1e30\n"),
+            Err(PatchError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn a_surface_without_patches_has_default_normals() {
+        let empty = read_patches("0\n0\n").expect("Valid patch data");
+        let point = Tup::point(0, 0, 0);
+        for s in [
+            BezierSurface::direct(empty.clone()),
+            BezierSurface::tessellated(empty, 4),
+        ] {
+            assert!(s
+                .intersect(&Ray::new(point, Tup::vector(0, 1, 0)))
+                .is_empty());
+            assert_eq!(Tup::vector(0, 1, 0), s.normal_at(point));
+            assert_eq!((0.0, 0.0), s.uv_at(point));
+        }
+    }
+
+    #[test]
+    fn a_patch_is_intersected_directly() {
+        let s = BezierSurface::direct(vec![dome()]);
+        assert!(s.is_direct());
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        let xs = s.intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_nearly_eq(4.4375, xs[0].t());
+        let point = ray.position(xs[0].t());
+        assert_eq!(Tup::vector(0, 1, 0), xs[0].object().normal_at(point));
+        let (u, v) = xs[0].object().uv_at(point);
+        assert_nearly_eq(0.5, u);
+        assert_nearly_eq(0.5, v);
+    }
+
+    #[test]
+    fn a_slanted_ray_lands_on_the_patch() {
+        let p = dome();
+        let s = BezierSurface::direct(vec![p]);
+        let ray = Ray::new(Tup::point(-3.0, 2.0, -0.4), Tup::vector(1.0, -0.6, 0.2));
+        let xs = s.intersect(&ray);
+        assert!(!xs.is_empty());
+        let point = ray.position(xs[0].t());
+        let (_, _, distance) = p.nearest(point, (0.5, 0.5));
+        assert!(distance < 1e-12);
+    }
+
+    #[test]
+    fn a_tessellated_patch_is_close_to_the_real_one() {
+        let direct = BezierSurface::direct(vec![dome()]);
+        let tessellated = BezierSurface::tessellated(vec![dome()], 16);
+        assert!(!tessellated.is_direct());
+        let ray = Ray::new(Tup::point(0.3, 5.0, -0.2), Tup::vector(0, -1, 0));
+        let exact = direct.intersect(&ray);
+        let approximate = tessellated.intersect(&ray);
+        assert_eq!(1, approximate.len());
+        assert!((exact[0].t() - approximate[0].t()).abs() < 0.01);
+        let point = ray.position(approximate[0].t());
+        let n = approximate[0].object().normal_at(point);
+        assert!(n.dot(&direct.normal_at(ray.position(exact[0].t()))) > 0.999);
+    }
+
+    #[test]
+    fn normals_can_be_found_without_a_hit() {
+        let s = BezierSurface::direct(vec![dome()]);
+        let point = dome().point_at(0.2, 0.7);
+        assert_eq!(dome().normal_at(0.2, 0.7), s.local_normal_at(point));
+    }
+
+    #[test]
+    fn the_surface_in_an_intersection_is_the_same_surface() {
+        let s = BezierSurface::tessellated(vec![dome()], 4);
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        let xs = s.intersect(&ray);
+        assert_eq!(format!("{:?}", s), format!("{:?}", xs[0].object()));
+        assert_ne!(s, BezierSurface::tessellated(vec![dome()], 4));
+    }
+
+    #[test]
+    fn hits_on_several_patches_are_in_order() {
+        let mut points = dome().points();
+        for p in points.iter_mut() {
+            *p = Tup::point(p.x, p.y - 2.0, p.z);
+        }
+        let s = BezierSurface::direct(vec![BezierPatch::new(points), dome()]);
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        let xs = s.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert!(xs[0].t() < xs[1].t());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::math_helpers::EPSILON;
+use crate::rays::Ray;
+use crate::tup::Tup;
+
+// An axis aligned box, used to skip over parts of complex shapes that a ray
+// doesn't come near
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Bounds {
+    min: Tup,
+    max: Tup,
+}
+
+impl Bounds {
+    pub fn new(min: Tup, max: Tup) -> Self {
+        Self { min, max }
+    }
+
+    // Holds nothing, so adding the first point makes a box around just it
+    pub fn empty() -> Self {
+        let infinity = f64::INFINITY;
+        Self {
+            min: Tup::point(infinity, infinity, infinity),
+            max: Tup::point(-infinity, -infinity, -infinity),
+        }
+    }
+
+    pub fn around<I: IntoIterator<Item = Tup>>(points: I) -> Self {
+        points
+            .into_iter()
+            .fold(Self::empty(), |bounds, p| bounds.with_point(p))
+    }
+
+    pub fn with_point(self, point: Tup) -> Self {
+        Self {
+            min: Tup::point(
+                self.min.x.min(point.x),
+                self.min.y.min(point.y),
+                self.min.z.min(point.z),
+            ),
+            max: Tup::point(
+                self.max.x.max(point.x),
+                self.max.y.max(point.y),
+                self.max.z.max(point.z),
+            ),
+        }
+    }
+
+    pub fn with_bounds(self, other: Bounds) -> Self {
+        self.with_point(other.min).with_point(other.max)
+    }
+
+    pub fn min(&self) -> Tup {
+        self.min
+    }
+
+    pub fn max(&self) -> Tup {
+        self.max
+    }
+
+    pub fn center(&self) -> Tup {
+        Tup::point(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    // The span of t in which `ray` is inside the box, if it passes through
+    // at all. Boxes are padded slightly so flat ones can still be hit
+    pub fn span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let slab = |origin: f64, direction: f64, min: f64, max: f64| {
+            let (min, max) = (min - EPSILON, max + EPSILON);
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    (f64::INFINITY, f64::NEG_INFINITY)
+                } else {
+                    (f64::NEG_INFINITY, f64::INFINITY)
+                }
+            } else {
+                let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+                (t0.min(t1), t0.max(t1))
+            }
+        };
+        let spans = [
+            slab(o.x, d.x, self.min.x, self.max.x),
+            slab(o.y, d.y, self.min.y, self.max.y),
+            slab(o.z, d.z, self.min.z, self.max.z),
+        ];
+        let near = spans.iter().fold(f64::NEG_INFINITY, |m, s| m.max(s.0));
+        let far = spans.iter().fold(f64::INFINITY, |m, s| m.min(s.1));
+        if near > far {
+            None
+        } else {
+            Some((near, far))
+        }
+    }
+}
+
+#[cfg(test)]
+mod bounds_test {
+    use super::*;
+
+    #[test]
+    fn bounds_grow_to_hold_every_point() {
+        let b = Bounds::around([Tup::point(1, -2, 3), Tup::point(-1, 4, 0)]);
+        assert_eq!(Tup::point(-1, -2, 0), b.min());
+        assert_eq!(Tup::point(1, 4, 3), b.max());
+        assert_eq!(Tup::point(0.0, 1.0, 1.5), b.center());
+    }
+
+    #[test]
+    fn a_ray_passes_through_a_box() {
+        let b = Bounds::new(Tup::point(-1, -1, -1), Tup::point(1, 1, 1));
+        let ray = Ray::new(Tup::point(0.5, 0.0, -5.0), Tup::vector(0, 0, 1));
+        let (near, far) = b.span(&ray).expect("Hits the box");
+        assert!((near - 4.0).abs() < 1e-4);
+        assert!((far - 6.0).abs() < 1e-4);
+        let beside = Ray::new(Tup::point(2.0, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(None, b.span(&beside));
+    }
+
+    #[test]
+    fn a_flat_box_can_still_be_hit() {
+        let b = Bounds::around([Tup::point(-1, 0, -1), Tup::point(1, 0, 1)]);
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert!(b.span(&ray).is_some());
+    }
+}
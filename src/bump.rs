@@ -0,0 +1,264 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::matrix::Mat4;
+use crate::noise;
+use crate::patterns::Pattern;
+use crate::tup::Tup;
+use std::fmt;
+use std::sync::Arc;
+
+// Fine surface detail that changes how a surface is shaded without changing
+// its shape. The shading normal is tilted away from the shape's own normal,
+// either as read from a normal map or by following the slope of a height
+// field such as noise
+#[derive(PartialEq, Clone, Debug)]
+pub enum Bump {
+    // A tangent space normal map, see `NormalMap`. `strength` scales the
+    // tilt, one leaves the map as it is
+    NormalMap {
+        map: Arc<NormalMap>,
+        strength: f64,
+    },
+    // Bumps shaped like fractal noise in the object's space, `height` tall
+    Noise {
+        frequency: f64,
+        octaves: usize,
+        height: f64,
+    },
+    // Bumps following the brightness of a pattern, `height` tall where the
+    // pattern is white. Patterns with hard edges give hard edged grooves
+    Pattern {
+        pattern: Pattern,
+        height: f64,
+    },
+}
+
+impl Bump {
+    // Materials copied from one with a normal map all use the same map
+    pub fn normal_map(map: NormalMap, strength: f64) -> Self {
+        Self::NormalMap {
+            map: Arc::new(map),
+            strength,
+        }
+    }
+
+    pub fn noise(frequency: f64, octaves: usize, height: f64) -> Self {
+        Self::Noise {
+            frequency,
+            octaves,
+            height,
+        }
+    }
+
+    pub fn pattern(pattern: Pattern, height: f64) -> Self {
+        Self::Pattern { pattern, height }
+    }
+
+    // Distance between the points sampled to find the slope of a height
+    // field
+    const DELTA: f64 = 1e-4;
+
+    // Tilts `normal`, the shape's own unit normal at `point`. `tangent`
+    // points along the surface in the direction of increasing u, and `uv`
+    // are the texture coordinates of the point
+    pub fn perturb(
+        &self,
+        world_to_object: Mat4,
+        point: Tup,
+        normal: Tup,
+        tangent: Tup,
+        uv: (f64, f64),
+    ) -> Tup {
+        // Straighten the tangent out in case the surface curves away from it
+        let tangent = (tangent - normal * normal.dot(&tangent)).normalize();
+        let bitangent = tangent.cross(&normal);
+        match self {
+            Self::NormalMap { map, strength } => {
+                let n = map.normal_at(uv.0, uv.1);
+                (tangent * (n.x * strength) + bitangent * (n.y * strength) + normal * n.z)
+                    .normalize()
+            }
+            _ => {
+                let height = |p: Tup| self.height_at(world_to_object, p);
+                let here = height(point);
+                let slope_t = (height(point + tangent * Self::DELTA) - here) / Self::DELTA;
+                let slope_b = (height(point + bitangent * Self::DELTA) - here) / Self::DELTA;
+                (normal - tangent * slope_t - bitangent * slope_b).normalize()
+            }
+        }
+    }
+
+    fn height_at(&self, world_to_object: Mat4, point: Tup) -> f64 {
+        match self {
+            Self::NormalMap { .. } => 0.0,
+            Self::Noise {
+                frequency,
+                octaves,
+                height,
+            } => {
+                let local = world_to_object * point;
+                noise::fbm(local * *frequency, *octaves) * height
+            }
+            Self::Pattern { pattern, height } => {
+                let c = pattern.color_at(world_to_object, point);
+                (c.red() + c.green() + c.blue()) / 3.0 * height
+            }
+        }
+    }
+}
+
+// An image of normals in tangent space. Red, green and blue hold the x, y
+// and z components mapped from [-1, 1] to [0, 1], with x along the tangent,
+// y towards the top of the image and z straight out of the surface. This is
+// the usual layout of normal maps that look mostly light blue
+#[derive(Clone)]
+pub struct NormalMap {
+    image: Canvas,
+}
+
+impl NormalMap {
+    pub fn new(image: Canvas) -> Self {
+        Self { image }
+    }
+
+    pub fn image(&self) -> &Canvas {
+        &self.image
+    }
+
+    pub fn normal_at(&self, u: f64, v: f64) -> Tup {
+        let c = self.image.color_at_uv(u, v);
+        let component = |value: f64| value * 2.0 - 1.0;
+        Tup::vector(
+            component(c.red()),
+            component(c.green()),
+            component(c.blue()),
+        )
+    }
+
+    // The color encoding `normal`, handy for building maps by hand
+    pub fn encode(normal: Tup) -> Color {
+        let n = normal.normalize();
+        Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0)
+    }
+}
+
+// Maps are far too big to print or compare. Two maps are equal only if they
+// are the same map
+impl fmt::Debug for NormalMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "NormalMap {{ width: {}, height: {}, at: {:p} }}",
+            self.image.width(),
+            self.image.height(),
+            self
+        )
+    }
+}
+
+impl PartialEq for NormalMap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod bump_test {
+    use super::*;
+    use crate::color::consts as color;
+    use crate::test_helpers::assert_nearly_eq;
+
+    fn flat_map(normal: Tup) -> NormalMap {
+        let mut image = Canvas::new(2, 2);
+        for pixel in image.pixels_mut() {
+            *pixel = NormalMap::encode(normal);
+        }
+        NormalMap::new(image)
+    }
+
+    fn perturb_up(bump: Bump) -> Tup {
+        bump.perturb(
+            Mat4::default(),
+            Tup::point(0.3, 0.0, 0.7),
+            Tup::vector(0, 1, 0),
+            Tup::vector(1, 0, 0),
+            (0.3, 0.3),
+        )
+    }
+
+    #[test]
+    fn a_flat_normal_map_leaves_the_normal_alone() {
+        let bump = Bump::normal_map(flat_map(Tup::vector(0, 0, 1)), 1.0);
+        let n = perturb_up(bump);
+        assert_nearly_eq(1.0, n.y);
+    }
+
+    #[test]
+    fn a_normal_map_tilts_the_normal_in_tangent_space() {
+        let tilted = Tup::vector(1, 0, 1).normalize();
+        let bump = Bump::normal_map(flat_map(tilted), 1.0);
+        let n = perturb_up(bump);
+        assert!(
+            (n - Tup::vector(1, 1, 0).normalize()).magnitude() < 1e-2,
+            "{n:?}"
+        );
+    }
+
+    #[test]
+    fn green_tilts_the_normal_towards_the_top_of_the_image() {
+        let bump = Bump::normal_map(flat_map(Tup::vector(0, 1, 1)), 1.0);
+        let n = perturb_up(bump);
+        // With the tangent along x and the normal up, as on a plane, the top
+        // of the image is towards positive z
+        assert!(n.z > 0.5, "{n:?}");
+    }
+
+    #[test]
+    fn normal_map_strength_scales_the_tilt() {
+        let tilted = Tup::vector(1, 0, 1).normalize();
+        let full = perturb_up(Bump::normal_map(flat_map(tilted), 1.0));
+        let none = perturb_up(Bump::normal_map(flat_map(tilted), 0.0));
+        assert!(full.x > 0.5);
+        assert_nearly_eq(1.0, none.y);
+    }
+
+    #[test]
+    fn noise_bumps_tilt_the_normal_by_varying_amounts() {
+        let bump = Bump::noise(4.0, 3, 0.2);
+        let normals: Vec<Tup> = (0..10)
+            .map(|i| {
+                bump.perturb(
+                    Mat4::default(),
+                    Tup::point(i as f64 * 0.37, 0.0, 0.21),
+                    Tup::vector(0, 1, 0),
+                    Tup::vector(1, 0, 0),
+                    (0.0, 0.0),
+                )
+            })
+            .collect();
+        assert!(normals.iter().all(|n| (n.magnitude() - 1.0).abs() < 1e-9));
+        assert!(normals.iter().all(|n| n.y > 0.0));
+        assert!(normals.iter().any(|n| n.y < 0.99));
+    }
+
+    #[test]
+    fn a_flat_pattern_makes_no_bumps() {
+        let bump = Bump::pattern(Pattern::stripe_pattern(color::WHITE, color::WHITE), 1.0);
+        assert_eq!(Tup::vector(0, 1, 0), perturb_up(bump));
+    }
+
+    #[test]
+    fn a_gradient_pattern_makes_a_slope() {
+        let bump = Bump::pattern(Pattern::gradient_pattern(color::BLACK, color::WHITE), 1.0);
+        let n = perturb_up(bump);
+        assert_eq!(Tup::vector(-1, 1, 0).normalize(), n);
+    }
+
+    #[test]
+    fn normal_maps_are_only_equal_to_themselves() {
+        let a = Bump::normal_map(flat_map(Tup::vector(0, 0, 1)), 1.0);
+        let b = Bump::normal_map(flat_map(Tup::vector(0, 0, 1)), 1.0);
+        assert_eq!(a, a);
+        assert_ne!(a, b);
+    }
+}
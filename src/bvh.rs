@@ -0,0 +1,205 @@
+use crate::bounds::Bounds;
+use crate::rays::Ray;
+
+// A bounding volume hierarchy: a tree of boxes over a list of items, each
+// with its own box, so a ray only needs testing against the items in the
+// boxes it passes through. Items are numbered by their place in the list
+// the hierarchy was built from
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // Item numbers, arranged so every leaf holds a run of them
+    items: Vec<usize>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Node {
+    Leaf {
+        bounds: Bounds,
+        first: usize,
+        count: usize,
+    },
+    Branch {
+        bounds: Bounds,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> Bounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => *bounds,
+        }
+    }
+}
+
+impl Bvh {
+    // Leaves hold at most this many items
+    const LEAF_SIZE: usize = 4;
+
+    pub fn new(bounds: &[Bounds]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * bounds.len() / Self::LEAF_SIZE + 1),
+            items: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            bvh.build(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+
+    // Splits the items in half at the middle of their centers along the
+    // axis they're most spread out on, and returns the new node's index
+    fn build(&mut self, bounds: &[Bounds], first: usize, end: usize) -> usize {
+        let items = &mut self.items[first..end];
+        let node_bounds = items
+            .iter()
+            .fold(Bounds::empty(), |b, &i| b.with_bounds(bounds[i]));
+        let centers = Bounds::around(items.iter().map(|&i| bounds[i].center()));
+        let extent = centers.max() - centers.min();
+        let axis_of = |i: usize, axis: usize| {
+            let c = bounds[i].center();
+            [c.x, c.y, c.z][axis]
+        };
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let spread = [extent.x, extent.y, extent.z][axis];
+        let index = self.nodes.len();
+        if items.len() <= Self::LEAF_SIZE || spread <= 0.0 {
+            self.nodes.push(Node::Leaf {
+                bounds: node_bounds,
+                first,
+                count: end - first,
+            });
+            return index;
+        }
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |&a, &b| {
+            axis_of(a, axis).total_cmp(&axis_of(b, axis))
+        });
+        // Filled in once the children exist
+        self.nodes.push(Node::Leaf {
+            bounds: node_bounds,
+            first,
+            count: 0,
+        });
+        let left = self.build(bounds, first, first + middle);
+        let right = self.build(bounds, first + middle, end);
+        self.nodes[index] = Node::Branch {
+            bounds: node_bounds,
+            left,
+            right,
+        };
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // The box around every item
+    pub fn bounds(&self) -> Bounds {
+        self.nodes
+            .first()
+            .map(|n| n.bounds())
+            .unwrap_or_else(Bounds::empty)
+    }
+
+    // Calls `visit` with every item whose leaf's box `ray` passes through
+    pub fn candidates<F: FnMut(usize)>(&self, ray: &Ray, mut visit: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = self.nodes[index];
+            if node.bounds().span(ray).is_none() {
+                continue;
+            }
+            match node {
+                Node::Leaf { first, count, .. } => {
+                    for &item in &self.items[first..first + count] {
+                        visit(item);
+                    }
+                }
+                Node::Branch { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod bvh_test {
+    use super::*;
+    use crate::tup::Tup;
+
+    // Unit cubes in a row along x, starting at the origin
+    fn cubes(count: usize) -> Vec<Bounds> {
+        (0..count)
+            .map(|i| {
+                let x = i as f64 * 2.0;
+                Bounds::new(Tup::point(x, 0.0, 0.0), Tup::point(x + 1.0, 1.0, 1.0))
+            })
+            .collect()
+    }
+
+    fn candidates(bvh: &Bvh, ray: &Ray) -> Vec<usize> {
+        let mut found = Vec::new();
+        bvh.candidates(ray, |i| found.push(i));
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn an_empty_hierarchy_has_no_candidates() {
+        let bvh = Bvh::new(&[]);
+        assert!(bvh.is_empty());
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert!(candidates(&bvh, &ray).is_empty());
+    }
+
+    #[test]
+    fn the_hierarchy_is_bounded_by_all_its_items() {
+        let bvh = Bvh::new(&cubes(10));
+        assert_eq!(10, bvh.len());
+        assert_eq!(Tup::point(0, 0, 0), bvh.bounds().min());
+        assert_eq!(Tup::point(19, 1, 1), bvh.bounds().max());
+    }
+
+    #[test]
+    fn a_ray_only_visits_the_leaves_it_passes_through() {
+        let bvh = Bvh::new(&cubes(100));
+        let ray = Ray::new(Tup::point(150.5, 0.5, -5.0), Tup::vector(0, 0, 1));
+        let found = candidates(&bvh, &ray);
+        assert!(found.contains(&75));
+        assert!(found.len() <= Bvh::LEAF_SIZE);
+    }
+
+    #[test]
+    fn a_ray_along_the_row_visits_every_item_once() {
+        let bvh = Bvh::new(&cubes(37));
+        let ray = Ray::new(Tup::point(-5.0, 0.5, 0.5), Tup::vector(1, 0, 0));
+        assert_eq!((0..37).collect::<Vec<usize>>(), candidates(&bvh, &ray));
+    }
+
+    #[test]
+    fn items_in_the_same_place_share_a_leaf() {
+        let same = vec![Bounds::new(Tup::point(0, 0, 0), Tup::point(1, 1, 1)); 9];
+        let bvh = Bvh::new(&same);
+        let ray = Ray::new(Tup::point(0.5, 0.5, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(9, candidates(&bvh, &ray).len());
+    }
+}
@@ -1,9 +1,24 @@
 use crate::canvas::Canvas;
+use crate::color::consts as col;
+use crate::color::Color;
 use crate::matrix::Mat4;
+use crate::path_tracer::PathTracer;
 use crate::rays::Ray;
+use crate::sampling::Rng;
+use crate::spectrum;
 use crate::tup::Tup;
 use crate::world::World;
 
+// How the color seen through each pixel is computed
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum Integrator {
+    // Recursive Phong shading with perfect reflection and refraction, see
+    // `World::color_at`
+    #[default]
+    Whitted,
+    PathTraced(PathTracer),
+}
+
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub struct Camera {
     hsize: usize,
@@ -11,6 +26,10 @@ pub struct Camera {
     field_of_view: f64,
     transform: Mat4,
     log_progress: bool,
+    integrator: Integrator,
+    // When set, pixels are rendered one wavelength at a time and the results
+    // combined into a color, see `with_spectral_samples`
+    spectral_samples: Option<usize>,
 }
 
 impl Camera {
@@ -21,6 +40,8 @@ impl Camera {
             field_of_view,
             transform: Mat4::identity_matrix(),
             log_progress: false,
+            integrator: Integrator::default(),
+            spectral_samples: None,
         }
     }
 
@@ -35,6 +56,22 @@ impl Camera {
         }
     }
 
+    pub fn with_integrator(self, integrator: Integrator) -> Self {
+        Self { integrator, ..self }
+    }
+
+    // Renders with individual wavelengths of light instead of red, green and
+    // blue, so dispersion and materials with explicit spectra come out right.
+    // Whitted renders trace `samples` evenly spaced wavelengths per pixel.
+    // Path traced renders give each path its own wavelength instead, so
+    // there the sample count only has to be nonzero
+    pub fn with_spectral_samples(self, samples: usize) -> Self {
+        Self {
+            spectral_samples: Some(samples).filter(|&samples| samples > 0),
+            ..self
+        }
+    }
+
     pub fn hsize(&self) -> usize {
         self.hsize
     }
@@ -51,6 +88,14 @@ impl Camera {
         self.transform
     }
 
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn spectral_samples(&self) -> Option<usize> {
+        self.spectral_samples
+    }
+
     fn half_width_and_height(&self) -> (f64, f64) {
         let half_view = (self.field_of_view() / 2.0).tan();
         let aspect_ratio = self.hsize() as f64 / self.vsize() as f64;
@@ -67,9 +112,15 @@ impl Camera {
     }
 
     pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
+        self.ray_for_subpixel(px, py, 0.5, 0.5)
+    }
+
+    // Like `ray_for_pixel`, but passes through the point at fractional offset
+    // (`dx`, `dy`) from the top left corner of the pixel instead of its center
+    pub fn ray_for_subpixel(&self, px: usize, py: usize, dx: f64, dy: f64) -> Ray {
         let pixel_size = self.pixel_size();
-        let x_offset = (px as f64 + 0.5) * pixel_size;
-        let y_offset = (py as f64 + 0.5) * pixel_size;
+        let x_offset = (px as f64 + dx) * pixel_size;
+        let y_offset = (py as f64 + dy) * pixel_size;
         let (half_width, half_height) = self.half_width_and_height();
         let world_x = half_width - x_offset;
         let world_y = half_height - y_offset;
@@ -87,11 +138,62 @@ impl Camera {
         print!("{:.0}% complete\r", percent_complete);
     }
 
+    fn path_traced_color(
+        &self,
+        world: &World,
+        tracer: PathTracer,
+        row: usize,
+        col: usize,
+    ) -> Color {
+        let samples = tracer.samples_per_pixel().max(1);
+        let mut rng = Rng::new((row * self.hsize + col) as u64);
+        if self.spectral_samples.is_some() {
+            let mut radiances = Vec::with_capacity(samples);
+            for i in 0..samples {
+                let ray = self.ray_for_subpixel(col, row, rng.next_f64(), rng.next_f64());
+                let wavelength = spectrum::wavelength_in_stratum(i, samples, rng.next_f64());
+                let color = tracer.trace(world, ray.with_wavelength(wavelength), &mut rng);
+                radiances.push((wavelength, Self::spectral_radiance(color)));
+            }
+            return spectrum::wavelengths_to_color(radiances);
+        }
+        let mut sum = col::BLACK;
+        for _ in 0..samples {
+            let ray = self.ray_for_subpixel(col, row, rng.next_f64(), rng.next_f64());
+            sum = sum + tracer.trace(world, ray, &mut rng);
+        }
+        sum * (1.0 / samples as f64)
+    }
+
+    fn spectral_color(&self, world: &World, samples: usize, row: usize, col: usize) -> Color {
+        let ray = self.ray_for_pixel(col, row);
+        spectrum::wavelengths_to_color(spectrum::stratified_wavelengths(samples).map(
+            |wavelength| {
+                let color = world.color_at(ray.with_wavelength(wavelength), World::MAX_BOUNCES);
+                (wavelength, Self::spectral_radiance(color))
+            },
+        ))
+    }
+
+    // Everything seen by a ray with a wavelength is gray, so any channel
+    // holds the radiance at that wavelength
+    fn spectral_radiance(color: Color) -> f64 {
+        (color.red() + color.green() + color.blue()) / 3.0
+    }
+
     pub fn render(&self, world: &World) -> Canvas {
         let mut image = Canvas::new(self.hsize, self.vsize);
         for (row, col, pixel) in image.enumerate_pixels_mut() {
-            let ray = self.ray_for_pixel(col, row);
-            let color = world.color_at(ray, World::MAX_BOUNCES);
+            let color = match self.integrator {
+                Integrator::Whitted => match self.spectral_samples {
+                    Some(samples) => self.spectral_color(world, samples, row, col),
+                    None => {
+                        let ray = self.ray_for_pixel(col, row);
+                        world.color_at(ray, World::MAX_BOUNCES)
+                    }
+                },
+                Integrator::PathTraced(tracer) => self.path_traced_color(world, tracer, row, col),
+            };
             *pixel = color;
 
             if self.log_progress {
@@ -105,7 +207,9 @@ impl Camera {
 #[cfg(test)]
 mod camera_test {
     use super::*;
-    use crate::color::Color;
+    use crate::background::Background;
+    use crate::materials::Material;
+    use crate::spheres::Sphere;
     use crate::test_helpers::{assert_nearly_eq, default_test_world};
     use crate::transforms;
     use std::f64::consts;
@@ -146,6 +250,32 @@ mod camera_test {
         assert_eq!(Mat4::identity_matrix(), camera.transform());
     }
 
+    #[test]
+    fn a_camera_uses_the_whitted_integrator_by_default() {
+        let camera = Camera::new(160, 120, consts::PI / 2.0);
+        assert_eq!(Integrator::Whitted, camera.integrator());
+    }
+
+    #[test]
+    fn a_camera_renders_rgb_by_default() {
+        let camera = Camera::new(160, 120, consts::PI / 2.0);
+        assert_eq!(None, camera.spectral_samples());
+    }
+
+    #[test]
+    fn a_camera_can_render_spectrally() {
+        let camera = Camera::new(160, 120, consts::PI / 2.0).with_spectral_samples(16);
+        assert_eq!(Some(16), camera.spectral_samples());
+        assert_eq!(None, camera.with_spectral_samples(0).spectral_samples());
+    }
+
+    #[test]
+    fn a_camera_integrator_can_be_selected() {
+        let integrator = Integrator::PathTraced(PathTracer::new(8));
+        let camera = Camera::new(160, 120, consts::PI / 2.0).with_integrator(integrator);
+        assert_eq!(integrator, camera.integrator());
+    }
+
     #[test]
     fn camera_knows_pixel_size_for_horizontal_canvas() {
         let hsize = 200;
@@ -186,6 +316,16 @@ mod camera_test {
         assert_eq!(Tup::vector(0.66519, 0.33259, -0.66851), r.direction());
     }
 
+    #[test]
+    fn a_ray_through_a_subpixel_offset_can_be_calculated() {
+        let camera = Camera::new(201, 101, consts::PI / 2.0);
+        let center = camera.ray_for_subpixel(100, 50, 0.5, 0.5);
+        assert_eq!(camera.ray_for_pixel(100, 50), center);
+        let corner = camera.ray_for_subpixel(0, 0, 0.0, 0.0);
+        assert!(corner.direction().x > camera.ray_for_pixel(0, 0).direction().x);
+        assert!(corner.direction().y > camera.ray_for_pixel(0, 0).direction().y);
+    }
+
     #[test]
     fn a_ray_can_be_calculated_when_camera_is_transfomed() {
         let hsize = 201;
@@ -215,4 +355,63 @@ mod camera_test {
         let image = camera.render(&world);
         assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855))
     }
+
+    #[test]
+    fn a_world_can_be_path_traced_from_a_camera() {
+        let world = default_test_world().with_background(Background::solid(col::CYAN));
+        let from = Tup::point(0, 0, -5);
+        let to = Tup::point(0, 0, 0);
+        let up = Tup::vector(0, 1, 0);
+        let transform = transforms::view_transform(from, to, up);
+        let camera = Camera::new(11, 11, consts::PI / 2.0)
+            .with_transform(transform)
+            .with_integrator(Integrator::PathTraced(PathTracer::new(4)));
+        let image = camera.render(&world);
+        assert_eq!(col::CYAN, image.pixel_at(0, 0));
+        assert_ne!(col::CYAN, image.pixel_at(5, 5));
+    }
+
+    #[test]
+    fn gray_renders_the_same_spectrally() {
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let world = World::default().with_background(Background::solid(gray));
+        let camera = Camera::new(3, 3, consts::PI / 2.0).with_spectral_samples(8);
+        assert_eq!(gray, camera.render(&world).pixel_at(1, 1));
+        let camera = camera.with_integrator(Integrator::PathTraced(PathTracer::new(8)));
+        assert_eq!(gray, camera.render(&world).pixel_at(1, 1));
+    }
+
+    #[test]
+    fn colors_keep_their_hue_when_rendered_spectrally() {
+        let world = World::default().with_background(Background::solid(col::CYAN));
+        let camera = Camera::new(3, 3, consts::PI / 2.0).with_spectral_samples(16);
+        let c = camera.render(&world).pixel_at(0, 0);
+        assert!(c.green() > 0.8 && c.blue() > 0.8, "{c:?}");
+        assert!(c.red() < 0.2, "{c:?}");
+    }
+
+    #[test]
+    fn dispersive_glass_is_rendered_spectrally() {
+        // A ray through the lower half of a glass ball is bent upwards
+        // towards the bright sky, blue more than red
+        let glass = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_transparency(1.0)
+            .with_refractive_index(1.5)
+            .with_abbe_number(20.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(glass))
+            .with_background(Background::gradient(col::BLACK, col::WHITE));
+        let camera = Camera::new(1, 1, 0.01)
+            .with_transform(transforms::view_transform(
+                Tup::point(0.0, -0.5, -5.0),
+                Tup::point(0.0, -0.5, 0.0),
+                Tup::vector(0, 1, 0),
+            ))
+            .with_spectral_samples(16);
+        let c = camera.render(&world).pixel_at(0, 0);
+        assert!(c.blue() > c.red(), "{c:?}");
+    }
 }
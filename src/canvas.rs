@@ -1,7 +1,11 @@
 use crate::color::Color;
+use crate::hdr::{self, HdrError};
+use pix::rgb::SRgb8;
 use pix::Raster;
-use png_pong::{Encoder, PngRaster};
+use png_pong::{Decoder, Encoder, PngRaster};
+use std::fmt;
 
+#[derive(Clone)]
 pub struct Canvas {
     width: usize,
     pixels: Vec<Color>,
@@ -13,6 +17,49 @@ impl Canvas {
         Self { width, pixels }
     }
 
+    pub fn from_png(bytes: &[u8]) -> Result<Self, png_pong::decode::Error> {
+        let step = Decoder::new(bytes)?
+            .into_steps()
+            .next()
+            // Truncated files can end before the first frame
+            .unwrap_or(Err(png_pong::decode::Error::Empty))?;
+        let (width, rgb_pixels) = match step.raster {
+            PngRaster::Palette(indices, palette, _) => {
+                let entries: Vec<SRgb8> = indices
+                    .as_u8_slice()
+                    .iter()
+                    .map(|&i| palette.entry(i as usize).unwrap_or_default())
+                    .collect();
+                let raster = Raster::with_pixels(indices.width(), indices.height(), entries);
+                Self::rgb_bytes(&raster)
+            }
+            PngRaster::Gray8(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Gray16(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Rgb8(r) => Self::rgb_bytes(&r),
+            PngRaster::Rgb16(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Graya8(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Graya16(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Rgba8(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+            PngRaster::Rgba16(r) => Self::rgb_bytes(&Raster::<SRgb8>::with_raster(&r)),
+        };
+        let pixels = rgb_pixels
+            .chunks(3)
+            .map(|rgb| Color::from_hex(u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]])))
+            .collect();
+        Ok(Self {
+            width: width as usize,
+            pixels,
+        })
+    }
+
+    pub fn from_hdr(bytes: &[u8]) -> Result<Self, HdrError> {
+        hdr::decode(bytes)
+    }
+
+    fn rgb_bytes(raster: &Raster<SRgb8>) -> (u32, Vec<u8>) {
+        (raster.width(), raster.as_u8_slice().to_vec())
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -21,13 +68,13 @@ impl Canvas {
         self.pixels.len() / self.width
     }
 
-    pub fn pixels(&self) -> Pixels {
+    pub fn pixels(&self) -> Pixels<'_> {
         Pixels {
             pixels: self.pixels.iter(),
         }
     }
 
-    pub fn pixels_mut(&mut self) -> PixelsMut {
+    pub fn pixels_mut(&mut self) -> PixelsMut<'_> {
         PixelsMut {
             pixels: self.pixels.iter_mut(),
         }
@@ -43,7 +90,7 @@ impl Canvas {
         self
     }
 
-    pub fn enumerate_pixels_mut(&mut self) -> EnumeratePixelsMut {
+    pub fn enumerate_pixels_mut(&mut self) -> EnumeratePixelsMut<'_> {
         let width = self.width();
         EnumeratePixelsMut::new(self.pixels.iter_mut(), width)
     }
@@ -52,6 +99,24 @@ impl Canvas {
         self.pixels[self.index(x, y)]
     }
 
+    // Bilinearly filtered lookup where (0, 0) is the top left corner of the
+    // image and (1, 1) is the bottom right. `u` wraps around horizontally and
+    // `v` is clamped to the top and bottom rows
+    pub fn color_at_uv(&self, u: f64, v: f64) -> Color {
+        let x = u.rem_euclid(1.0) * self.width() as f64 - 0.5;
+        let y =
+            (v.clamp(0.0, 1.0) * self.height() as f64 - 0.5).clamp(0.0, (self.height() - 1) as f64);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap_x = |x: f64| (x as i64).rem_euclid(self.width() as i64) as usize;
+        let (left, right) = (wrap_x(x0), wrap_x(x0 + 1.0));
+        let (top, bottom) = (y0 as usize, (y0 as usize + 1).min(self.height() - 1));
+        let upper = self.pixel_at(left, top) * (1.0 - fx) + self.pixel_at(right, top) * fx;
+        let lower = self.pixel_at(left, bottom) * (1.0 - fx) + self.pixel_at(right, bottom) * fx;
+        upper * (1.0 - fy) + lower * fy
+    }
+
     fn break_line(s: &str, max_len: usize) -> String {
         if s.chars().count() < max_len {
             s.to_string()
@@ -119,6 +184,15 @@ impl Canvas {
     }
 }
 
+impl fmt::Debug for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canvas")
+            .field("width", &self.width())
+            .field("height", &self.height())
+            .finish()
+    }
+}
+
 pub struct Pixels<'a> {
     pixels: std::slice::Iter<'a, Color>,
 }
@@ -266,6 +340,55 @@ mod canvas_tests {
         assert_eq!(expected, lines_4_to_7);
     }
 
+    #[test]
+    fn a_canvas_can_be_read_back_from_png_data() {
+        let c1 = Color::from_hex(0xFF0000);
+        let c2 = Color::from_hex(0x3366CC);
+        let c = Canvas::new(3, 2)
+            .write_pixel(0, 0, c1)
+            .write_pixel(2, 1, c2);
+        let decoded = Canvas::from_png(&c.to_png()).expect("Valid png data");
+        assert_eq!(3, decoded.width());
+        assert_eq!(2, decoded.height());
+        assert_eq!(c1, decoded.pixel_at(0, 0));
+        assert_eq!(c2, decoded.pixel_at(2, 1));
+    }
+
+    #[test]
+    fn invalid_png_data_is_an_error() {
+        assert!(Canvas::from_png(b"not a png").is_err());
+    }
+
+    #[test]
+    fn truncated_png_data_is_an_error() {
+        let png = Canvas::new(3, 2).to_png();
+        for length in 0..png.len() {
+            assert!(Canvas::from_png(&png[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn uv_lookup_at_a_pixel_center_returns_the_pixel() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let c = Canvas::new(4, 2).write_pixel(1, 1, red);
+        assert_eq!(red, c.color_at_uv(0.375, 0.75));
+    }
+
+    #[test]
+    fn uv_lookup_blends_neighbouring_pixels() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let c = Canvas::new(2, 1).write_pixel(0, 0, white);
+        assert_eq!(Color::new(0.5, 0.5, 0.5), c.color_at_uv(0.5, 0.5));
+    }
+
+    #[test]
+    fn uv_lookup_wraps_horizontally() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let c = Canvas::new(4, 1).write_pixel(0, 0, white);
+        assert_eq!(c.color_at_uv(0.125, 0.5), c.color_at_uv(1.125, 0.5));
+        assert_eq!(Color::new(0.5, 0.5, 0.5), c.color_at_uv(0.0, 0.5));
+    }
+
     #[test]
     fn ppm_are_terminated_by_a_newline_character() {
         let c = Canvas::new(5, 3);
@@ -0,0 +1,197 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// A flat disk in the xz plane centered on the origin, facing up. With an
+// inner radius it becomes an annulus, a ring with a hole in the middle
+#[derive(PartialEq, Clone, Debug)]
+pub struct Disk {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    radius: f64,
+    inner_radius: f64,
+}
+
+impl Disk {
+    pub fn new(radius: f64) -> Self {
+        Self {
+            radius,
+            ..Self::default()
+        }
+    }
+
+    pub fn annulus(inner_radius: f64, radius: f64) -> Self {
+        Self {
+            radius,
+            inner_radius,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn inner_radius(&self) -> f64 {
+        self.inner_radius
+    }
+}
+
+impl Default for Disk {
+    fn default() -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            radius: 1.0,
+            inner_radius: 0.0,
+        }
+    }
+}
+
+impl Shape for Disk {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        if local_ray.direction().y.abs() < EPSILON {
+            return Intersections::default();
+        }
+        let t = -local_ray.origin().y / local_ray.direction().y;
+        let point = local_ray.position(t);
+        let distance_squared = point.x * point.x + point.z * point.z;
+        let outside = distance_squared > self.radius * self.radius;
+        let in_hole = distance_squared < self.inner_radius * self.inner_radius;
+        if outside || in_hole {
+            Intersections::default()
+        } else {
+            Intersections::new(&[Intersection::new(t, self.clone())])
+        }
+    }
+
+    fn local_normal_at(&self, _point: Tup) -> Tup {
+        Tup::vector(0, 1, 0)
+    }
+
+    // u runs around the disk like longitude on a sphere and v runs out from
+    // the inner edge to the outer one
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        let u = 0.5 + point.x.atan2(-point.z) / (2.0 * PI);
+        let distance = (point.x * point.x + point.z * point.z).sqrt();
+        let v = (distance - self.inner_radius) / (self.radius - self.inner_radius);
+        (u, v)
+    }
+
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        let around = Tup::vector(-point.z, 0.0, point.x);
+        if around.magnitude() < EPSILON {
+            Tup::vector(1, 0, 0)
+        } else {
+            around.normalize()
+        }
+    }
+}
+
+#[cfg(test)]
+mod disks_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+
+    #[test]
+    fn a_disk_has_a_unit_radius_by_default() {
+        let d = Disk::default();
+        assert_eq!(1.0, d.radius());
+        assert_eq!(0.0, d.inner_radius());
+    }
+
+    #[test]
+    fn a_ray_can_hit_a_disk() {
+        let d = Disk::new(2.0);
+        let r = Ray::new(Tup::point(1.5, 1.0, 0.0), Tup::vector(0, -1, 0));
+        let xs = d.local_intersect(r);
+        assert_eq!(1, xs.len());
+        assert_eq!(1.0, xs[0].t());
+    }
+
+    #[test]
+    fn a_ray_can_miss_a_disk() {
+        let d = Disk::new(2.0);
+        let r = Ray::new(Tup::point(1.5, 1.0, 1.5), Tup::vector(0, -1, 0));
+        assert!(d.local_intersect(r).is_empty());
+    }
+
+    #[test]
+    fn a_ray_parallel_to_a_disk_misses_it() {
+        let d = Disk::default();
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert!(d.local_intersect(r).is_empty());
+    }
+
+    #[test]
+    fn a_ray_passes_through_the_hole_in_an_annulus() {
+        let d = Disk::annulus(0.5, 1.0);
+        let through_hole = Ray::new(Tup::point(0.25, 1.0, 0.0), Tup::vector(0, -1, 0));
+        assert!(d.local_intersect(through_hole).is_empty());
+        let on_ring = Ray::new(Tup::point(0.0, 1.0, -0.75), Tup::vector(0, -1, 0));
+        assert_eq!(1, d.local_intersect(on_ring).len());
+    }
+
+    #[test]
+    fn the_normal_of_a_disk_faces_up() {
+        let d = Disk::default().with_transform(transforms::rotation_x(std::f64::consts::FRAC_PI_2));
+        assert_eq!(Tup::vector(0, 0, 1), d.normal_at(Tup::point(0.5, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn texture_coordinates_run_around_and_across_an_annulus() {
+        let d = Disk::annulus(1.0, 3.0);
+        let (u, v) = d.local_uv_at(Tup::point(0, 0, -1));
+        assert_nearly_eq(0.5, u);
+        assert_nearly_eq(0.0, v);
+        let (u, v) = d.local_uv_at(Tup::point(2, 0, 0));
+        assert_nearly_eq(0.75, u);
+        assert_nearly_eq(0.5, v);
+    }
+}
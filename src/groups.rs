@@ -0,0 +1,354 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// Shapes gathered together so they can be placed, and given a material, as
+// one. Children that weren't given a material of their own take on the
+// group's, while those that were keep theirs. The group's transform is
+// applied on top of each child's own
+#[derive(Clone)]
+pub struct Group {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    // Shared with the copies of the group carried by intersections
+    children: Arc<Vec<Box<dyn Shape>>>,
+    // The child a ray hit, which is what the group's copies in
+    // intersections are shaded as
+    hit: Option<Box<dyn Shape>>,
+}
+
+impl Group {
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn with_child<T: 'static + Shape>(self, child: T) -> Self {
+        self.with_children([Box::new(child) as Box<dyn Shape>])
+    }
+
+    // Such as the triangles of a mesh
+    pub fn with_children<I: IntoIterator<Item = Box<dyn Shape>>>(mut self, children: I) -> Self {
+        Arc::make_mut(&mut self.children).extend(children);
+        self
+    }
+
+    pub fn children(&self) -> &[Box<dyn Shape>] {
+        &self.children
+    }
+
+    // A group has no surface of its own. The copies of it carried by
+    // intersections know which child's surface to use, others have to find
+    // the child the point is on. Empty groups have none
+    fn surface(&self, point: Tup) -> Option<&dyn Shape> {
+        self.hit.as_deref().or_else(|| self.locate(point))
+    }
+
+    // How far either side of a point the rays looking for its child reach
+    const REACH: f64 = 1e-3;
+
+    // The child with a surface nearest the point, found by casting short
+    // rays through the point along each axis
+    fn locate(&self, point: Tup) -> Option<&dyn Shape> {
+        let axes = [
+            Tup::vector(1, 0, 0),
+            Tup::vector(0, 1, 0),
+            Tup::vector(0, 0, 1),
+        ];
+        let distance_to = |child: &dyn Shape| {
+            axes.iter()
+                .flat_map(|&axis| {
+                    let xs = child.intersect(&Ray::new(point - axis * Self::REACH, axis));
+                    (0..xs.len())
+                        .map(|i| (xs[i].t() - Self::REACH).abs())
+                        .collect::<Vec<_>>()
+                })
+                .min_by(|a, b| a.total_cmp(b))
+        };
+        self.children
+            .iter()
+            .filter_map(|child| distance_to(child.as_ref()).map(|d| (d, child.as_ref())))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, child)| child)
+    }
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            children: Arc::default(),
+            hit: None,
+        }
+    }
+}
+
+// The child that was hit is shown rather than all of them, so that
+// different children aren't mistaken for one another
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Group")
+            .field("id", &self.id)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .field("children", &self.children.len())
+            .field("hit", &self.hit)
+            .finish()
+    }
+}
+
+impl PartialEq for Group {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Shape for Group {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.own_material().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    // A child's own material wins over the group's
+    fn own_material(&self) -> Option<Material> {
+        self.hit
+            .as_ref()
+            .and_then(|child| child.own_material())
+            .or_else(|| self.material.clone())
+    }
+
+    fn world_to_object(&self) -> Mat4 {
+        match &self.hit {
+            Some(child) => child.world_to_object() * self.transform.inverse(),
+            None => self.transform.inverse(),
+        }
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let mut xs = Vec::new();
+        for child in self.children.iter() {
+            let child_xs = child.intersect(&local_ray);
+            for i in 0..child_xs.len() {
+                let x = &child_xs[i];
+                let group = Self {
+                    hit: Some(x.object().clone()),
+                    ..self.clone()
+                };
+                let intersection = Intersection::new(x.t(), group);
+                xs.push(match x.face() {
+                    Some(face) => intersection.with_face(face),
+                    None => intersection,
+                });
+            }
+        }
+        xs.sort_by(|a, b| a.t().total_cmp(&b.t()));
+        Intersections::new(&xs)
+    }
+
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        self.surface(point)
+            .map_or(Tup::vector(0, 1, 0), |child| child.normal_at(point))
+    }
+
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        self.surface(point)
+            .map_or((0.0, 0.0), |child| child.uv_at(point))
+    }
+
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        self.surface(point)
+            .map_or(Tup::vector(1, 0, 0), |child| child.tangent_at(point))
+    }
+}
+
+#[cfg(test)]
+mod groups_test {
+    use super::*;
+    use crate::color::Color;
+    use crate::instances::Instance;
+    use crate::spheres::Sphere;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+    use std::f64::consts;
+    use std::sync::Arc;
+
+    fn red() -> Material {
+        Material::default().with_color(Color::new(1, 0, 0))
+    }
+
+    fn blue() -> Material {
+        Material::default().with_color(Color::new(0, 0, 1))
+    }
+
+    #[test]
+    fn a_ray_misses_an_empty_group() {
+        let group = Group::default();
+        assert!(group.children().is_empty());
+        let ray = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, 0, 1));
+        assert!(group.intersect(&ray).is_empty());
+    }
+
+    #[test]
+    fn the_hits_on_all_children_are_in_order() {
+        let s1 = Sphere::default();
+        let s2 = Sphere::default().with_transform(transforms::translation(0, 0, -3));
+        let s3 = Sphere::default().with_transform(transforms::translation(5, 0, 0));
+        let group = Group::default()
+            .with_child(s1)
+            .with_child(s2)
+            .with_child(s3);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = group.intersect(&ray);
+        assert_eq!(4, xs.len());
+        for (i, t) in [1.0, 3.0, 4.0, 6.0].into_iter().enumerate() {
+            assert_nearly_eq(t, xs[i].t());
+        }
+    }
+
+    #[test]
+    fn the_group_transform_applies_on_top_of_the_children_s_own() {
+        let sphere = Sphere::default().with_transform(transforms::translation(5, 0, 0));
+        let group = Group::default()
+            .with_transform(transforms::scaling(2, 2, 2))
+            .with_child(sphere);
+        let ray = Ray::new(Tup::point(10, 0, -10), Tup::vector(0, 0, 1));
+        assert_eq!(2, group.intersect(&ray).len());
+    }
+
+    #[test]
+    fn normals_on_a_child_pass_through_the_group_transform() {
+        let sphere = Sphere::default().with_transform(transforms::translation(5, 0, 0));
+        let group = Group::default()
+            .with_transform(transforms::rotation_y(consts::FRAC_PI_2))
+            .with_child(sphere);
+        let ray = Ray::new(Tup::point(0, 0, 10), Tup::vector(0, 0, -1));
+        let xs = group.intersect(&ray);
+        let n = xs[0].object().normal_at(ray.position(xs[0].t()));
+        assert_eq!(Tup::vector(0, 0, 1), n);
+    }
+
+    #[test]
+    fn a_group_finds_the_child_a_point_is_on() {
+        let sphere = Sphere::default().with_transform(transforms::translation(5, 0, 0));
+        let group = Group::default()
+            .with_transform(transforms::rotation_y(consts::FRAC_PI_2))
+            .with_child(Sphere::default())
+            .with_child(sphere);
+        assert_eq!(Tup::vector(0, 0, -1), group.normal_at(Tup::point(0, 0, -6)));
+        assert_eq!(Tup::vector(-1, 0, 0), group.normal_at(Tup::point(-1, 0, 0)));
+        let empty = Group::default();
+        assert_eq!(Tup::vector(0, 1, 0), empty.normal_at(Tup::point(0, 0, 0)));
+    }
+
+    #[test]
+    fn children_without_a_material_take_on_the_group_s() {
+        let plain = Sphere::default();
+        let painted = Sphere::default()
+            .with_material(blue())
+            .with_transform(transforms::translation(0, 0, 5));
+        let group = Group::default()
+            .with_material(red())
+            .with_child(plain.clone())
+            .with_child(painted);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = group.intersect(&ray);
+        assert_eq!(red(), xs[0].object().material());
+        assert_eq!(blue(), xs[2].object().material());
+        assert_eq!(Material::default(), plain.material());
+        assert_eq!(None, plain.own_material());
+    }
+
+    #[test]
+    fn groups_inside_groups_inherit_the_nearest_material() {
+        let inner = Group::default().with_child(Sphere::default());
+        let outer = Group::default().with_material(red()).with_child(inner);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert_eq!(red(), outer.intersect(&ray)[0].object().material());
+        let inner = Group::default()
+            .with_material(blue())
+            .with_child(Sphere::default());
+        let outer = Group::default().with_material(red()).with_child(inner);
+        assert_eq!(blue(), outer.intersect(&ray)[0].object().material());
+    }
+
+    #[test]
+    fn a_group_without_a_material_leaves_its_children_alone() {
+        let group = Group::default().with_child(Sphere::default().with_material(blue()));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert_eq!(blue(), group.intersect(&ray)[0].object().material());
+        assert_eq!(None, group.own_material());
+    }
+
+    #[test]
+    fn instances_of_a_group_keep_the_materials_of_its_children() {
+        let group = Group::default()
+            .with_material(red())
+            .with_child(Sphere::default())
+            .with_child(
+                Sphere::default()
+                    .with_material(blue())
+                    .with_transform(transforms::translation(0, 0, 5)),
+            );
+        let geometry = Arc::new(group);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = Instance::new(geometry.clone()).intersect(&ray);
+        assert_eq!(red(), xs[0].object().material());
+        assert_eq!(blue(), xs[2].object().material());
+        let xs = Instance::new(geometry)
+            .with_material(Material::default())
+            .intersect(&ray);
+        assert_eq!(Material::default(), xs[2].object().material());
+    }
+
+    #[test]
+    fn glass_children_of_one_group_are_told_apart() {
+        let glass = |refractive_index| {
+            Material::default()
+                .with_transparency(1.0)
+                .with_refractive_index(refractive_index)
+        };
+        let outer = Sphere::default()
+            .with_material(glass(1.5))
+            .with_transform(transforms::scaling(2, 2, 2));
+        let inner = Sphere::default().with_material(glass(2.5));
+        let group = Group::default().with_child(outer).with_child(inner);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = group.intersect(&ray);
+        let entering = xs[1].prepare_computations(&ray, &xs);
+        assert_eq!((1.5, 2.5), (entering.n1(), entering.n2()));
+        let leaving = xs[2].prepare_computations(&ray, &xs);
+        assert_eq!((2.5, 1.5), (leaving.n1(), leaving.n2()));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use std::error::Error;
+use std::fmt;
+
+// Reader for Radiance RGBE (`.hdr`) images. Each pixel stores a shared
+// exponent alongside three mantissas so colors are not clamped to [0, 1],
+// which is what makes these files useful as light sources
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HdrError {
+    MissingSignature,
+    UnsupportedFormat(String),
+    MissingResolution,
+    InvalidResolution(String),
+    UnexpectedEndOfData,
+    InvalidRunLength,
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdrError::MissingSignature => write!(f, "missing `#?RADIANCE` signature"),
+            HdrError::UnsupportedFormat(format) => write!(f, "unsupported format `{format}`"),
+            HdrError::MissingResolution => write!(f, "missing resolution line"),
+            HdrError::InvalidResolution(line) => write!(f, "invalid resolution line `{line}`"),
+            HdrError::UnexpectedEndOfData => write!(f, "unexpected end of pixel data"),
+            HdrError::InvalidRunLength => write!(f, "run length overflows scanline"),
+        }
+    }
+}
+
+impl Error for HdrError {}
+
+const SUPPORTED_FORMAT: &str = "32-bit_rle_rgbe";
+
+pub fn decode(bytes: &[u8]) -> Result<Canvas, HdrError> {
+    let mut reader = Reader::new(bytes);
+    read_header(&mut reader)?;
+    let (width, height) = read_resolution(&mut reader)?;
+    // The header alone isn't trusted to size the image. There must be
+    // enough data left for it, however tightly it's encoded
+    let fits = width.checked_mul(height).is_some()
+        && min_scanline_len(width)
+            .and_then(|len| len.checked_mul(height))
+            .is_some_and(|len| len <= reader.remaining());
+    if !fits {
+        return Err(HdrError::UnexpectedEndOfData);
+    }
+    let mut canvas = Canvas::new(width, height);
+    let mut scanline = vec![[0_u8; 4]; width];
+    for y in 0..height {
+        read_scanline(&mut reader, &mut scanline)?;
+        for (x, rgbe) in scanline.iter().enumerate() {
+            canvas = canvas.write_pixel(x, y, rgbe_to_color(*rgbe));
+        }
+    }
+    Ok(canvas)
+}
+
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        Color::new(0, 0, 0)
+    } else {
+        let scale = 2.0_f64.powi(e as i32 - (128 + 8));
+        Color::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+    }
+}
+
+fn read_header(reader: &mut Reader) -> Result<(), HdrError> {
+    let signature = reader.line().ok_or(HdrError::MissingSignature)?;
+    if !signature.starts_with("#?") {
+        return Err(HdrError::MissingSignature);
+    }
+    loop {
+        let line = reader.line().ok_or(HdrError::MissingResolution)?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != SUPPORTED_FORMAT {
+                return Err(HdrError::UnsupportedFormat(format.to_string()));
+            }
+        }
+    }
+}
+
+// Only the standard orientation, rows top to bottom and columns left to
+// right, is supported
+fn read_resolution(reader: &mut Reader) -> Result<(usize, usize), HdrError> {
+    let line = reader.line().ok_or(HdrError::MissingResolution)?;
+    let invalid = || HdrError::InvalidResolution(line.clone());
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields.as_slice() {
+        ["-Y", height, "+X", width] => {
+            let height = height.parse().map_err(|_| invalid())?;
+            let width = width.parse().map_err(|_| invalid())?;
+            if width == 0 || height == 0 {
+                Err(invalid())
+            } else {
+                Ok((width, height))
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// The fewest bytes a scanline can take: a header and runs of 127 pixels in
+// each channel if it's run length encoded, or else every pixel in full
+fn min_scanline_len(width: usize) -> Option<usize> {
+    if is_run_length_width(width) {
+        Some(4 + 4 * 2 * width.div_ceil(127))
+    } else {
+        width.checked_mul(4)
+    }
+}
+
+fn is_run_length_width(width: usize) -> bool {
+    (8..0x8000).contains(&width)
+}
+
+fn read_scanline(reader: &mut Reader, scanline: &mut [[u8; 4]]) -> Result<(), HdrError> {
+    let width = scanline.len();
+    let first = reader.peek(4).ok_or(HdrError::UnexpectedEndOfData)?;
+    let is_run_length_encoded = is_run_length_width(width)
+        && first[0] == 2
+        && first[1] == 2
+        && first[2] & 0x80 == 0
+        && ((first[2] as usize) << 8 | first[3] as usize) == width;
+    if !is_run_length_encoded {
+        for pixel in scanline.iter_mut() {
+            let rgbe = reader.take(4).ok_or(HdrError::UnexpectedEndOfData)?;
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(());
+    }
+    reader.take(4);
+    // Each channel is stored separately as a mix of runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = reader.byte().ok_or(HdrError::UnexpectedEndOfData)? as usize;
+            if count > 128 {
+                let run = count - 128;
+                let value = reader.byte().ok_or(HdrError::UnexpectedEndOfData)?;
+                if x + run > width {
+                    return Err(HdrError::InvalidRunLength);
+                }
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(HdrError::InvalidRunLength);
+                }
+                let values = reader.take(count).ok_or(HdrError::UnexpectedEndOfData)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = *value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn line(&mut self) -> Option<String> {
+        let rest = &self.bytes[self.position..];
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        self.position += end + 1;
+        Some(String::from_utf8_lossy(&rest[..end]).trim_end().to_string())
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn peek(&self, count: usize) -> Option<&'a [u8]> {
+        self.bytes.get(self.position..self.position + count)
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let taken = self.peek(count)?;
+        self.position += count;
+        Some(taken)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+}
+
+#[cfg(test)]
+mod hdr_test {
+    use super::*;
+
+    fn header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y {height} +X {width}\n")
+            .into_bytes()
+    }
+
+    #[test]
+    fn an_rgbe_pixel_with_zero_exponent_is_black() {
+        assert_eq!(Color::new(0, 0, 0), rgbe_to_color([200, 100, 50, 0]));
+    }
+
+    #[test]
+    fn an_rgbe_pixel_shares_its_exponent_between_channels() {
+        assert_eq!(
+            Color::new(0.5, 0.25, 1.0),
+            rgbe_to_color([64, 32, 128, 129])
+        );
+        assert_eq!(Color::new(8.0, 2.0, 0.0), rgbe_to_color([128, 32, 0, 132]));
+    }
+
+    #[test]
+    fn flat_scanlines_can_be_decoded() {
+        let mut bytes = header(2, 2);
+        bytes.extend_from_slice(&[128, 0, 0, 129, 0, 128, 0, 129]);
+        bytes.extend_from_slice(&[0, 0, 128, 129, 128, 128, 128, 131]);
+        let image = decode(&bytes).expect("Valid hdr data");
+        assert_eq!(2, image.width());
+        assert_eq!(2, image.height());
+        assert_eq!(Color::new(1, 0, 0), image.pixel_at(0, 0));
+        assert_eq!(Color::new(0, 1, 0), image.pixel_at(1, 0));
+        assert_eq!(Color::new(0, 0, 1), image.pixel_at(0, 1));
+        assert_eq!(Color::new(4, 4, 4), image.pixel_at(1, 1));
+    }
+
+    #[test]
+    fn run_length_encoded_scanlines_can_be_decoded() {
+        let width = 10;
+        let mut bytes = header(width, 1);
+        bytes.extend_from_slice(&[2, 2, 0, width as u8]);
+        // Red: a run of 10
+        bytes.extend_from_slice(&[128 + 10, 128]);
+        // Green: 3 literals followed by a run of 7
+        bytes.extend_from_slice(&[3, 0, 64, 128, 128 + 7, 0]);
+        // Blue: a run of 10
+        bytes.extend_from_slice(&[128 + 10, 0]);
+        // Exponent: a run of 10
+        bytes.extend_from_slice(&[128 + 10, 129]);
+        let image = decode(&bytes).expect("Valid hdr data");
+        assert_eq!(Color::new(1, 0, 0), image.pixel_at(0, 0));
+        assert_eq!(Color::new(1.0, 0.5, 0.0), image.pixel_at(1, 0));
+        assert_eq!(Color::new(1, 1, 0), image.pixel_at(2, 0));
+        assert_eq!(Color::new(1, 0, 0), image.pixel_at(9, 0));
+    }
+
+    #[test]
+    fn data_without_a_signature_is_an_error() {
+        let result = decode(b"P3\n1 1\n255\n");
+        assert_eq!(Some(HdrError::MissingSignature), result.err());
+    }
+
+    #[test]
+    fn unsupported_pixel_formats_are_an_error() {
+        let result = decode(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n");
+        assert_eq!(
+            Some(HdrError::UnsupportedFormat("32-bit_rle_xyze".to_string())),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn unsupported_orientations_are_an_error() {
+        let result = decode(b"#?RADIANCE\n\n+Y 1 +X 1\n");
+        assert_eq!(
+            Some(HdrError::InvalidResolution("+Y 1 +X 1".to_string())),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn truncated_pixel_data_is_an_error() {
+        let mut bytes = header(2, 1);
+        bytes.extend_from_slice(&[128, 0, 0, 129, 0, 128]);
+        assert_eq!(Some(HdrError::UnexpectedEndOfData), decode(&bytes).err());
+    }
+
+    #[test]
+    fn runs_past_the_end_of_a_scanline_are_an_error() {
+        let mut bytes = header(8, 1);
+        bytes.extend_from_slice(&[2, 2, 0, 8, 128 + 9, 0]);
+        // Padding so there's as much data as the shortest scanline needs
+        bytes.extend_from_slice(&[0; 6]);
+        assert_eq!(Some(HdrError::InvalidRunLength), decode(&bytes).err());
+    }
+
+    #[test]
+    fn a_resolution_larger_than_the_data_is_an_error() {
+        let mut bytes = header(100_000, 100_000);
+        bytes.extend_from_slice(&[2, 2, 0, 8, 128 + 8, 0]);
+        assert_eq!(Some(HdrError::UnexpectedEndOfData), decode(&bytes).err());
+        let bytes = header(usize::MAX, 2);
+        assert_eq!(Some(HdrError::UnexpectedEndOfData), decode(&bytes).err());
+    }
+}
@@ -0,0 +1,602 @@
+use crate::canvas::Canvas;
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::matrix::Mat4;
+use crate::noise;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+pub enum HeightMapError {
+    TooSmall { columns: usize, rows: usize },
+    SizeMismatch { expected: usize, actual: usize },
+    Png(png_pong::decode::Error),
+}
+
+impl fmt::Display for HeightMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeightMapError::TooSmall { columns, rows } => write!(
+                f,
+                "height map needs at least 2 by 2 heights, got {columns} by {rows}"
+            ),
+            HeightMapError::SizeMismatch { expected, actual } => write!(
+                f,
+                "height data has the wrong size, expected {expected} but got {actual}"
+            ),
+            HeightMapError::Png(e) => write!(f, "height map image is invalid: {e}"),
+        }
+    }
+}
+
+impl Error for HeightMapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HeightMapError::Png(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Heights sampled on a regular grid spread over the square from -1 to 1 in
+// x and z. Columns run along x and rows run from positive z to negative z,
+// so an image laid on the ground has its top row furthest away, like a
+// texture on a plane. Normals at each sample are worked out up front from
+// the slopes to their neighbours
+pub struct HeightMap {
+    columns: usize,
+    rows: usize,
+    heights: Vec<f64>,
+    normals: Vec<Tup>,
+    lowest: f64,
+    highest: f64,
+}
+
+impl HeightMap {
+    // Heights ordered a row at a time, the row at positive z first
+    pub fn new(columns: usize, rows: usize, heights: Vec<f64>) -> Result<Self, HeightMapError> {
+        if columns < 2 || rows < 2 {
+            return Err(HeightMapError::TooSmall { columns, rows });
+        }
+        if heights.len() != columns * rows {
+            return Err(HeightMapError::SizeMismatch {
+                expected: columns * rows,
+                actual: heights.len(),
+            });
+        }
+        let lowest = heights.iter().fold(f64::INFINITY, |m, h| m.min(*h));
+        let highest = heights.iter().fold(f64::NEG_INFINITY, |m, h| m.max(*h));
+        let mut map = Self {
+            columns,
+            rows,
+            heights,
+            normals: Vec::new(),
+            lowest,
+            highest,
+        };
+        map.normals = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| map.slope_normal(column, row))
+            .collect();
+        Ok(map)
+    }
+
+    // The brightness of each pixel, from zero for black to one for white
+    pub fn from_canvas(canvas: &Canvas) -> Result<Self, HeightMapError> {
+        let heights = canvas
+            .pixels()
+            .map(|c| (c.red() + c.green() + c.blue()) / 3.0)
+            .collect();
+        Self::new(canvas.width(), canvas.height(), heights)
+    }
+
+    // A grayscale image, usually exported from a terrain editor or a map of
+    // real elevations
+    pub fn from_png(bytes: &[u8]) -> Result<Self, HeightMapError> {
+        let canvas = Canvas::from_png(bytes).map_err(HeightMapError::Png)?;
+        Self::from_canvas(&canvas)
+    }
+
+    // Rolling hills of fractal noise between zero and one. `frequency` sets
+    // how many hills fit across the map and `octaves` how rough they are
+    pub fn noise(columns: usize, rows: usize, frequency: f64, octaves: usize) -> Self {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        let heights = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let x = column as f64 / (columns - 1) as f64;
+                let z = row as f64 / (rows - 1) as f64;
+                let point = Tup::point(x * frequency, 0.5, z * frequency);
+                (0.5 + 0.5 * noise::fbm(point, octaves)).clamp(0.0, 1.0)
+            })
+            .collect();
+        Self::new(columns, rows, heights).expect("Noise fills the whole grid")
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn height(&self, column: usize, row: usize) -> f64 {
+        self.heights[row * self.columns + column]
+    }
+
+    fn normal(&self, column: usize, row: usize) -> Tup {
+        self.normals[row * self.columns + column]
+    }
+
+    fn vertex(&self, column: usize, row: usize) -> Tup {
+        Tup::point(
+            -1.0 + 2.0 * column as f64 / (self.columns - 1) as f64,
+            self.height(column, row),
+            1.0 - 2.0 * row as f64 / (self.rows - 1) as f64,
+        )
+    }
+
+    // From the difference between the neighbours on either side, or the
+    // sample itself and its one neighbour at the edges
+    fn slope_normal(&self, column: usize, row: usize) -> Tup {
+        let (left, right) = (column.saturating_sub(1), (column + 1).min(self.columns - 1));
+        let (far, near) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+        let dx = self.vertex(right, row) - self.vertex(left, row);
+        let dz = self.vertex(column, near) - self.vertex(column, far);
+        Tup::vector(-dx.y / dx.x, 1.0, -dz.y / dz.z).normalize()
+    }
+
+    // Continuous grid coordinates of a point, with samples at whole numbers
+    fn grid_coordinates(&self, x: f64, z: f64) -> (f64, f64) {
+        (
+            (x + 1.0) / 2.0 * (self.columns - 1) as f64,
+            (1.0 - z) / 2.0 * (self.rows - 1) as f64,
+        )
+    }
+
+    // The cell holding grid coordinates, kept inside the map
+    fn cell(&self, gx: f64, gz: f64) -> (usize, usize) {
+        let clamp = |g: f64, cells: usize| (g.floor().max(0.0) as usize).min(cells - 1);
+        (clamp(gx, self.columns - 1), clamp(gz, self.rows - 1))
+    }
+}
+
+// Maps can hold millions of heights, far too many to print or compare. Two
+// maps are equal only if they are the same map
+impl fmt::Debug for HeightMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HeightMap {{ columns: {}, rows: {}, at: {:p} }}",
+            self.columns, self.rows, self
+        )
+    }
+}
+
+impl PartialEq for HeightMap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// Terrain from a height map, covering the square from -1 to 1 in x and z
+// with the heights as y. Each cell of the grid is split into two triangles,
+// and the normals of the samples are blended across them so the terrain
+// looks smooth. Scale it to the size of the landscape
+#[derive(PartialEq, Clone, Debug)]
+pub struct Heightfield {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    map: Arc<HeightMap>,
+}
+
+impl Heightfield {
+    // Copies of the heightfield, such as those held by intersections, all
+    // share the one map
+    pub fn new(map: HeightMap) -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            map: Arc::new(map),
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn map(&self) -> &HeightMap {
+        &self.map
+    }
+
+    // The span of t in which `ray` is inside the box around the terrain
+    fn bounding_span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let slab = |origin: f64, direction: f64, min: f64, max: f64| {
+            if direction.abs() < EPSILON {
+                if origin < min || origin > max {
+                    (f64::INFINITY, f64::NEG_INFINITY)
+                } else {
+                    (f64::NEG_INFINITY, f64::INFINITY)
+                }
+            } else {
+                let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+                (t0.min(t1), t0.max(t1))
+            }
+        };
+        let (lowest, highest) = (self.map.lowest - EPSILON, self.map.highest + EPSILON);
+        let spans = [
+            slab(o.x, d.x, -1.0, 1.0),
+            slab(o.y, d.y, lowest, highest),
+            slab(o.z, d.z, -1.0, 1.0),
+        ];
+        let near = spans.iter().fold(f64::NEG_INFINITY, |m, s| m.max(s.0));
+        let far = spans.iter().fold(f64::INFINITY, |m, s| m.min(s.1));
+        if near > far {
+            None
+        } else {
+            Some((near, far))
+        }
+    }
+
+    // Both triangles of a cell, unless the ray passes above or below all of
+    // its corners while it is over the cell
+    fn intersect_cell(
+        &self,
+        ray: &Ray,
+        (column, row): (usize, usize),
+        (enter, exit): (f64, f64),
+        ts: &mut Vec<f64>,
+    ) {
+        let corners = [
+            self.map.vertex(column, row),
+            self.map.vertex(column + 1, row),
+            self.map.vertex(column + 1, row + 1),
+            self.map.vertex(column, row + 1),
+        ];
+        let lowest = corners.iter().fold(f64::INFINITY, |m, c| m.min(c.y));
+        let highest = corners.iter().fold(f64::NEG_INFINITY, |m, c| m.max(c.y));
+        let (y0, y1) = (ray.position(enter).y, ray.position(exit).y);
+        if y0.min(y1) > highest + EPSILON || y0.max(y1) < lowest - EPSILON {
+            return;
+        }
+        for [p1, p2, p3] in [
+            [corners[0], corners[1], corners[2]],
+            [corners[0], corners[2], corners[3]],
+        ] {
+            if let Some(t) = triangle_hit(ray, p1, p2, p3) {
+                ts.push(t);
+            }
+        }
+    }
+}
+
+// Möller and Trumbore's ray and triangle test
+fn triangle_hit(ray: &Ray, p1: Tup, p2: Tup, p3: Tup) -> Option<f64> {
+    let e1 = p2 - p1;
+    let e2 = p3 - p1;
+    let dir_cross_e2 = ray.direction().cross(&e2);
+    let det = e1.dot(&dir_cross_e2);
+    if det.abs() < EPSILON * EPSILON {
+        return None;
+    }
+    let f = 1.0 / det;
+    let p1_to_origin = ray.origin() - p1;
+    let u = f * p1_to_origin.dot(&dir_cross_e2);
+    if !(-EPSILON..=1.0 + EPSILON).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(&e1);
+    let v = f * ray.direction().dot(&origin_cross_e1);
+    if v < -EPSILON || u + v > 1.0 + EPSILON {
+        return None;
+    }
+    Some(f * e2.dot(&origin_cross_e1))
+}
+
+impl Shape for Heightfield {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    // Walks the cells under the ray in order, Amanatides and Woo style, so
+    // only the triangles the ray actually passes over are tested
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let (near, far) = match self.bounding_span(&local_ray) {
+            Some(span) => span,
+            None => return Intersections::default(),
+        };
+        let map = &self.map;
+        let start = local_ray.position(near);
+        let (gx, gz) = map.grid_coordinates(start.x, start.z);
+        let (mut column, mut row) = map.cell(gx, gz);
+        // The ray's speed across the grid, in cells per unit of t
+        let d = local_ray.direction();
+        let speed_x = d.x * (map.columns - 1) as f64 / 2.0;
+        let speed_z = -d.z * (map.rows - 1) as f64 / 2.0;
+        let crossing = |g: f64, cell: usize, speed: f64| {
+            if speed > 0.0 {
+                (near + (cell as f64 + 1.0 - g) / speed, 1.0 / speed)
+            } else if speed < 0.0 {
+                (near + (cell as f64 - g) / speed, -1.0 / speed)
+            } else {
+                (f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (mut next_x, step_x) = crossing(gx, column, speed_x);
+        let (mut next_z, step_z) = crossing(gz, row, speed_z);
+        let mut enter = near;
+        let mut ts = Vec::new();
+        loop {
+            let exit = next_x.min(next_z).min(far);
+            self.intersect_cell(&local_ray, (column, row), (enter, exit), &mut ts);
+            if exit >= far {
+                break;
+            }
+            if next_x < next_z {
+                if speed_x > 0.0 && column + 1 < map.columns - 1 {
+                    column += 1;
+                } else if speed_x < 0.0 && column > 0 {
+                    column -= 1;
+                } else {
+                    break;
+                }
+                next_x += step_x;
+            } else {
+                if speed_z > 0.0 && row + 1 < map.rows - 1 {
+                    row += 1;
+                } else if speed_z < 0.0 && row > 0 {
+                    row -= 1;
+                } else {
+                    break;
+                }
+                next_z += step_z;
+            }
+            enter = exit;
+        }
+        // Hits on the edges shared by two triangles are found by both
+        ts.sort_by(|a, b| a.total_cmp(b));
+        ts.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+        let xs: Vec<Intersection> = ts
+            .into_iter()
+            .map(|t| Intersection::new(t, self.clone()))
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    // The normals of the triangle's corners, weighted by how close the
+    // point is to each of them
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let map = &self.map;
+        let (gx, gz) = map.grid_coordinates(point.x, point.z);
+        let (column, row) = map.cell(gx, gz);
+        let fx = (gx - column as f64).clamp(0.0, 1.0);
+        let fz = (gz - row as f64).clamp(0.0, 1.0);
+        let n00 = map.normal(column, row);
+        let n11 = map.normal(column + 1, row + 1);
+        let normal = if fx >= fz {
+            n00 * (1.0 - fx) + map.normal(column + 1, row) * (fx - fz) + n11 * fz
+        } else {
+            n00 * (1.0 - fz) + map.normal(column, row + 1) * (fz - fx) + n11 * fx
+        };
+        normal.normalize()
+    }
+
+    // The height map's image lies exactly over the terrain
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        ((point.x + 1.0) / 2.0, (1.0 - point.z) / 2.0)
+    }
+
+    fn local_tangent_at(&self, _point: Tup) -> Tup {
+        Tup::vector(1, 0, 0)
+    }
+}
+
+#[cfg(test)]
+mod heightfields_test {
+    use super::*;
+    use crate::color::Color;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+
+    fn map(columns: usize, rows: usize, heights: &[f64]) -> HeightMap {
+        HeightMap::new(columns, rows, heights.to_vec()).expect("Valid heights")
+    }
+
+    #[test]
+    fn a_height_map_needs_at_least_two_by_two_heights() {
+        assert!(matches!(
+            HeightMap::new(1, 3, vec![0.0; 3]),
+            Err(HeightMapError::TooSmall {
+                columns: 1,
+                rows: 3
+            })
+        ));
+        assert!(matches!(
+            HeightMap::new(2, 2, vec![0.0; 3]),
+            Err(HeightMapError::SizeMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn heights_are_read_from_the_brightness_of_an_image() {
+        let c = Canvas::new(2, 2)
+            .write_pixel(1, 0, Color::new(1, 1, 1))
+            .write_pixel(0, 1, Color::new(0.5, 0.5, 0.5));
+        let m = HeightMap::from_png(&c.to_png()).expect("Valid png data");
+        assert_eq!((2, 2), (m.columns(), m.rows()));
+        assert_eq!(0.0, m.height(0, 0));
+        assert_eq!(1.0, m.height(1, 0));
+        assert!((m.height(0, 1) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn invalid_image_data_is_an_error() {
+        assert!(matches!(
+            HeightMap::from_png(b"not a png"),
+            Err(HeightMapError::Png(_))
+        ));
+    }
+
+    #[test]
+    fn noise_makes_uneven_ground() {
+        let m = HeightMap::noise(16, 16, 3.0, 4);
+        let heights: Vec<f64> = (0..16)
+            .flat_map(|row| (0..16).map(move |column| (column, row)))
+            .map(|(column, row)| m.height(column, row))
+            .collect();
+        assert!(heights.iter().all(|h| (0.0..=1.0).contains(h)));
+        assert!(heights.iter().any(|h| (h - heights[0]).abs() > 0.05));
+    }
+
+    #[test]
+    fn a_ray_falling_on_flat_ground() {
+        let h = Heightfield::new(map(2, 2, &[0.5; 4]));
+        let ray = Ray::new(Tup::point(0.3, 2.0, -0.4), Tup::vector(0, -1, 0));
+        let xs = h.intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_nearly_eq(1.5, xs[0].t());
+        assert_eq!(
+            Tup::vector(0, 1, 0),
+            h.normal_at(Tup::point(0.3, 0.5, -0.4))
+        );
+    }
+
+    #[test]
+    fn a_ray_beyond_the_edge_misses() {
+        let h = Heightfield::new(map(2, 2, &[0.5; 4]));
+        let ray = Ray::new(Tup::point(1.1, 2.0, 0.0), Tup::vector(0, -1, 0));
+        assert!(h.intersect(&ray).is_empty());
+        let above = Ray::new(Tup::point(-5.0, 0.6, 0.0), Tup::vector(1, 0, 0));
+        assert!(h.intersect(&above).is_empty());
+    }
+
+    #[test]
+    fn a_ray_on_a_slope() {
+        // Rising from 0 on the left to 1 on the right
+        let h = Heightfield::new(map(2, 2, &[0.0, 1.0, 0.0, 1.0]));
+        let ray = Ray::new(Tup::point(0.0, 5.0, 0.2), Tup::vector(0, -1, 0));
+        let xs = h.intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_nearly_eq(4.5, xs[0].t());
+        assert_eq!(
+            Tup::vector(-0.5, 1.0, 0.0).normalize(),
+            h.normal_at(Tup::point(0.0, 0.5, 0.2))
+        );
+    }
+
+    #[test]
+    fn a_ray_across_a_ridge_crosses_it_twice() {
+        let h = Heightfield::new(map(3, 2, &[0.0, 1.0, 0.0, 0.0, 1.0, 0.0]));
+        let ray = Ray::new(Tup::point(-5.0, 0.5, 0.3), Tup::vector(1, 0, 0));
+        let xs = h.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(4.5, xs[0].t());
+        assert_nearly_eq(5.5, xs[1].t());
+    }
+
+    #[test]
+    fn normals_are_blended_between_samples() {
+        let h = Heightfield::new(map(3, 2, &[0.0, 1.0, 0.0, 0.0, 1.0, 0.0]));
+        // On top of the ridge the slopes either side cancel out
+        assert_eq!(Tup::vector(0, 1, 0), h.normal_at(Tup::point(0.0, 1.0, 0.5)));
+        // Halfway up, the normal is between the edge's and the top's
+        let edge = Tup::vector(-1, 1, 0).normalize();
+        let top = Tup::vector(0, 1, 0);
+        assert_eq!(
+            ((edge + top) * 0.5).normalize(),
+            h.normal_at(Tup::point(-0.5, 0.5, 0.0))
+        );
+    }
+
+    #[test]
+    fn grid_traversal_finds_the_nearest_triangle() {
+        let h = Heightfield::new(HeightMap::noise(24, 24, 4.0, 3))
+            .with_transform(transforms::scaling(1.0, 0.3, 1.0));
+        let m = h.map();
+        for i in 0..20 {
+            let origin = Tup::point(-1.5 + 0.1 * i as f64, 1.0, -1.4 + 0.05 * i as f64);
+            let direction = Tup::vector(0.8, -0.35, 0.6 - 0.05 * i as f64).normalize();
+            let ray = Ray::new(origin, direction);
+            let local = ray.transform(&h.transform().inverse());
+            let mut brute_force = f64::INFINITY;
+            for row in 0..m.rows() - 1 {
+                for column in 0..m.columns() - 1 {
+                    let (p00, p10) = (m.vertex(column, row), m.vertex(column + 1, row));
+                    let (p11, p01) = (m.vertex(column + 1, row + 1), m.vertex(column, row + 1));
+                    for t in [
+                        triangle_hit(&local, p00, p10, p11),
+                        triangle_hit(&local, p00, p11, p01),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .filter(|t| *t > 0.0)
+                    {
+                        brute_force = brute_force.min(t);
+                    }
+                }
+            }
+            match h.intersect(&ray).hit() {
+                Some(hit) => assert_nearly_eq(brute_force, hit.t()),
+                None => assert!(brute_force.is_infinite()),
+            }
+        }
+    }
+
+    #[test]
+    fn the_height_map_image_lies_over_the_terrain() {
+        let h = Heightfield::new(map(2, 2, &[0.0; 4]));
+        assert_eq!((0.0, 0.0), h.local_uv_at(Tup::point(-1, 0, 1)));
+        assert_eq!((1.0, 1.0), h.local_uv_at(Tup::point(1, 0, -1)));
+    }
+
+    #[test]
+    fn heightfields_are_only_equal_to_themselves() {
+        let m = map(2, 2, &[0.0; 4]);
+        let h = Heightfield::new(m);
+        assert_eq!(h, h);
+        assert_ne!(h, Heightfield::new(map(2, 2, &[0.0; 4])));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// Another copy of a shape, placed with its own transform and material. The
+// geometry itself is shared, so a forest of a thousand trees holds only one
+// tree. The instance's transform is applied on top of the geometry's own
+#[derive(Clone)]
+pub struct Instance {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    geometry: Arc<dyn Shape>,
+    // The object the geometry gave back for a hit, which may remember where
+    // it was hit, as meshes do
+    hit: Option<Box<dyn Shape>>,
+}
+
+impl Instance {
+    // Until given a material, an instance shows the geometry's own
+    pub fn new(geometry: Arc<dyn Shape>) -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            geometry,
+            hit: None,
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn geometry(&self) -> &Arc<dyn Shape> {
+        &self.geometry
+    }
+
+    fn surface(&self) -> &dyn Shape {
+        self.hit.as_deref().unwrap_or(&*self.geometry)
+    }
+}
+
+// The geometry is shown by address, as two instances of it are the same
+// geometry. The object that was hit is shown too, so that the different
+// children of an instanced group aren't mistaken for one another
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("id", &self.id)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .field("geometry", &Arc::as_ptr(&self.geometry))
+            .field("hit", &self.hit)
+            .finish()
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Shape for Instance {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.own_material().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    // The instance's material wins over any the geometry has
+    fn own_material(&self) -> Option<Material> {
+        self.material
+            .clone()
+            .or_else(|| self.surface().own_material())
+    }
+
+    fn world_to_object(&self) -> Mat4 {
+        self.surface().world_to_object() * self.transform.inverse()
+    }
+
+    // The ray is already in the instance's space, so the geometry only has
+    // its own transform left to apply
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let geometry_xs = self.geometry.intersect(&local_ray);
+        let xs: Vec<Intersection> = (0..geometry_xs.len())
+            .map(|i| {
+                let x = &geometry_xs[i];
+                let instance = Self {
+                    hit: Some(x.object().clone()),
+                    ..self.clone()
+                };
+                let intersection = Intersection::new(x.t(), instance);
+                match x.face() {
+                    Some(face) => intersection.with_face(face),
+                    None => intersection,
+                }
+            })
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        self.surface().normal_at(point)
+    }
+
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        self.surface().uv_at(point)
+    }
+
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        self.surface().tangent_at(point)
+    }
+}
+
+#[cfg(test)]
+mod instances_test {
+    use super::*;
+    use crate::color::Color;
+    use crate::meshes::{Mesh, MeshData};
+    use crate::spheres::Sphere;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+    use std::f64::consts;
+
+    #[test]
+    fn an_instance_is_intersected_where_it_was_placed() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::default());
+        let instance =
+            Instance::new(sphere.clone()).with_transform(transforms::translation(0, 0, 10));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = instance.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(14.0, xs[0].t());
+        assert_nearly_eq(16.0, xs[1].t());
+        assert_nearly_eq(4.0, sphere.intersect(&ray)[0].t());
+    }
+
+    #[test]
+    fn the_instance_transform_applies_on_top_of_the_geometry_s_own() {
+        let sphere = Arc::new(Sphere::default().with_transform(transforms::scaling(2, 2, 2)));
+        let instance = Instance::new(sphere).with_transform(transforms::translation(5, 0, 0));
+        let ray = Ray::new(Tup::point(5, 0, -5), Tup::vector(0, 0, 1));
+        let xs = instance.intersect(&ray);
+        assert_nearly_eq(3.0, xs[0].t());
+        assert_nearly_eq(7.0, xs[1].t());
+    }
+
+    #[test]
+    fn an_instance_has_its_own_material() {
+        let red = Material::default().with_color(Color::new(1, 0, 0));
+        let sphere = Arc::new(Sphere::default().with_material(red.clone()));
+        let instance = Instance::new(sphere.clone());
+        assert_eq!(red, instance.material());
+        let blue = Material::default().with_color(Color::new(0, 0, 1));
+        let instance = instance.with_material(blue.clone());
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert_eq!(blue, instance.intersect(&ray)[0].object().material());
+        assert_eq!(red, sphere.material());
+    }
+
+    #[test]
+    fn instances_share_their_geometry() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::default());
+        let forest: Vec<Instance> = (0..10)
+            .map(|i| Instance::new(sphere.clone()).with_transform(transforms::translation(i, 0, 0)))
+            .collect();
+        assert_eq!(11, Arc::strong_count(&sphere));
+        assert!(Arc::ptr_eq(&sphere, forest[3].geometry()));
+    }
+
+    #[test]
+    fn normals_are_carried_through_both_transforms() {
+        let sphere = Arc::new(Sphere::default().with_transform(transforms::translation(0, 1, 0)));
+        let instance =
+            Instance::new(sphere).with_transform(transforms::rotation_z(consts::FRAC_PI_2));
+        let n = instance.normal_at(Tup::point(-2, 0, 0));
+        assert_eq!(Tup::vector(-1, 0, 0), n);
+    }
+
+    #[test]
+    fn an_instance_of_a_mesh_reports_the_face_and_its_normal() {
+        let square = MeshData::new(
+            vec![
+                Tup::point(-1, 0, -1),
+                Tup::point(1, 0, -1),
+                Tup::point(1, 0, 1),
+                Tup::point(-1, 0, 1),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .expect("Valid mesh");
+        let mesh = Arc::new(Mesh::new(square));
+        let instance = Instance::new(mesh).with_transform(transforms::rotation_x(consts::PI));
+        let ray = Ray::new(Tup::point(0.5, -5.0, -0.2), Tup::vector(0, 1, 0));
+        let xs = instance.intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_eq!(Some(0), xs[0].face());
+        let point = ray.position(xs[0].t());
+        assert_eq!(Tup::vector(0, -1, 0), xs[0].object().normal_at(point));
+    }
+
+    #[test]
+    fn a_glass_instance_is_entered_and_left_as_one_object() {
+        let sphere = Arc::new(Sphere::glass_sphere());
+        let instance = Instance::new(sphere).with_transform(transforms::scaling(2, 2, 2));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = instance.intersect(&ray);
+        let leaving = xs[1].prepare_computations(&ray, &xs);
+        assert_eq!((1.5, 1.0), (leaving.n1(), leaving.n2()));
+    }
+
+    #[test]
+    fn instances_can_be_instanced() {
+        let sphere = Arc::new(Sphere::default());
+        let row = Arc::new(Instance::new(sphere).with_transform(transforms::translation(0, 0, 3)));
+        let instance = Instance::new(row).with_transform(transforms::translation(0, 0, 3));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = instance.intersect(&ray);
+        assert_nearly_eq(10.0, xs[0].t());
+        let point = ray.position(xs[0].t());
+        assert_eq!(Tup::vector(0, 0, -1), xs[0].object().normal_at(point));
+    }
+}
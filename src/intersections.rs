@@ -1,3 +1,4 @@
+use crate::materials::Material;
 use crate::math_helpers::EPSILON;
 use crate::rays::Ray;
 use crate::shapes::Shape;
@@ -6,6 +7,7 @@ use std::ops::Index;
 
 type Object = Box<dyn Shape>;
 
+#[derive(Clone)]
 pub struct Computations {
     intersection: Intersection,
     point: Tup,
@@ -15,6 +17,15 @@ pub struct Computations {
     over_point: Tup,
     under_point: Tup,
     reflectv: Tup,
+    wavelength: Option<f64>,
+    // The materials on either side of the surface, or `None` for empty
+    // space. Kept so the refractive indices can be recomputed for other
+    // wavelengths
+    n1_material: Option<Material>,
+    n2_material: Option<Material>,
+    // The object the ray travelled through to reach the surface, or `None`
+    // for empty space
+    n1_object: Option<Object>,
     n1: f64,
     n2: f64,
 }
@@ -23,36 +34,71 @@ impl Computations {
     fn new(intersection: &Intersection, ray: &Ray, xs: &Intersections) -> Self {
         let point = ray.position(intersection.t());
         let eyev = -ray.direction();
-        let n = intersection.object().normal_at(point);
-        let inside = n.dot(&eyev) < 0.0;
+        let geometric_n = intersection.object().normal_at(point);
+        let inside = geometric_n.dot(&eyev) < 0.0;
+        let n = Self::shading_normal(intersection.object(), point, geometric_n);
         let normalv = if inside { -n } else { n };
-        let (n1, n2) = Self::calc_n1_n2(intersection, xs);
+        // Points are nudged off the real surface, not the bumped one, so they
+        // always end up on the right side of it
+        let offsetv = if inside { -geometric_n } else { geometric_n };
+        let (n1_object, n2_object) = Self::calc_n1_n2(intersection, xs);
+        let n1_material = n1_object.as_ref().map(|o| o.material());
+        let n2_material = n2_object.map(|o| o.material());
+        let wavelength = ray.wavelength();
         Self {
             intersection: intersection.clone(),
             point,
             eyev,
             normalv,
             inside,
-            over_point: point + (normalv * EPSILON),
-            under_point: point - (normalv * EPSILON),
+            over_point: point + (offsetv * EPSILON),
+            under_point: point - (offsetv * EPSILON),
             reflectv: ray.direction().reflect(&normalv),
-            n1,
-            n2,
+            wavelength,
+            n1: Self::refractive_index(n1_material.as_ref(), wavelength),
+            n2: Self::refractive_index(n2_material.as_ref(), wavelength),
+            n1_material,
+            n2_material,
+            n1_object,
         }
     }
 
-    fn calc_n1_n2(intersection: &Intersection, xs: &Intersections) -> (f64, f64) {
+    // The normal everything is shaded with, tilted by the material's bump
+    // map if it has one
+    fn shading_normal(object: &Object, point: Tup, normal: Tup) -> Tup {
+        match object.material().bump() {
+            Some(bump) => bump.perturb(
+                object.world_to_object(),
+                point,
+                normal,
+                object.tangent_at(point),
+                object.uv_at(point),
+            ),
+            None => normal,
+        }
+    }
+
+    fn refractive_index(material: Option<&Material>, wavelength: Option<f64>) -> f64 {
+        match (material, wavelength) {
+            (Some(m), Some(wavelength)) => m.refractive_index_at(wavelength),
+            (Some(m), None) => m.refractive_index(),
+            (None, _) => 1.0,
+        }
+    }
+
+    fn calc_n1_n2(
+        intersection: &Intersection,
+        xs: &Intersections,
+    ) -> (Option<Object>, Option<Object>) {
         let likely_eq = |o1: &Object, o2: &Object| format!("{:?}", o1) == format!("{:?}", o2);
         let mut containers = Vec::new();
-        let mut n1 = 1.0;
-        let mut n2 = 1.0;
+        let mut n1 = None;
+        let mut n2 = None;
         for i in 0..xs.len() {
             let inter = xs[i].clone();
             let is_hit = intersection.t() == inter.t();
             if is_hit {
-                n1 = containers
-                    .last()
-                    .map_or(1.0, |j: &Object| j.material().refractive_index());
+                n1 = containers.last().cloned();
             };
 
             let index = containers.iter().position(|x| likely_eq(x, inter.object()));
@@ -66,9 +112,7 @@ impl Computations {
             }
 
             if is_hit {
-                n2 = containers
-                    .last()
-                    .map_or(1.0, |j: &Object| j.material().refractive_index());
+                n2 = containers.last().cloned();
                 break;
             }
         }
@@ -83,6 +127,33 @@ impl Computations {
         self.intersection.object()
     }
 
+    // The material of the hit object, as seen by a ray of a single
+    // wavelength if the ray has one
+    pub fn material(&self) -> Material {
+        let material = self.object().material();
+        match self.wavelength {
+            Some(wavelength) => material.at_wavelength(wavelength),
+            None => material,
+        }
+    }
+
+    // The material the ray travelled through to reach the surface, as seen
+    // by a ray of a single wavelength if the ray has one. `None` for empty
+    // space
+    pub fn n1_material(&self) -> Option<Material> {
+        match self.wavelength {
+            Some(wavelength) => self
+                .n1_material
+                .as_ref()
+                .map(|m| m.at_wavelength(wavelength)),
+            None => self.n1_material.clone(),
+        }
+    }
+
+    pub fn n1_object(&self) -> Option<&Object> {
+        self.n1_object.as_ref()
+    }
+
     pub fn point(&self) -> Tup {
         self.point
     }
@@ -119,6 +190,53 @@ impl Computations {
         self.under_point
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    // The same hit seen by a ray of a single wavelength, which may bend
+    // differently when either material is dispersive
+    pub fn at_wavelength(&self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            n1: Self::refractive_index(self.n1_material.as_ref(), Some(wavelength)),
+            n2: Self::refractive_index(self.n2_material.as_ref(), Some(wavelength)),
+            ..self.clone()
+        }
+    }
+
+    // Whether light crossing this surface is split by wavelength
+    pub fn is_dispersive(&self) -> bool {
+        [&self.n1_material, &self.n2_material]
+            .into_iter()
+            .flatten()
+            .any(|m| m.abbe_number().is_some())
+    }
+
+    // A ray leaving this hit, carrying the wavelength of the ray that
+    // arrived
+    pub fn spawn_ray(&self, origin: Tup, direction: Tup) -> Ray {
+        let ray = Ray::new(origin, direction);
+        match self.wavelength {
+            Some(wavelength) => ray.with_wavelength(wavelength),
+            None => ray,
+        }
+    }
+
+    // The direction of the transmitted ray, or `None` under total internal
+    // reflection
+    pub fn refractv(&self) -> Option<Tup> {
+        let n_ratio = self.n1 / self.n2;
+        let cos_i = self.eyev.dot(&self.normalv);
+        let sin2_t = (n_ratio * n_ratio) * (1.0 - (cos_i * cos_i));
+        if sin2_t > 1.0 {
+            None
+        } else {
+            let cos_t = (1.0 - sin2_t).sqrt();
+            Some(self.normalv * (n_ratio * cos_i - cos_t) - self.eyev * n_ratio)
+        }
+    }
+
     pub fn schlick(&self) -> f64 {
         let mut cos = self.eyev.dot(&self.normalv());
         if self.n1 > self.n2 {
@@ -141,6 +259,8 @@ impl Computations {
 pub struct Intersection {
     t: f64,
     object: Object,
+    // Which face of a mesh was hit
+    face: Option<usize>,
 }
 
 impl Intersection {
@@ -152,6 +272,7 @@ impl Intersection {
         Self {
             t: t.into(),
             object: Box::new(s),
+            face: None,
         }
     }
 
@@ -162,6 +283,14 @@ impl Intersection {
         Self {
             t: t.into(),
             object: s,
+            face: None,
+        }
+    }
+
+    pub fn with_face(self, face: usize) -> Self {
+        Self {
+            face: Some(face),
+            ..self
         }
     }
 
@@ -173,6 +302,10 @@ impl Intersection {
         &self.object
     }
 
+    pub fn face(&self) -> Option<usize> {
+        self.face
+    }
+
     pub fn prepare_computations(&self, ray: &Ray, xs: &Intersections) -> Computations {
         Computations::new(self, ray, xs)
     }
@@ -210,6 +343,10 @@ impl Intersections {
         self.len() == 0
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Intersection> + '_ {
+        self.inters.iter()
+    }
+
     pub fn hit(&self) -> Option<&Intersection> {
         self.inters
             .iter()
@@ -235,6 +372,9 @@ mod intersections_test {
     use std::f64::consts;
 
     use super::*;
+    use crate::bump::{Bump, NormalMap};
+    use crate::canvas::Canvas;
+    use crate::color::Color;
     use crate::matrix::Mat4;
     use crate::planes::Plane;
     use crate::spheres::Sphere;
@@ -251,15 +391,22 @@ mod intersections_test {
     #[test]
     fn an_intersection_encapsulates_an_object() {
         let s = Sphere::default();
-        let intersection = Intersection::new(3.5, s);
+        let intersection = Intersection::new(3.5, s.clone());
         assert_eq!(s.material(), intersection.object().material());
         assert_eq!(s.transform(), intersection.object().transform());
     }
 
+    #[test]
+    fn an_intersection_can_record_the_face_it_hit() {
+        let s = Sphere::default();
+        assert_eq!(None, Intersection::new(3.5, s.clone()).face());
+        assert_eq!(Some(7), Intersection::new(3.5, s).with_face(7).face());
+    }
+
     #[test]
     fn intersections_can_be_aggregated() {
         let s = Sphere::default();
-        let i1 = Intersection::new(1.0, s);
+        let i1 = Intersection::new(1.0, s.clone());
         let i2 = Intersection::new(2.0, s);
         let mut xs = Intersections::default();
         xs = xs.push(i1);
@@ -272,7 +419,7 @@ mod intersections_test {
     #[test]
     fn when_all_intersectons_are_positive_hit_returns_the_smallest_t() {
         let s = Sphere::default();
-        let i1 = Intersection::new(1, s);
+        let i1 = Intersection::new(1, s.clone());
         let i2 = Intersection::new(2, s);
         let xs = Intersections::new(&[i1, i2]);
         let i = xs.hit();
@@ -282,7 +429,7 @@ mod intersections_test {
     #[test]
     fn when_there_are_negative_intersections_hit_returns_smallest_positive_t() {
         let s = Sphere::default();
-        let i1 = Intersection::new(-1, s);
+        let i1 = Intersection::new(-1, s.clone());
         let i2 = Intersection::new(1, s);
         let xs = Intersections::new(&[i1, i2]);
         let i = xs.hit();
@@ -292,7 +439,7 @@ mod intersections_test {
     #[test]
     fn when_all_intersections_have_negative_t_hit_returns_nothing() {
         let s = Sphere::default();
-        let i1 = Intersection::new(-2, s);
+        let i1 = Intersection::new(-2, s.clone());
         let i2 = Intersection::new(-1, s);
         let xs = Intersections::new(&[i1, i2]);
         let i = xs.hit();
@@ -302,9 +449,9 @@ mod intersections_test {
     #[test]
     fn hit_always_returns_the_smallest_nonnegative_intersection() {
         let s = Sphere::default();
-        let i1 = Intersection::new(5, s);
-        let i2 = Intersection::new(7, s);
-        let i3 = Intersection::new(-3, s);
+        let i1 = Intersection::new(5, s.clone());
+        let i2 = Intersection::new(7, s.clone());
+        let i3 = Intersection::new(-3, s.clone());
         let i4 = Intersection::new(2, s);
         let xs = Intersections::new(&[i1, i2, i3, i4]);
         let i = xs.hit();
@@ -393,9 +540,9 @@ mod intersections_test {
         let inner_c = custom_glass_sphere(2.5, transforms::translation(0.0, 0.0, 0.25));
         let r = Ray::new(Tup::point(0, 0, 4), Tup::vector(0, 0, 1));
         let xs = Intersections::new(&[
-            Intersection::new(2, outer_a),
-            Intersection::new(2.75, inner_b),
-            Intersection::new(3.25, inner_c),
+            Intersection::new(2, outer_a.clone()),
+            Intersection::new(2.75, inner_b.clone()),
+            Intersection::new(3.25, inner_c.clone()),
             Intersection::new(4.75, inner_b),
             Intersection::new(5.25, inner_c),
             Intersection::new(6, outer_a),
@@ -428,6 +575,35 @@ mod intersections_test {
         assert!(comps.point().z < under_point.z);
     }
 
+    #[test]
+    fn the_refraction_vector_bends_toward_the_normal_entering_glass() {
+        let s = Sphere::glass_sphere();
+        let r = Ray::new(Tup::point(0.0, 0.5, -5.0), Tup::vector(0, 0, 1));
+        let xs = s.intersect(&r);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        let refractv = comps.refractv().expect("No total internal reflection");
+        let cos_i = comps.eyev().dot(&comps.normalv());
+        let cos_t = -refractv.dot(&comps.normalv()) / refractv.magnitude();
+        assert!(cos_t > cos_i);
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        let sin_t = (1.0 - cos_t * cos_t).sqrt();
+        assert_nearly_eq(1.5, sin_i / sin_t);
+    }
+
+    #[test]
+    fn there_is_no_refraction_vector_under_total_internal_reflection() {
+        let s = Sphere::glass_sphere();
+        let r = Ray::new(
+            Tup::point(0.0, 0.0, consts::SQRT_2 / 2.0),
+            Tup::vector(0, 1, 0),
+        );
+        let i1 = Intersection::new(-consts::SQRT_2 / 2.0, s.clone());
+        let i2 = Intersection::new(consts::SQRT_2 / 2.0, s);
+        let xs = Intersections::new(&[i1, i2]);
+        let comps = xs[1].prepare_computations(&r, &xs);
+        assert!(comps.refractv().is_none());
+    }
+
     #[test]
     fn the_schlick_aprox_under_total_internal_reflection_is_1() {
         let s = Sphere::glass_sphere();
@@ -435,7 +611,7 @@ mod intersections_test {
             Tup::point(0.0, 0.0, consts::SQRT_2 / 2.0),
             Tup::vector(0, 1, 0),
         );
-        let i1 = Intersection::new(-consts::SQRT_2 / 2.0, s);
+        let i1 = Intersection::new(-consts::SQRT_2 / 2.0, s.clone());
         let i2 = Intersection::new(consts::SQRT_2 / 2.0, s);
         let xs = Intersections::new(&[i1, i2]);
         let comps = xs[1].prepare_computations(&r, &xs);
@@ -447,7 +623,10 @@ mod intersections_test {
     fn the_schlick_approx_with_perpendicular_viewing_angle() {
         let s = Sphere::glass_sphere();
         let r = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, 1, 0));
-        let xs = Intersections::new(&[Intersection::new(-1.0, s), Intersection::new(1.0, s)]);
+        let xs = Intersections::new(&[
+            Intersection::new(-1.0, s.clone()),
+            Intersection::new(1.0, s),
+        ]);
         let comps = xs[1].prepare_computations(&r, &xs);
         let reflectance = comps.schlick();
         assert_nearly_eq(reflectance, 0.04);
@@ -462,4 +641,81 @@ mod intersections_test {
         let reflectance = comps.schlick();
         assert_nearly_eq(reflectance, 0.48873);
     }
+
+    fn flint_glass_sphere() -> Sphere {
+        Sphere::default().with_material(
+            Material::default()
+                .with_transparency(1.0)
+                .with_refractive_index(1.62)
+                .with_abbe_number(36.0),
+        )
+    }
+
+    #[test]
+    fn a_glass_sphere_is_not_dispersive() {
+        let s = Sphere::glass_sphere();
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = Intersections::new(&[Intersection::new(4, s.clone()), Intersection::new(6, s)]);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert!(!comps.is_dispersive());
+        assert_eq!(None, comps.wavelength());
+    }
+
+    #[test]
+    fn the_refractive_index_depends_on_the_wavelength_of_the_ray() {
+        let s = flint_glass_sphere();
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = Intersections::new(&[
+            Intersection::new(4, s.clone()),
+            Intersection::new(6, s.clone()),
+        ]);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert!(comps.is_dispersive());
+        assert_nearly_eq(1.62, comps.n2());
+        let blue = xs[0].prepare_computations(&r.with_wavelength(450.0), &xs);
+        let red = comps.at_wavelength(650.0);
+        assert_eq!(Some(450.0), blue.wavelength());
+        assert_nearly_eq(1.0, blue.n1());
+        assert!(blue.n2() > red.n2());
+        assert_nearly_eq(s.material().refractive_index_at(650.0), red.n2());
+    }
+
+    #[test]
+    fn rays_spawned_from_a_hit_keep_the_wavelength() {
+        let s = flint_glass_sphere();
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1)).with_wavelength(500.0);
+        let xs = Intersections::new(&[Intersection::new(4, s.clone()), Intersection::new(6, s)]);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        let spawned = comps.spawn_ray(comps.under_point(), Tup::vector(0, 0, 1));
+        assert_eq!(Some(500.0), spawned.wavelength());
+    }
+
+    #[test]
+    fn the_material_of_a_hit_is_seen_at_the_ray_wavelength() {
+        let s = Sphere::default()
+            .with_material(Material::default().with_color(Color::new(1.0, 0.0, 0.0)));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = Intersections::new(&[Intersection::new(4, s)]);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert_eq!(Color::new(1, 0, 0), comps.material().color());
+        let comps = xs[0].prepare_computations(&r.with_wavelength(450.0), &xs);
+        assert_eq!(Color::new(0, 0, 0), comps.material().color());
+    }
+
+    #[test]
+    fn a_bump_map_tilts_the_shading_normal() {
+        let tilted = Tup::vector(1, 0, 1).normalize();
+        let mut image = Canvas::new(1, 1);
+        image = image.write_pixel(0, 0, NormalMap::encode(tilted));
+        let material = Material::default().with_bump(Bump::normal_map(NormalMap::new(image), 1.0));
+        let shape = Sphere::default().with_material(material);
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let i = Intersection::new(4, shape);
+        let comps = i.prepare_computations(&r, &Intersections::new(std::slice::from_ref(&i)));
+        assert_eq!(Tup::vector(1, 0, -1).normalize(), comps.normalv());
+        assert_eq!(Tup::vector(1, 0, 0), comps.reflectv());
+        // The points either side of the surface stay on the real normal
+        assert_eq!(Tup::point(0.0, 0.0, -1.0 - EPSILON), comps.over_point());
+        assert!(!comps.inside());
+    }
 }
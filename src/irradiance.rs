@@ -0,0 +1,133 @@
+use crate::background::Background;
+use crate::color::consts;
+use crate::color::Color;
+use crate::tup::Tup;
+use std::f64::consts::PI;
+
+// Diffuse lighting from an environment, projected onto the first nine
+// spherical harmonics. Irradiance varies slowly with the surface normal so
+// nine coefficients reproduce it to within a few percent, and a lookup is
+// just a dot product instead of an integral over the whole sky.
+// See Ramamoorthi & Hanrahan, "An Efficient Representation for Irradiance
+// Environment Maps"
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct IrradianceMap {
+    coefficients: [Color; 9],
+}
+
+impl IrradianceMap {
+    const THETA_STEPS: usize = 64;
+    const PHI_STEPS: usize = 128;
+
+    // Convolution of the radiance with the clamped cosine lobe for bands 0, 1
+    // and 2
+    const BAND_WEIGHTS: [f64; 9] = [
+        PI,
+        2.0 * PI / 3.0,
+        2.0 * PI / 3.0,
+        2.0 * PI / 3.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+        PI / 4.0,
+    ];
+
+    pub fn from_background(background: &Background) -> Self {
+        let mut coefficients = [consts::BLACK; 9];
+        let d_theta = PI / Self::THETA_STEPS as f64;
+        let d_phi = 2.0 * PI / Self::PHI_STEPS as f64;
+        for i in 0..Self::THETA_STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            let solid_angle = theta.sin() * d_theta * d_phi;
+            for j in 0..Self::PHI_STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = Tup::vector(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let radiance = background.color_at(direction);
+                for (c, y) in coefficients.iter_mut().zip(Self::basis(direction)) {
+                    *c = *c + radiance * (y * solid_angle);
+                }
+            }
+        }
+        Self { coefficients }
+    }
+
+    fn basis(n: Tup) -> [f64; 9] {
+        [
+            0.282095,
+            0.488603 * n.y,
+            0.488603 * n.z,
+            0.488603 * n.x,
+            1.092548 * n.x * n.y,
+            1.092548 * n.y * n.z,
+            0.315392 * (3.0 * n.z * n.z - 1.0),
+            1.092548 * n.x * n.z,
+            0.546274 * (n.x * n.x - n.y * n.y),
+        ]
+    }
+
+    // The irradiance arriving at a surface facing `normal`, divided by pi so
+    // that a uniformly white environment yields white. This is the light a
+    // perfectly diffuse surface reflects, so it can stand in for the ambient
+    // light intensity
+    pub fn irradiance_at(&self, normal: Tup) -> Color {
+        let irradiance = self
+            .coefficients
+            .iter()
+            .zip(Self::basis(normal))
+            .zip(Self::BAND_WEIGHTS)
+            .fold(consts::BLACK, |sum, ((c, y), weight)| {
+                sum + *c * (y * weight)
+            });
+        irradiance * (1.0 / PI)
+    }
+}
+
+#[cfg(test)]
+mod irradiance_test {
+    use super::*;
+    use crate::color::consts as color;
+
+    fn assert_color_close(expected: Color, actual: Color) {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-3;
+        assert!(
+            close(expected.red(), actual.red())
+                && close(expected.green(), actual.green())
+                && close(expected.blue(), actual.blue()),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn a_uniform_environment_gives_the_same_irradiance_everywhere() {
+        let map = IrradianceMap::from_background(&Background::solid(Color::new(0.5, 1.0, 2.0)));
+        let expected = Color::new(0.5, 1.0, 2.0);
+        assert_color_close(expected, map.irradiance_at(Tup::vector(0, 1, 0)));
+        assert_color_close(expected, map.irradiance_at(Tup::vector(1, 0, 0)));
+        assert_color_close(expected, map.irradiance_at(Tup::vector(0, 0, -1)));
+    }
+
+    #[test]
+    fn a_black_environment_gives_no_irradiance() {
+        let map = IrradianceMap::from_background(&Background::default());
+        assert_color_close(color::BLACK, map.irradiance_at(Tup::vector(0, 1, 0)));
+    }
+
+    #[test]
+    fn surfaces_facing_a_bright_sky_receive_more_light() {
+        // White above the horizon and black below. Facing straight up sees
+        // the full sky, facing down sees none of it and facing sideways sees
+        // half of it
+        let map = IrradianceMap::from_background(&Background::gradient(color::BLACK, color::WHITE));
+        let up = map.irradiance_at(Tup::vector(0, 1, 0));
+        let side = map.irradiance_at(Tup::vector(1, 0, 0));
+        let down = map.irradiance_at(Tup::vector(0, -1, 0));
+        assert!(up.red() > side.red());
+        assert!(side.red() > down.red());
+        assert!(down.red().abs() < 0.05);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::color::Color;
+use crate::spectrum;
 use crate::tup::Tup;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -22,6 +23,14 @@ impl Light {
     pub fn intensity(&self) -> Color {
         self.intensity
     }
+
+    // The light as seen at a single wavelength, with a gray intensity
+    pub fn at_wavelength(&self, wavelength: f64) -> Self {
+        Self {
+            intensity: spectrum::monochrome(self.intensity, Some(wavelength)),
+            ..*self
+        }
+    }
 }
 
 #[cfg(test)]
@@ -43,4 +52,12 @@ mod lights_test {
         let point_light = Light::point_light(position, intensity);
         assert_eq!(intensity, point_light.intensity());
     }
+
+    #[test]
+    fn a_light_at_one_wavelength_is_gray() {
+        let position = Tup::point(1, 2, 3);
+        let light = Light::point_light(position, Color::new(2.0, 1.0, 0.0)).at_wavelength(650.0);
+        assert_eq!(position, light.position());
+        assert_eq!(Color::new(2, 2, 2), light.intensity());
+    }
 }
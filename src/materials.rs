@@ -1,13 +1,40 @@
+use crate::bump::Bump;
 use crate::color::consts;
 use crate::color::Color;
 use crate::lights::Light;
 use crate::matrix::Mat4;
+use crate::media::Medium;
+use crate::microfacet;
 use crate::patterns::Pattern;
+use crate::spectrum::{self, Spectrum};
 use crate::tup::Tup;
+use std::f64::consts::PI;
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+// How light from the light source is reflected by a material
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum ShadingModel {
+    // Classic Phong shading controlled by `diffuse`, `specular` and
+    // `shininess`
+    #[default]
+    Phong,
+
+    // Physically based shading: a GGX microfacet specular lobe controlled by
+    // `roughness` and `metallic` over an energy conserving diffuse base.
+    // Dielectrics reflect as their `refractive_index` dictates, or like most
+    // dielectrics if it is left at 1. `diffuse`, `specular` and `shininess`
+    // are ignored
+    Microfacet,
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub struct Material {
     color: Color,
+    // An explicit reflectance spectrum used instead of `color` in spectral
+    // renders. Materials with different spectra can match in RGB and still
+    // look different under a colored light
+    spectrum: Option<Spectrum>,
+    // Set on the single wavelength copies made by `at_wavelength`
+    wavelength: Option<f64>,
     // Ambient reflection is background lighting or light reflected from
     // other objects in the environment. THis is treated as constant, coloring
     // all points equqlly
@@ -25,7 +52,61 @@ pub struct Material {
     reflective: f64,
     transparency: f64,
     refractive_index: f64,
+    // How strongly the refractive index varies with wavelength. Lower
+    // numbers disperse more, crown glass is around 60 and flint glass
+    // around 30. `None` refracts all wavelengths alike
+    abbe_number: Option<f64>,
     pattern: Option<Pattern>,
+    // Tilts the shading normal to fake fine detail like scratches, dents or
+    // the grain of a surface
+    bump: Option<Bump>,
+
+    // Light given off by the surface itself, independent of any light
+    // source. The color is scaled by the strength, which may exceed 1.0
+    emissive: Color,
+    emissive_strength: f64,
+
+    // Light absorbed per unit of distance travelled inside the material, for
+    // each color channel. Glass that absorbs green and blue looks red, and
+    // looks darker the thicker it is. The color is scaled by the density
+    absorption: Color,
+    absorption_density: f64,
+
+    // Fog or smoke filling the inside of the object. The surface is still
+    // shaded as usual, so a smoke filled ball is usually fully transparent
+    // with a refractive index of one
+    medium: Option<Medium>,
+
+    // Objects that don't cast shadows are ignored by shadow rays, which is
+    // handy for light fittings and other props that would otherwise block
+    // the light they are meant to show
+    casts_shadow: bool,
+
+    // Subsurface scattering for translucent materials like marble, wax and
+    // skin. Light entering the surface wanders around inside, travelling
+    // `mean_free_path` on average between scattering events, and leaves
+    // somewhere else tinted by `scatter_color`, the fraction of light that
+    // survives each event. It replaces the diffuse term of the surface, so
+    // the object should be closed. A mean free path of zero turns it off
+    scatter_color: Color,
+    mean_free_path: f64,
+
+    shading_model: ShadingModel,
+    // Microfacet roughness from perfectly smooth (0.0) to completely matte
+    // (1.0)
+    roughness: f64,
+    // Metals have no diffuse reflection and tint their specular reflection
+    // with the surface color. Values between 0.0 and 1.0 blend the two
+    metallic: f64,
+
+    // A clear glossy coat over the rest of the material, like the lacquer
+    // on car paint or varnished wood. `clearcoat` weighs the coat from none
+    // (0.0) to full (1.0). It reflects by its own refractive index and
+    // roughness, more so at grazing angles, and only the light it lets
+    // through reaches the material below and gets back out
+    clearcoat: f64,
+    clearcoat_ior: f64,
+    clearcoat_roughness: f64,
 }
 
 impl Material {
@@ -34,7 +115,21 @@ impl Material {
     }
 
     pub fn with_color(self, color: Color) -> Self {
-        Self { color, ..self }
+        Self {
+            color,
+            spectrum: None,
+            ..self
+        }
+    }
+
+    // Sets `color` to match the spectrum, so RGB renders look as close as
+    // they can to spectral ones
+    pub fn with_spectrum(self, spectrum: Spectrum) -> Self {
+        Self {
+            color: spectrum.to_color(),
+            spectrum: Some(spectrum),
+            ..self
+        }
     }
 
     pub fn with_diffuse(self, diffuse: f64) -> Self {
@@ -56,6 +151,13 @@ impl Material {
         }
     }
 
+    pub fn with_bump(self, bump: Bump) -> Self {
+        Self {
+            bump: Some(bump),
+            ..self
+        }
+    }
+
     pub fn with_reflective(self, reflective: f64) -> Self {
         Self { reflective, ..self }
     }
@@ -74,6 +176,96 @@ impl Material {
         }
     }
 
+    pub fn with_abbe_number(self, abbe_number: f64) -> Self {
+        Self {
+            abbe_number: Some(abbe_number),
+            ..self
+        }
+    }
+
+    pub fn with_emissive(self, emissive: Color) -> Self {
+        Self { emissive, ..self }
+    }
+
+    pub fn with_emissive_strength(self, emissive_strength: f64) -> Self {
+        Self {
+            emissive_strength,
+            ..self
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_absorption_density(self, absorption_density: f64) -> Self {
+        Self {
+            absorption_density,
+            ..self
+        }
+    }
+
+    pub fn with_medium(self, medium: Medium) -> Self {
+        Self {
+            medium: Some(medium),
+            ..self
+        }
+    }
+
+    pub fn with_casts_shadow(self, casts_shadow: bool) -> Self {
+        Self {
+            casts_shadow,
+            ..self
+        }
+    }
+
+    pub fn with_scatter_color(self, scatter_color: Color) -> Self {
+        Self {
+            scatter_color,
+            ..self
+        }
+    }
+
+    pub fn with_mean_free_path(self, mean_free_path: f64) -> Self {
+        Self {
+            mean_free_path,
+            ..self
+        }
+    }
+
+    pub fn with_shading_model(self, shading_model: ShadingModel) -> Self {
+        Self {
+            shading_model,
+            ..self
+        }
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        Self { roughness, ..self }
+    }
+
+    pub fn with_metallic(self, metallic: f64) -> Self {
+        Self { metallic, ..self }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f64) -> Self {
+        Self { clearcoat, ..self }
+    }
+
+    pub fn with_clearcoat_ior(self, clearcoat_ior: f64) -> Self {
+        Self {
+            clearcoat_ior,
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat_roughness(self, clearcoat_roughness: f64) -> Self {
+        Self {
+            clearcoat_roughness,
+            ..self
+        }
+    }
+
     pub fn ambient(&self) -> f64 {
         self.ambient
     }
@@ -82,6 +274,10 @@ impl Material {
         self.color
     }
 
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        self.spectrum
+    }
+
     pub fn diffuse(&self) -> f64 {
         self.diffuse
     }
@@ -94,6 +290,10 @@ impl Material {
         self.shininess
     }
 
+    pub fn bump(&self) -> Option<Bump> {
+        self.bump.clone()
+    }
+
     pub fn reflective(&self) -> f64 {
         self.reflective
     }
@@ -106,6 +306,161 @@ impl Material {
         self.refractive_index
     }
 
+    pub fn abbe_number(&self) -> Option<f64> {
+        self.abbe_number
+    }
+
+    // The refractive index for light of the given wavelength in nanometers.
+    // The index set on the material is the one for the yellow helium d line,
+    // and the Abbe number fixes the slope of a two term Cauchy equation,
+    // n = a + b / wavelength^2, through the blue F and red C lines
+    pub fn refractive_index_at(&self, wavelength: f64) -> f64 {
+        const D_LINE: f64 = 587.6;
+        const F_LINE: f64 = 486.1;
+        const C_LINE: f64 = 656.3;
+        match self.abbe_number {
+            Some(abbe_number) => {
+                let inverse_square = |nm: f64| 1.0 / (nm * nm);
+                let b = (self.refractive_index - 1.0)
+                    / (abbe_number * (inverse_square(F_LINE) - inverse_square(C_LINE)));
+                let a = self.refractive_index - b * inverse_square(D_LINE);
+                a + b * inverse_square(wavelength)
+            }
+            None => self.refractive_index,
+        }
+    }
+
+    pub fn emissive(&self) -> Color {
+        self.emissive
+    }
+
+    pub fn emissive_strength(&self) -> f64 {
+        self.emissive_strength
+    }
+
+    pub fn emission(&self) -> Color {
+        self.emissive * self.emissive_strength
+    }
+
+    pub fn absorption(&self) -> Color {
+        self.absorption
+    }
+
+    pub fn absorption_density(&self) -> f64 {
+        self.absorption_density
+    }
+
+    // The fraction of light in each channel that survives travelling
+    // `distance` through the material, following the Beer-Lambert law
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let optical_depth = self.absorption * (self.absorption_density * distance);
+        Color::new(
+            (-optical_depth.red()).exp(),
+            (-optical_depth.green()).exp(),
+            (-optical_depth.blue()).exp(),
+        )
+    }
+
+    pub fn medium(&self) -> Option<Medium> {
+        self.medium.clone()
+    }
+
+    pub fn casts_shadow(&self) -> bool {
+        self.casts_shadow
+    }
+
+    pub fn scatter_color(&self) -> Color {
+        self.scatter_color
+    }
+
+    pub fn mean_free_path(&self) -> f64 {
+        self.mean_free_path
+    }
+
+    pub fn has_subsurface(&self) -> bool {
+        self.mean_free_path > 0.0
+    }
+
+    // The fraction of the light leaving a subsurface walk that is reflected
+    // back towards the eye, standing in for the diffuse term it replaces
+    pub fn subsurface_albedo(&self, world_to_object: Mat4, position: Tup) -> Color {
+        self.color_at(world_to_object, position) * self.diffuse_weight()
+    }
+
+    // How much of the light reaching the surface is reflected diffusely.
+    // Set by `diffuse` for Phong shading, while microfacet metals have no
+    // diffuse part at all
+    pub fn diffuse_weight(&self) -> f64 {
+        match self.shading_model {
+            ShadingModel::Phong => self.diffuse,
+            ShadingModel::Microfacet => 1.0 - self.metallic,
+        }
+    }
+
+    pub fn shading_model(&self) -> ShadingModel {
+        self.shading_model
+    }
+
+    pub fn roughness(&self) -> f64 {
+        self.roughness
+    }
+
+    pub fn metallic(&self) -> f64 {
+        self.metallic
+    }
+
+    pub fn clearcoat(&self) -> f64 {
+        self.clearcoat
+    }
+
+    pub fn clearcoat_ior(&self) -> f64 {
+        self.clearcoat_ior
+    }
+
+    pub fn clearcoat_roughness(&self) -> f64 {
+        self.clearcoat_roughness
+    }
+
+    // The fraction of light the clearcoat reflects when seen at an angle
+    // with the given cosine to the normal. The rest passes through to the
+    // material below
+    pub fn clearcoat_reflectance(&self, cos_theta: f64) -> f64 {
+        if self.clearcoat == 0.0 {
+            return 0.0;
+        }
+        let f0 = microfacet::f0_from_ior(1.0, self.clearcoat_ior);
+        // The coat is colorless, so every channel is the same
+        let fresnel = microfacet::fresnel_schlick(cos_theta, Color::new(f0, f0, f0));
+        self.clearcoat * fresnel.red()
+    }
+
+    // The color of the surface at `position`, taking any pattern into account
+    pub fn color_at(&self, world_to_object: Mat4, position: Tup) -> Color {
+        self.pattern
+            .map(|p| spectrum::monochrome(p.color_at(world_to_object, position), self.wavelength))
+            .unwrap_or(self.color)
+    }
+
+    // A copy of the material as seen by light of a single wavelength, with
+    // every color replaced by a gray holding its spectrum's value there
+    pub fn at_wavelength(&self, wavelength: f64) -> Self {
+        let color = match self.spectrum {
+            Some(spectrum) => {
+                let value = spectrum.value_at(wavelength);
+                Color::new(value, value, value)
+            }
+            None => spectrum::monochrome(self.color, Some(wavelength)),
+        };
+        Self {
+            color,
+            emissive: spectrum::monochrome(self.emissive, Some(wavelength)),
+            absorption: spectrum::monochrome(self.absorption, Some(wavelength)),
+            scatter_color: spectrum::monochrome(self.scatter_color, Some(wavelength)),
+            wavelength: Some(wavelength),
+            ..self.clone()
+        }
+    }
+
     fn calc_diffuse(&self, effective_color: Color, light_dot_normal: f64) -> Color {
         effective_color * self.diffuse() * light_dot_normal
     }
@@ -121,32 +476,173 @@ impl Material {
         }
     }
 
+    // Reflectance at normal incidence shared by most dielectrics, for
+    // materials whose refractive index was never set
+    const DIELECTRIC_F0: f64 = 0.04;
+
+    fn dielectric_f0(&self) -> f64 {
+        if self.refractive_index == 1.0 {
+            Self::DIELECTRIC_F0
+        } else {
+            microfacet::f0_from_ior(1.0, self.refractive_index)
+        }
+    }
+
+    fn calc_microfacet(
+        &self,
+        color: Color,
+        lightv: Tup,
+        normalv: Tup,
+        eyev: Tup,
+        light: Light,
+    ) -> (Color, Color) {
+        // Light grazing the surface adds nothing, and would leave the
+        // specular lobe as 0 / 0
+        let n_dot_l = lightv.dot(&normalv);
+        if n_dot_l <= 0.0 {
+            return (consts::BLACK, consts::BLACK);
+        }
+        let n_dot_v = normalv.dot(&eyev).max(1e-4);
+        let halfv = (lightv + eyev).normalize();
+        let n_dot_h = normalv.dot(&halfv).max(0.0);
+        let v_dot_h = eyev.dot(&halfv).max(0.0);
+        let dielectric_f0 = self.dielectric_f0();
+        let dielectric_f0 = Color::new(dielectric_f0, dielectric_f0, dielectric_f0);
+        let f0 = dielectric_f0 * (1.0 - self.metallic) + color * self.metallic;
+        let fresnel = microfacet::fresnel_schlick(v_dot_h, f0);
+        let d = microfacet::ggx_distribution(n_dot_h, self.roughness);
+        let g = microfacet::smith_geometry(n_dot_v, n_dot_l, self.roughness);
+        let specular_brdf = fresnel * (d * g / (4.0 * n_dot_v * n_dot_l));
+        // Whatever is not reflected by the microfacets enters the surface
+        // and is scattered diffusely, unless the surface is a metal
+        let diffuse_weight = (Color::new(1, 1, 1) - fresnel) * (1.0 - self.metallic);
+        // Light intensities are scaled so that a white Lambertian surface
+        // facing the light reflects exactly the light's intensity. That is pi
+        // times the radiometric value, so the specular lobe is scaled to match
+        let incoming = light.intensity() * n_dot_l;
+        (
+            diffuse_weight * color * incoming,
+            specular_brdf * PI * incoming,
+        )
+    }
+
+    // The highlight of the light source on the clearcoat, a GGX lobe like
+    // that of microfacet shading but uncolored and with the coat's own
+    // refractive index and roughness
+    fn calc_clearcoat(&self, lightv: Tup, normalv: Tup, eyev: Tup, light: Light) -> Color {
+        // Like the base lobe, grazing light adds nothing to the coat
+        let n_dot_l = lightv.dot(&normalv);
+        if n_dot_l <= 0.0 {
+            return consts::BLACK;
+        }
+        let n_dot_v = normalv.dot(&eyev).max(1e-4);
+        let halfv = (lightv + eyev).normalize();
+        let n_dot_h = normalv.dot(&halfv).max(0.0);
+        let v_dot_h = eyev.dot(&halfv).max(0.0);
+        let f0 = microfacet::f0_from_ior(1.0, self.clearcoat_ior);
+        let fresnel = microfacet::fresnel_schlick(v_dot_h, Color::new(f0, f0, f0));
+        let d = microfacet::ggx_distribution(n_dot_h, self.clearcoat_roughness);
+        let g = microfacet::smith_geometry(n_dot_v, n_dot_l, self.clearcoat_roughness);
+        let specular_brdf = fresnel * (self.clearcoat * d * g / (4.0 * n_dot_v * n_dot_l));
+        specular_brdf * PI * light.intensity() * n_dot_l
+    }
+
+    // `light_factor` is the fraction of the light that reaches `position`
+    // in each channel. Black means fully in shadow, white fully lit
     pub fn lighting(
         &self,
-        object_transform: Mat4,
+        world_to_object: Mat4,
         light: Light,
         position: Tup,
         eyev: Tup,
         normalv: Tup,
-        in_shadow: bool,
+        light_factor: Color,
     ) -> Color {
-        let color = self
-            .pattern
-            .map(|p| p.color_at(object_transform, position))
-            .unwrap_or(self.color);
+        let ambient_light = light.intensity() * self.ambient();
+        self.shade(
+            world_to_object,
+            light,
+            position,
+            eyev,
+            normalv,
+            light_factor,
+            ambient_light,
+        )
+    }
+
+    // Like `lighting`, but the constant ambient term is replaced with light
+    // gathered from the environment. `irradiance` is the environment light
+    // arriving at the surface, which is reflected diffusely
+    #[allow(clippy::too_many_arguments)]
+    pub fn lighting_with_irradiance(
+        &self,
+        world_to_object: Mat4,
+        light: Light,
+        position: Tup,
+        eyev: Tup,
+        normalv: Tup,
+        light_factor: Color,
+        irradiance: Color,
+    ) -> Color {
+        let ambient_light = irradiance * self.diffuse_weight();
+        self.shade(
+            world_to_object,
+            light,
+            position,
+            eyev,
+            normalv,
+            light_factor,
+            ambient_light,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn shade(
+        &self,
+        world_to_object: Mat4,
+        light: Light,
+        position: Tup,
+        eyev: Tup,
+        normalv: Tup,
+        light_factor: Color,
+        ambient_light: Color,
+    ) -> Color {
+        let color = self.color_at(world_to_object, position);
         let effective_color = color * light.intensity();
         let lightv = (light.position() - position).normalize();
-        let ambient = effective_color * self.ambient();
+        let ambient = color * ambient_light;
         let light_dot_normal = lightv.dot(&normalv);
-        let (diffuse, specular) = if light_dot_normal < 0.0 || in_shadow {
+        let lit = light_dot_normal > 0.0 && light_factor != consts::BLACK;
+        let (diffuse, specular) = if !lit {
             (consts::BLACK, consts::BLACK)
         } else {
-            (
-                self.calc_diffuse(effective_color, light_dot_normal),
-                self.calc_specular(lightv, normalv, eyev, light),
-            )
+            match self.shading_model {
+                ShadingModel::Phong => (
+                    self.calc_diffuse(effective_color, light_dot_normal),
+                    self.calc_specular(lightv, normalv, eyev, light),
+                ),
+                ShadingModel::Microfacet => {
+                    self.calc_microfacet(color, lightv, normalv, eyev, light)
+                }
+            }
         };
-        ambient + diffuse + specular
+        // Subsurface materials reflect diffusely from wherever the light
+        // entered, not just from `position`. The world adds that in instead
+        let diffuse = if self.has_subsurface() {
+            consts::BLACK
+        } else {
+            diffuse
+        };
+        let base = ambient + (diffuse + specular) * light_factor;
+        if self.clearcoat == 0.0 {
+            return base;
+        }
+        let coat = if lit {
+            self.calc_clearcoat(lightv, normalv, eyev, light) * light_factor
+        } else {
+            consts::BLACK
+        };
+        base * (1.0 - self.clearcoat_reflectance(normalv.dot(&eyev))) + coat
     }
 }
 
@@ -155,13 +651,31 @@ impl Default for Material {
         Self {
             ambient: 0.1,
             color: Color::new(1, 1, 1),
+            spectrum: None,
+            wavelength: None,
             diffuse: 0.9,
             specular: 0.9,
             shininess: 200.0,
             reflective: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            abbe_number: None,
             pattern: None,
+            bump: None,
+            emissive: consts::BLACK,
+            emissive_strength: 1.0,
+            absorption: consts::BLACK,
+            absorption_density: 1.0,
+            medium: None,
+            casts_shadow: true,
+            scatter_color: consts::WHITE,
+            mean_free_path: 0.0,
+            shading_model: ShadingModel::Phong,
+            roughness: 0.0,
+            metallic: 0.0,
+            clearcoat: 0.0,
+            clearcoat_ior: 1.5,
+            clearcoat_roughness: 0.0,
         }
     }
 }
@@ -170,6 +684,7 @@ impl Default for Material {
 mod materials_test {
     use super::*;
     use crate::color::consts as color;
+    use crate::test_helpers::assert_nearly_eq;
 
     #[test]
     fn default_material_has_a_color() {
@@ -208,7 +723,14 @@ mod materials_test {
         let eyev = Tup::vector(0, 0, -1);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, false);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+        );
         let sum_of_lights = m.ambient() + m.diffuse() + m.specular();
         assert_eq!(
             Color::new(sum_of_lights, sum_of_lights, sum_of_lights),
@@ -223,7 +745,14 @@ mod materials_test {
         let eyev = Tup::vector(0.0, 2.0_f64.sqrt() / 2.0, -(2.0_f64.sqrt()) / 2.0);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, false);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+        );
         let sum_of_lights = m.ambient() + m.diffuse() + (0.0 * m.specular());
         assert_eq!(
             Color::new(sum_of_lights, sum_of_lights, sum_of_lights),
@@ -238,7 +767,14 @@ mod materials_test {
         let eyev = Tup::vector(0, 0, -1);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 10, -10), Color::new(1, 1, 1));
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, false);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+        );
         let sum_of_lights =
             m.ambient() + (2.0_f64.sqrt() / 2.0 * m.diffuse()) + (0.0 * m.specular());
         assert_eq!(
@@ -254,7 +790,14 @@ mod materials_test {
         let eyev = Tup::vector(0.0, -(2.0_f64.sqrt()) / 2.0, -(2.0_f64.sqrt()) / 2.0);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 10, -10), Color::new(1, 1, 1));
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, false);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+        );
         let sum_of_lights = m.ambient() + (2.0_f64.sqrt() / 2.0 * m.diffuse()) + m.specular();
         assert_eq!(
             Color::new(sum_of_lights, sum_of_lights, sum_of_lights),
@@ -269,7 +812,14 @@ mod materials_test {
         let eyev = Tup::vector(0, 0, 1);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 0, 10), Color::new(1, 1, 1));
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, false);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+        );
         let sum_of_lights = m.ambient() + (0.0 * m.diffuse()) + (0.0 * m.specular());
         assert_eq!(
             Color::new(sum_of_lights, sum_of_lights, sum_of_lights),
@@ -284,8 +834,15 @@ mod materials_test {
         let eyev = Tup::vector(0, 0, -1);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
-        let in_shadow = true;
-        let result = m.lighting(Mat4::default(), light, position, eyev, normalv, in_shadow);
+        let light_factor = color::BLACK;
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            light_factor,
+        );
         assert_eq!(Color::new(m.ambient(), m.ambient(), m.ambient()), result);
     }
 
@@ -299,14 +856,14 @@ mod materials_test {
         let eyev = Tup::vector(0, 0, -1);
         let normalv = Tup::vector(0, 0, -1);
         let light = Light::point_light(Tup::point(0, 0, -10), color::WHITE);
-        let in_shadow = false;
+        let light_factor = color::WHITE;
         let c1 = m.lighting(
             Mat4::default(),
             light,
             Tup::point(0.9, 0.0, 0.0),
             eyev,
             normalv,
-            in_shadow,
+            light_factor,
         );
         let c2 = m.lighting(
             Mat4::default(),
@@ -314,12 +871,76 @@ mod materials_test {
             Tup::point(1.1, 0.0, 0.0),
             eyev,
             normalv,
-            in_shadow,
+            light_factor,
         );
         assert_eq!(color::WHITE, c1);
         assert_eq!(color::BLACK, c2)
     }
 
+    #[test]
+    fn lighting_with_irradiance_replaces_the_ambient_term() {
+        let m = Material::default().with_color(Color::new(1.0, 0.5, 0.5));
+        let position = Tup::point(0, 0, 0);
+        let eyev = Tup::vector(0, 0, -1);
+        let normalv = Tup::vector(0, 0, -1);
+        let light = Light::point_light(Tup::point(0, 0, -10), color::BLACK);
+        let irradiance = Color::new(0.2, 0.4, 1.0);
+        let result = m.lighting_with_irradiance(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+            irradiance,
+        );
+        let expected = Color::new(0.2, 0.2, 0.5) * m.diffuse();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn microfacet_irradiance_is_weighted_by_how_much_is_not_metal() {
+        let m = Material::default()
+            .with_shading_model(ShadingModel::Microfacet)
+            .with_color(Color::new(1.0, 0.5, 0.5))
+            .with_diffuse(0.0)
+            .with_metallic(0.25);
+        let light = Light::point_light(Tup::point(0, 0, -10), color::BLACK);
+        let result = m.lighting_with_irradiance(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            Tup::vector(0, 0, -1),
+            Tup::vector(0, 0, -1),
+            color::WHITE,
+            Color::new(0.2, 0.4, 1.0),
+        );
+        assert_eq!(Color::new(0.2, 0.2, 0.5) * 0.75, result);
+    }
+
+    #[test]
+    fn lighting_with_irradiance_still_includes_direct_light() {
+        let m = Material::default();
+        let position = Tup::point(0, 0, 0);
+        let eyev = Tup::vector(0, 0, -1);
+        let normalv = Tup::vector(0, 0, -1);
+        let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
+        let result = m.lighting_with_irradiance(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            color::WHITE,
+            color::BLACK,
+        );
+        let sum_of_lights = m.diffuse() + m.specular();
+        assert_eq!(
+            Color::new(sum_of_lights, sum_of_lights, sum_of_lights),
+            result
+        );
+    }
+
     #[test]
     fn material_has_a_default_reflectivity() {
         let m = Material::default();
@@ -349,4 +970,421 @@ mod materials_test {
         let m = Material::default().with_refractive_index(1.8);
         assert_eq!(1.8, m.refractive_index());
     }
+
+    #[test]
+    fn material_has_no_default_emission() {
+        let m = Material::default();
+        assert_eq!(color::BLACK, m.emissive());
+        assert_eq!(1.0, m.emissive_strength());
+        assert_eq!(color::BLACK, m.emission());
+    }
+
+    #[test]
+    fn material_emission_is_scaled_by_its_strength() {
+        let m = Material::default()
+            .with_emissive(Color::new(1.0, 0.5, 0.0))
+            .with_emissive_strength(4.0);
+        assert_eq!(Color::new(1.0, 0.5, 0.0), m.emissive());
+        assert_eq!(Color::new(4.0, 2.0, 0.0), m.emission());
+    }
+
+    #[test]
+    fn material_uses_phong_shading_by_default() {
+        let m = Material::default();
+        assert_eq!(ShadingModel::Phong, m.shading_model());
+        assert_eq!(0.0, m.roughness());
+        assert_eq!(0.0, m.metallic());
+    }
+
+    fn microfacet_material() -> Material {
+        Material::default()
+            .with_shading_model(ShadingModel::Microfacet)
+            .with_ambient(0.0)
+    }
+
+    fn light_head_on(m: Material, eyev: Tup) -> Color {
+        let normalv = Tup::vector(0, 0, -1);
+        let light = Light::point_light(Tup::point(0, 0, -10), color::WHITE);
+        m.lighting(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            eyev,
+            normalv,
+            color::WHITE,
+        )
+    }
+
+    #[test]
+    fn a_microfacet_metal_only_reflects_its_own_color() {
+        let m = microfacet_material()
+            .with_color(color::RED)
+            .with_metallic(1.0)
+            .with_roughness(0.5);
+        let result = light_head_on(m, Tup::vector(0, 0, -1));
+        assert!(result.red() > 0.0);
+        assert_eq!(0.0, result.green());
+        assert_eq!(0.0, result.blue());
+    }
+
+    #[test]
+    fn a_microfacet_dielectric_has_an_untinted_highlight() {
+        let m = microfacet_material()
+            .with_color(color::RED)
+            .with_roughness(0.3);
+        let result = light_head_on(m, Tup::vector(0, 0, -1));
+        assert!(result.red() > 0.9);
+        assert!(result.green() > 0.0);
+        assert_eq!(result.green(), result.blue());
+    }
+
+    #[test]
+    fn a_matte_microfacet_dielectric_is_close_to_lambertian() {
+        let m = microfacet_material().with_roughness(1.0);
+        let eyev = Tup::vector(0.0, 2.0_f64.sqrt() / 2.0, -(2.0_f64.sqrt()) / 2.0);
+        let result = light_head_on(m, eyev);
+        assert!((result.red() - 1.0).abs() < 0.05, "{result:?}");
+    }
+
+    #[test]
+    fn a_smoother_microfacet_surface_has_a_brighter_highlight() {
+        let eyev = Tup::vector(0, 0, -1);
+        let smooth = light_head_on(microfacet_material().with_roughness(0.2), eyev);
+        let rough = light_head_on(microfacet_material().with_roughness(0.6), eyev);
+        assert!(smooth.red() > rough.red());
+    }
+
+    #[test]
+    fn a_smoother_microfacet_surface_has_a_tighter_highlight() {
+        let eyev = Tup::vector(0.0, 0.5, -(3.0_f64.sqrt()) / 2.0);
+        let smooth = light_head_on(microfacet_material().with_roughness(0.2), eyev);
+        let rough = light_head_on(microfacet_material().with_roughness(0.6), eyev);
+        assert!(smooth.red() < rough.red());
+    }
+
+    #[test]
+    fn a_microfacet_dielectric_reflects_as_its_refractive_index_dictates() {
+        let eyev = Tup::vector(0, 0, -1);
+        let m = microfacet_material().with_color(color::BLACK);
+        let glass = light_head_on(m.clone().with_refractive_index(1.5), eyev);
+        let diamond = light_head_on(m.with_refractive_index(2.4), eyev);
+        assert!(glass.red() > 0.0);
+        assert!(diamond.red() > glass.red());
+    }
+
+    #[test]
+    fn a_default_microfacet_dielectric_has_a_highlight() {
+        let m = microfacet_material().with_color(color::BLACK);
+        let result = light_head_on(m, Tup::vector(0, 0, -1));
+        assert!(result.red() > 0.0);
+        assert_nearly_eq(result.red(), result.blue());
+    }
+
+    #[test]
+    fn a_microfacet_surface_lit_edge_on_is_black() {
+        let m = microfacet_material().with_roughness(0.5);
+        let light = Light::point_light(Tup::point(-10, 0, 0), color::WHITE);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            Tup::vector(0, 0, -1),
+            Tup::vector(0, 0, -1),
+            color::WHITE,
+        );
+        assert_eq!(color::BLACK, result);
+    }
+
+    #[test]
+    fn a_microfacet_surface_in_shadow_only_has_ambient_light() {
+        let m = Material::default()
+            .with_shading_model(ShadingModel::Microfacet)
+            .with_roughness(0.4);
+        let normalv = Tup::vector(0, 0, -1);
+        let light = Light::point_light(Tup::point(0, 0, -10), color::WHITE);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            Tup::vector(0, 0, -1),
+            normalv,
+            color::BLACK,
+        );
+        assert_eq!(Color::new(m.ambient(), m.ambient(), m.ambient()), result);
+    }
+
+    #[test]
+    fn materials_have_no_clearcoat_by_default() {
+        let m = Material::default();
+        assert_eq!(0.0, m.clearcoat());
+        assert_eq!(1.5, m.clearcoat_ior());
+        assert_eq!(0.0, m.clearcoat_roughness());
+        assert_eq!(0.0, m.clearcoat_reflectance(0.0));
+    }
+
+    #[test]
+    fn a_clearcoat_reflects_more_at_grazing_angles() {
+        let m = Material::default().with_clearcoat(1.0);
+        assert_nearly_eq(0.04, m.clearcoat_reflectance(1.0));
+        assert_nearly_eq(1.0, m.clearcoat_reflectance(0.0));
+        let half = m.clone().with_clearcoat(0.5);
+        assert_nearly_eq(0.02, half.clearcoat_reflectance(1.0));
+        let denser = m.clone().with_clearcoat_ior(2.0);
+        assert!(denser.clearcoat_reflectance(1.0) > m.clearcoat_reflectance(1.0));
+    }
+
+    #[test]
+    fn a_clearcoat_adds_an_untinted_highlight_to_a_metal() {
+        let metal = microfacet_material()
+            .with_color(color::RED)
+            .with_metallic(1.0)
+            .with_roughness(0.5);
+        let coated = metal
+            .clone()
+            .with_clearcoat(1.0)
+            .with_clearcoat_roughness(0.1);
+        let eyev = Tup::vector(0, 0, -1);
+        assert_eq!(0.0, light_head_on(metal, eyev).green());
+        let result = light_head_on(coated, eyev);
+        assert!(result.green() > 0.0);
+        assert_nearly_eq(result.green(), result.blue());
+    }
+
+    #[test]
+    fn a_clearcoat_dims_the_base_away_from_its_highlight() {
+        let base = microfacet_material().with_roughness(1.0);
+        let coated = base
+            .clone()
+            .with_clearcoat(1.0)
+            .with_clearcoat_roughness(0.05);
+        let eyev = Tup::vector(0.0, 0.5, -(3.0_f64.sqrt()) / 2.0);
+        let uncoated = light_head_on(base, eyev);
+        let result = light_head_on(coated.clone(), eyev);
+        let coat = coated.clearcoat_reflectance(3.0_f64.sqrt() / 2.0);
+        assert!(result.red() < uncoated.red());
+        assert!((result.red() - uncoated.red() * (1.0 - coat)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn a_clearcoat_lit_edge_on_has_no_highlight() {
+        let coated = microfacet_material()
+            .with_color(color::BLACK)
+            .with_clearcoat(1.0)
+            .with_clearcoat_roughness(0.1);
+        let light = Light::point_light(Tup::point(-10, 0, 0), color::WHITE);
+        let result = coated.lighting(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            Tup::vector(0, 0, -1),
+            Tup::vector(0, 0, -1),
+            color::WHITE,
+        );
+        assert_eq!(color::BLACK, result);
+    }
+
+    #[test]
+    fn a_rougher_clearcoat_spreads_its_highlight() {
+        let base = microfacet_material().with_color(color::BLACK);
+        let eyev = Tup::vector(0.0, 0.5, -(3.0_f64.sqrt()) / 2.0);
+        let smooth = base.with_clearcoat(1.0).with_clearcoat_roughness(0.1);
+        let rough = smooth.clone().with_clearcoat_roughness(0.6);
+        assert!(
+            light_head_on(rough.clone(), eyev).red() > light_head_on(smooth.clone(), eyev).red()
+        );
+        let eyev = Tup::vector(0, 0, -1);
+        assert!(light_head_on(rough, eyev).red() < light_head_on(smooth, eyev).red());
+    }
+
+    #[test]
+    fn material_absorbs_nothing_by_default() {
+        let m = Material::default();
+        assert_eq!(color::BLACK, m.absorption());
+        assert_eq!(1.0, m.absorption_density());
+        assert_eq!(color::WHITE, m.transmittance(100.0));
+    }
+
+    #[test]
+    fn transmittance_falls_off_exponentially_with_distance() {
+        let m = Material::default().with_absorption(Color::new(0.0, 1.0, 2.0));
+        let e = std::f64::consts::E;
+        assert_eq!(
+            Color::new(1.0, 1.0 / e, 1.0 / (e * e)),
+            m.transmittance(1.0)
+        );
+        assert_eq!(
+            Color::new(1.0, 1.0 / (e * e), 1.0 / e.powi(4)),
+            m.transmittance(2.0)
+        );
+    }
+
+    #[test]
+    fn absorption_density_scales_the_absorption() {
+        let m = Material::default()
+            .with_absorption(Color::new(0.0, 1.0, 2.0))
+            .with_absorption_density(0.5);
+        assert_eq!(
+            Material::default()
+                .with_absorption(Color::new(0.0, 1.0, 2.0))
+                .transmittance(1.0),
+            m.transmittance(2.0)
+        );
+    }
+
+    #[test]
+    fn materials_cast_shadows_by_default() {
+        assert!(Material::default().casts_shadow());
+        assert!(!Material::default().with_casts_shadow(false).casts_shadow());
+    }
+
+    #[test]
+    fn lighting_with_partially_blocked_light() {
+        let m = Material::default();
+        let position = Tup::point(0, 0, 0);
+        let eyev = Tup::vector(0, 0, -1);
+        let normalv = Tup::vector(0, 0, -1);
+        let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
+        let light_factor = Color::new(1.0, 0.5, 0.0);
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            position,
+            eyev,
+            normalv,
+            light_factor,
+        );
+        let lit = m.diffuse() + m.specular();
+        assert_eq!(
+            Color::new(m.ambient() + lit, m.ambient() + lit * 0.5, m.ambient()),
+            result
+        );
+    }
+
+    #[test]
+    fn a_material_without_an_abbe_number_does_not_disperse() {
+        let m = Material::default().with_refractive_index(1.5);
+        assert_eq!(None, m.abbe_number());
+        assert_eq!(1.5, m.refractive_index_at(450.0));
+        assert_eq!(1.5, m.refractive_index_at(650.0));
+    }
+
+    #[test]
+    fn the_refractive_index_is_exact_at_the_d_line() {
+        let m = Material::default()
+            .with_refractive_index(1.5168)
+            .with_abbe_number(64.17);
+        assert_nearly_eq(1.5168, m.refractive_index_at(587.6));
+    }
+
+    #[test]
+    fn the_abbe_number_fixes_the_spread_between_blue_and_red() {
+        let m = Material::default()
+            .with_refractive_index(1.62)
+            .with_abbe_number(36.0);
+        let spread = m.refractive_index_at(486.1) - m.refractive_index_at(656.3);
+        assert_nearly_eq((1.62 - 1.0) / 36.0, spread);
+        assert!(m.refractive_index_at(450.0) > m.refractive_index_at(650.0));
+    }
+
+    #[test]
+    fn a_material_seen_at_one_wavelength_is_gray() {
+        let m = Material::default()
+            .with_color(Color::new(1.0, 0.5, 0.0))
+            .with_emissive(Color::new(0.0, 0.0, 2.0));
+        let red = m.at_wavelength(650.0);
+        assert_eq!(color::WHITE, red.color());
+        assert_eq!(color::BLACK, red.emissive());
+        let blue = m.at_wavelength(430.0);
+        assert_eq!(color::BLACK, blue.color());
+        assert_eq!(Color::new(2, 2, 2), blue.emissive());
+    }
+
+    #[test]
+    fn patterns_are_gray_at_one_wavelength() {
+        let m = Material::default()
+            .with_pattern(Pattern::stripe_pattern(color::RED, color::BLUE))
+            .at_wavelength(650.0);
+        let c1 = m.color_at(Mat4::default(), Tup::point(0.5, 0.0, 0.0));
+        let c2 = m.color_at(Mat4::default(), Tup::point(1.5, 0.0, 0.0));
+        assert_eq!(color::WHITE, c1);
+        assert_eq!(color::BLACK, c2);
+    }
+
+    #[test]
+    fn an_explicit_spectrum_sets_the_color() {
+        let s = Spectrum::from_samples(380.0, 730.0, &[0.0, 1.0]);
+        let m = Material::default().with_spectrum(s);
+        assert_eq!(Some(s), m.spectrum());
+        assert_eq!(s.to_color(), m.color());
+        let at_500 = s.value_at(500.0);
+        assert_eq!(
+            Color::new(at_500, at_500, at_500),
+            m.at_wavelength(500.0).color()
+        );
+    }
+
+    #[test]
+    fn setting_a_color_replaces_the_spectrum() {
+        let m = Material::default()
+            .with_spectrum(Spectrum::constant(0.5))
+            .with_color(color::RED);
+        assert_eq!(None, m.spectrum());
+        assert_eq!(color::RED, m.color());
+    }
+
+    #[test]
+    fn materials_are_empty_inside_by_default() {
+        assert_eq!(None, Material::default().medium());
+        let smoke = Medium::fog(0.3);
+        assert_eq!(
+            Some(smoke.clone()),
+            Material::default().with_medium(smoke).medium()
+        );
+    }
+
+    #[test]
+    fn materials_have_no_subsurface_scattering_by_default() {
+        let m = Material::default();
+        assert!(!m.has_subsurface());
+        assert_eq!(color::WHITE, m.scatter_color());
+        let wax = m
+            .with_mean_free_path(0.2)
+            .with_scatter_color(Color::new(0.9, 0.8, 0.6));
+        assert!(wax.has_subsurface());
+        assert_eq!(0.2, wax.mean_free_path());
+        assert_eq!(Color::new(0.9, 0.8, 0.6), wax.scatter_color());
+    }
+
+    #[test]
+    fn lighting_leaves_out_diffuse_reflection_under_subsurface_scattering() {
+        let m = Material::default().with_mean_free_path(0.1);
+        let light = Light::point_light(Tup::point(0, 0, -10), Color::new(1, 1, 1));
+        let result = m.lighting(
+            Mat4::default(),
+            light,
+            Tup::point(0, 0, 0),
+            Tup::vector(0, 0, -1),
+            Tup::vector(0, 0, -1),
+            color::WHITE,
+        );
+        let ambient_and_specular = m.ambient() + m.specular();
+        assert_eq!(
+            Color::new(
+                ambient_and_specular,
+                ambient_and_specular,
+                ambient_and_specular
+            ),
+            result
+        );
+    }
+
+    #[test]
+    fn materials_are_smooth_by_default() {
+        assert_eq!(None, Material::default().bump());
+        let bump = Bump::noise(2.0, 3, 0.1);
+        assert_eq!(
+            Some(bump.clone()),
+            Material::default().with_bump(bump).bump()
+        );
+    }
 }
@@ -54,7 +54,7 @@ impl Mat4 {
     }
 
     fn cofactor(&self, row: usize, col: usize) -> f64 {
-        let factor = if (row + col) % 2 == 0 { 1 } else { -1 };
+        let factor = if (row + col).is_multiple_of(2) { 1 } else { -1 };
         factor as f64 * self.minor(row, col)
     }
 
@@ -179,7 +179,7 @@ impl Mat3 {
     }
 
     fn cofactor(&self, row: usize, col: usize) -> f64 {
-        let factor = if (row + col) % 2 == 0 { 1 } else { -1 };
+        let factor = if (row + col).is_multiple_of(2) { 1 } else { -1 };
         factor as f64 * self.minor(row, col)
     }
 
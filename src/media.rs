@@ -0,0 +1,172 @@
+use crate::color::consts;
+use crate::color::Color;
+use crate::spectrum;
+use crate::tup::Tup;
+use crate::volumes::Density;
+
+// A participating medium such as fog, haze, smoke or cloud. Light
+// travelling through it is absorbed and scattered out of its path at rates
+// set per unit of distance for each color channel, and light from the light
+// source is scattered into the path along the way. The rates are scaled by
+// the density, which may vary through space
+#[derive(PartialEq, Clone, Debug)]
+pub struct Medium {
+    absorption: Color,
+    scattering: Color,
+    density: Density,
+}
+
+impl Medium {
+    // Light scattered by a medium goes equally in every direction, so a
+    // fraction 1 / 4pi of it per unit of solid angle heads towards the eye.
+    // Light intensities are pi times their radiometric value, see
+    // `Material::lighting`, which leaves a quarter
+    pub const PHASE: f64 = 0.25;
+
+    // Beyond this much transmittance a medium is treated as opaque
+    const MIN_TRANSMITTANCE: f64 = 1e-3;
+
+    pub fn new(absorption: Color, scattering: Color) -> Self {
+        Self {
+            absorption,
+            scattering,
+            density: Density::Uniform,
+        }
+    }
+
+    pub fn with_density(self, density: Density) -> Self {
+        Self { density, ..self }
+    }
+
+    // Gray fog that scatters all colors alike and absorbs nothing. The
+    // density is the fraction of light scattered per unit of distance
+    pub fn fog(density: f64) -> Self {
+        Self::new(consts::BLACK, Color::new(density, density, density))
+    }
+
+    pub fn absorption(&self) -> Color {
+        self.absorption
+    }
+
+    pub fn scattering(&self) -> Color {
+        self.scattering
+    }
+
+    pub fn density(&self) -> Density {
+        self.density.clone()
+    }
+
+    pub fn is_homogeneous(&self) -> bool {
+        self.density.is_uniform()
+    }
+
+    // The density at a point in the space of the object the medium fills
+    pub fn density_at(&self, point: Tup) -> f64 {
+        self.density.at(point)
+    }
+
+    // The rate at which light is lost from a ray, by being either absorbed
+    // or scattered elsewhere, where the density is one
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    // The fraction of light that gets through `distance` of the medium where
+    // the density is one
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let optical_depth = self.extinction() * distance;
+        Color::new(
+            (-optical_depth.red()).exp(),
+            (-optical_depth.green()).exp(),
+            (-optical_depth.blue()).exp(),
+        )
+    }
+
+    // The distance after which hardly any light makes it through in any
+    // channel where the density is one. Infinite for media that don't dim
+    // light at all
+    pub fn extent(&self) -> f64 {
+        let extinction = self.extinction();
+        let slowest = extinction
+            .red()
+            .min(extinction.green())
+            .min(extinction.blue());
+        if slowest > 0.0 {
+            -Self::MIN_TRANSMITTANCE.ln() / slowest
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    // The medium as seen by light of a single wavelength, if any
+    pub fn at_wavelength(&self, wavelength: Option<f64>) -> Self {
+        Self {
+            absorption: spectrum::monochrome(self.absorption, wavelength),
+            scattering: spectrum::monochrome(self.scattering, wavelength),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod media_test {
+    use super::*;
+    use crate::color::consts as color;
+    use crate::test_helpers::assert_nearly_eq;
+
+    #[test]
+    fn extinction_combines_absorption_and_scattering() {
+        let m = Medium::new(Color::new(0.1, 0.2, 0.3), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(Color::new(0.6, 0.7, 0.3), m.extinction());
+    }
+
+    #[test]
+    fn fog_scatters_without_absorbing() {
+        let m = Medium::fog(0.2);
+        assert_eq!(color::BLACK, m.absorption());
+        assert_eq!(Color::new(0.2, 0.2, 0.2), m.scattering());
+    }
+
+    #[test]
+    fn transmittance_falls_off_exponentially() {
+        let m = Medium::new(Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.5, 0.0));
+        let e = std::f64::consts::E;
+        assert_eq!(
+            Color::new(1.0 / (e * e), 1.0 / e, 1.0),
+            m.transmittance(2.0)
+        );
+    }
+
+    #[test]
+    fn hardly_any_light_gets_past_the_extent() {
+        let m = Medium::fog(0.5);
+        let remaining = m.transmittance(m.extent());
+        assert_nearly_eq(1e-3, remaining.red());
+    }
+
+    #[test]
+    fn a_medium_that_doesnt_dim_light_goes_on_forever() {
+        let m = Medium::new(color::BLACK, color::BLACK);
+        assert_eq!(f64::INFINITY, m.extent());
+    }
+
+    #[test]
+    fn a_medium_at_one_wavelength_is_gray() {
+        let m = Medium::new(color::BLACK, Color::new(0.0, 0.0, 0.4)).at_wavelength(Some(430.0));
+        assert_eq!(Color::new(0.4, 0.4, 0.4), m.scattering());
+    }
+
+    #[test]
+    fn a_medium_is_homogeneous_by_default() {
+        let m = Medium::fog(0.1);
+        assert!(m.is_homogeneous());
+        assert_eq!(1.0, m.density_at(Tup::point(3, 2, 1)));
+    }
+
+    #[test]
+    fn a_medium_can_vary_in_density() {
+        let m = Medium::fog(0.1).with_density(Density::noise(1.0, 3, 0.0));
+        assert!(!m.is_homogeneous());
+        assert_eq!(Density::noise(1.0, 3, 0.0), m.density());
+    }
+}
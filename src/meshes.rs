@@ -0,0 +1,604 @@
+use crate::bounds::Bounds;
+use crate::bvh::Bvh;
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::triangles::{self, SmoothTriangle, Triangle};
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MeshError {
+    IndexOutOfRange(usize),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::IndexOutOfRange(index) => write!(f, "there is no vertex {index}"),
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+// Triangles sharing a list of vertices, as read from a model file. Each
+// face holds the indices of its three corners. Normals and texture
+// coordinates, when the file has them, are given for every vertex
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct MeshData {
+    positions: Vec<Tup>,
+    normals: Vec<Tup>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    pub fn new(positions: Vec<Tup>, faces: Vec<[usize; 3]>) -> Result<Self, MeshError> {
+        if let Some(&index) = faces.iter().flatten().find(|&&i| i >= positions.len()) {
+            return Err(MeshError::IndexOutOfRange(index));
+        }
+        Ok(Self {
+            positions,
+            faces,
+            ..Self::default()
+        })
+    }
+
+    pub fn with_normals(self, normals: Vec<Tup>) -> Self {
+        Self { normals, ..self }
+    }
+
+    pub fn with_uvs(self, uvs: Vec<(f64, f64)>) -> Self {
+        Self { uvs, ..self }
+    }
+
+    pub fn positions(&self) -> &[Tup] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Tup] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.uvs
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn has_normals(&self) -> bool {
+        !self.normals.is_empty() && self.normals.len() == self.positions.len()
+    }
+
+    pub fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty() && self.uvs.len() == self.positions.len()
+    }
+
+    fn corners(&self, face: usize) -> (Tup, Tup, Tup) {
+        let [a, b, c] = self.faces[face];
+        (self.positions[a], self.positions[b], self.positions[c])
+    }
+
+    // A separate shape for every face, smooth if the mesh has normals.
+    // Faces whose corners lie in a line, which scanners and CAD exports
+    // often leave behind, have no area and are left out
+    pub fn triangles(&self, material: Material) -> Vec<Box<dyn Shape>> {
+        self.faces
+            .iter()
+            .filter(|&&[a, b, c]| {
+                let (p1, p2, p3) = (self.positions[a], self.positions[b], self.positions[c]);
+                (p2 - p1).cross(&(p3 - p1)).magnitude() > 0.0
+            })
+            .map(|&[a, b, c]| -> Box<dyn Shape> {
+                let (p1, p2, p3) = (self.positions[a], self.positions[b], self.positions[c]);
+                if self.has_normals() {
+                    let (n1, n2, n3) = (self.normals[a], self.normals[b], self.normals[c]);
+                    Box::new(
+                        SmoothTriangle::new(p1, p2, p3, n1, n2, n3).with_material(material.clone()),
+                    )
+                } else {
+                    Box::new(Triangle::new(p1, p2, p3).with_material(material.clone()))
+                }
+            })
+            .collect()
+    }
+}
+
+// The buffers of a mesh with the hierarchy of boxes over its faces
+struct MeshBuffers {
+    data: MeshData,
+    bvh: Bvh,
+}
+
+// Meshes can have millions of faces, far too many to print
+impl fmt::Debug for MeshBuffers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MeshBuffers {{ vertices: {}, faces: {}, at: {:p} }}",
+            self.data.positions.len(),
+            self.data.faces.len(),
+            self
+        )
+    }
+}
+
+// Where a ray hit a mesh, carried by the copy of the mesh in the
+// intersection so the face needn't be searched for again
+#[derive(Copy, Clone)]
+struct FaceHit {
+    point: Tup,
+    face: usize,
+    u: f64,
+    v: f64,
+}
+
+// A whole triangle mesh as a single shape, with one transform and material.
+// The faces are found through a bounding volume hierarchy, so even large
+// meshes are quick to intersect. Copies of a mesh share its buffers
+#[derive(Clone)]
+pub struct Mesh {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    buffers: Arc<MeshBuffers>,
+    hit: Option<FaceHit>,
+}
+
+impl Mesh {
+    pub fn new(data: MeshData) -> Self {
+        let bounds: Vec<Bounds> = (0..data.faces.len())
+            .map(|face| {
+                let (p1, p2, p3) = data.corners(face);
+                Bounds::around([p1, p2, p3])
+            })
+            .collect();
+        let buffers = MeshBuffers {
+            bvh: Bvh::new(&bounds),
+            data,
+        };
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            buffers: Arc::new(buffers),
+            hit: None,
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.buffers.data
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.buffers.bvh.bounds()
+    }
+
+    fn hit_face(&self, ray: &Ray, face: usize) -> Option<(f64, f64, f64)> {
+        let (p1, p2, p3) = self.buffers.data.corners(face);
+        triangles::barycentric_hit(ray, p1, p2 - p1, p3 - p1)
+    }
+
+    // Where a hit wasn't recorded, the face the point lies closest to.
+    // `None` if the mesh has no faces with any area
+    fn locate(&self, point: Tup) -> Option<FaceHit> {
+        let data = &self.buffers.data;
+        let mut best = None;
+        let mut best_distance = f64::INFINITY;
+        for face in 0..data.faces.len() {
+            let (p1, p2, p3) = data.corners(face);
+            let (e1, e2) = (p2 - p1, p3 - p1);
+            let normal = e2.cross(&e1);
+            if normal.magnitude() == 0.0 {
+                continue;
+            }
+            let normal = normal * (1.0 / normal.magnitude());
+            let to_point = point - p1;
+            let distance = to_point.dot(&normal).abs();
+            let (d11, d12, d22) = (e1.dot(&e1), e1.dot(&e2), e2.dot(&e2));
+            let (dp1, dp2) = (to_point.dot(&e1), to_point.dot(&e2));
+            let denominator = d11 * d22 - d12 * d12;
+            let u = (d22 * dp1 - d12 * dp2) / denominator;
+            let v = (d11 * dp2 - d12 * dp1) / denominator;
+            let outside = (-u).max(-v).max(u + v - 1.0).max(0.0);
+            // Points beyond the edges count as further away
+            let distance = distance + outside * (d11 + d22).sqrt();
+            if distance < best_distance {
+                best_distance = distance;
+                best = Some(FaceHit { point, face, u, v });
+            }
+        }
+        best
+    }
+
+    fn hit_at(&self, point: Tup) -> Option<FaceHit> {
+        match self.hit {
+            Some(hit) if (hit.point - point).magnitude() < 1e-4 => Some(hit),
+            _ => self.locate(point),
+        }
+    }
+}
+
+// Meshes are told apart by their id alone. The copies carried by
+// intersections remember which face was hit, but are still the same mesh
+impl fmt::Debug for Mesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mesh")
+            .field("id", &self.id)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .field("buffers", &self.buffers)
+            .finish()
+    }
+}
+
+impl PartialEq for Mesh {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Shape for Mesh {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let mut hits = Vec::new();
+        self.buffers.bvh.candidates(&local_ray, |face| {
+            if let Some((t, u, v)) = self.hit_face(&local_ray, face) {
+                hits.push((t, face, u, v));
+            }
+        });
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let xs: Vec<Intersection> = hits
+            .into_iter()
+            .map(|(t, face, u, v)| {
+                let point = local_ray.position(t);
+                let mesh = Self {
+                    hit: Some(FaceHit { point, face, u, v }),
+                    ..self.clone()
+                };
+                Intersection::new(t, mesh).with_face(face)
+            })
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    // The vertex normals blended across the face, or the face's own normal
+    // if the mesh has none
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let Some(FaceHit { face, u, v, .. }) = self.hit_at(point) else {
+            return Tup::vector(0, 1, 0);
+        };
+        let data = &self.buffers.data;
+        let normal = if data.has_normals() {
+            let [a, b, c] = data.faces[face];
+            data.normals[a] * (1.0 - u - v) + data.normals[b] * u + data.normals[c] * v
+        } else {
+            let (p1, p2, p3) = data.corners(face);
+            (p3 - p1).cross(&(p2 - p1))
+        };
+        normal * (1.0 / normal.magnitude())
+    }
+
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        let data = &self.buffers.data;
+        if !data.has_uvs() {
+            return (0.0, 0.0);
+        }
+        let Some(FaceHit { face, u, v, .. }) = self.hit_at(point) else {
+            return (0.0, 0.0);
+        };
+        let [a, b, c] = data.faces[face];
+        let w = 1.0 - u - v;
+        (
+            data.uvs[a].0 * w + data.uvs[b].0 * u + data.uvs[c].0 * v,
+            data.uvs[a].1 * w + data.uvs[b].1 * u + data.uvs[c].1 * v,
+        )
+    }
+
+    // The direction in which u grows and v stays put, so normal maps line
+    // up with the texture. Along the face's first edge if the mesh has no
+    // texture coordinates, or they don't spread over the face
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        let Some(FaceHit { face, .. }) = self.hit_at(point) else {
+            return Tup::vector(1, 0, 0);
+        };
+        let data = &self.buffers.data;
+        let (p1, p2, p3) = data.corners(face);
+        let (e1, e2) = (p2 - p1, p3 - p1);
+        let [a, b, c] = data.faces[face];
+        let tangent = if data.has_uvs() {
+            let (du1, dv1) = (data.uvs[b].0 - data.uvs[a].0, data.uvs[b].1 - data.uvs[a].1);
+            let (du2, dv2) = (data.uvs[c].0 - data.uvs[a].0, data.uvs[c].1 - data.uvs[a].1);
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant != 0.0 {
+                (e1 * dv2 - e2 * dv1) * determinant.signum()
+            } else {
+                e1
+            }
+        } else {
+            e1
+        };
+        tangent * (1.0 / tangent.magnitude())
+    }
+}
+
+#[cfg(test)]
+mod meshes_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+
+    // A cube from -1 to 1 with every face wound to face outwards
+    fn cube() -> MeshData {
+        let positions = (0..8)
+            .map(|i| {
+                let coordinate = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+                Tup::point(coordinate(1), coordinate(2), coordinate(4))
+            })
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let faces = quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| [[a, c, b], [a, d, c]])
+            .collect();
+        MeshData::new(positions, faces).expect("Valid mesh")
+    }
+
+    fn square() -> MeshData {
+        MeshData::new(
+            vec![
+                Tup::point(-1, 0, -1),
+                Tup::point(1, 0, -1),
+                Tup::point(1, 0, 1),
+                Tup::point(-1, 0, 1),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+        .expect("Valid mesh")
+    }
+
+    #[test]
+    fn each_face_becomes_a_triangle() {
+        let m = Material::default().with_ambient(0.5);
+        let triangles = square().triangles(m.clone());
+        assert_eq!(2, triangles.len());
+        assert_eq!(m, triangles[1].material());
+        assert_eq!(
+            Tup::vector(0, 1, 0),
+            triangles[0].normal_at(Tup::point(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn vertex_normals_make_smooth_triangles() {
+        let normals = vec![
+            Tup::vector(-1, 1, 0).normalize(),
+            Tup::vector(1, 1, 0).normalize(),
+            Tup::vector(1, 1, 0).normalize(),
+            Tup::vector(-1, 1, 0).normalize(),
+        ];
+        let mesh = square().with_normals(normals);
+        assert!(mesh.has_normals());
+        let triangles = mesh.triangles(Material::default());
+        assert_eq!(
+            Tup::vector(0, 1, 0),
+            triangles[0].normal_at(Tup::point(0.0, 0.0, -1.0))
+        );
+    }
+
+    #[test]
+    fn faces_without_area_are_left_out() {
+        let mut faces = square().faces().to_vec();
+        faces.push([0, 1, 1]);
+        let mesh = MeshData::new(square().positions().to_vec(), faces).expect("Valid mesh");
+        assert_eq!(2, mesh.triangles(Material::default()).len());
+    }
+
+    #[test]
+    fn a_ray_through_a_mesh_reports_the_faces_it_hits() {
+        let mesh = Mesh::new(cube());
+        let ray = Ray::new(Tup::point(0.2, 0.3, -5.0), Tup::vector(0, 0, 1));
+        let xs = mesh.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(4.0, xs[0].t());
+        assert_nearly_eq(6.0, xs[1].t());
+        let front = xs[0].face().expect("Hits a face");
+        assert!(cube().faces()[front]
+            .iter()
+            .all(|&i| cube().positions()[i].z == -1.0));
+        assert_eq!(
+            Tup::vector(0, 0, -1),
+            xs[0].object().normal_at(ray.position(xs[0].t()))
+        );
+        assert_eq!(
+            Tup::vector(0, 0, 1),
+            xs[1].object().normal_at(ray.position(xs[1].t()))
+        );
+    }
+
+    #[test]
+    fn a_ray_can_miss_a_mesh() {
+        let mesh = Mesh::new(cube());
+        let ray = Ray::new(Tup::point(2.0, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert!(mesh.intersect(&ray).is_empty());
+    }
+
+    #[test]
+    fn the_whole_mesh_is_moved_by_its_transform() {
+        let mesh = Mesh::new(cube()).with_transform(transforms::translation(0.0, 0.0, 10.0));
+        let ray = Ray::new(Tup::point(0.2, 0.3, -5.0), Tup::vector(0, 0, 1));
+        let xs = mesh.intersect(&ray);
+        assert_nearly_eq(14.0, xs[0].t());
+    }
+
+    #[test]
+    fn vertex_normals_and_texture_coordinates_are_blended_across_a_face() {
+        let normals = vec![
+            Tup::vector(-1, 1, 0).normalize(),
+            Tup::vector(1, 1, 0).normalize(),
+            Tup::vector(1, 1, 0).normalize(),
+            Tup::vector(-1, 1, 0).normalize(),
+        ];
+        let uvs = vec![(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+        let mesh = Mesh::new(square().with_normals(normals).with_uvs(uvs));
+        let ray = Ray::new(Tup::point(0.0, 5.0, -0.5), Tup::vector(0, -1, 0));
+        let xs = mesh.intersect(&ray);
+        let point = ray.position(xs[0].t());
+        assert_eq!(Tup::vector(0, 1, 0), xs[0].object().normal_at(point));
+        let (u, v) = xs[0].object().uv_at(point);
+        assert_nearly_eq(0.5, u);
+        assert_nearly_eq(0.75, v);
+        // Without a recorded hit the face is searched for
+        assert_eq!(Tup::vector(0, 1, 0), mesh.normal_at(point));
+        assert_eq!((u, v), mesh.uv_at(point));
+    }
+
+    #[test]
+    fn the_tangent_follows_the_texture_coordinates() {
+        // u grows along z, across the first edge of each face
+        let uvs = vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)];
+        let mesh = Mesh::new(square().with_uvs(uvs));
+        for point in [Tup::point(0.5, 0.0, -0.2), Tup::point(-0.5, 0.0, 0.2)] {
+            assert_eq!(Tup::vector(0, 0, 1), mesh.tangent_at(point));
+        }
+        let untextured = Mesh::new(square());
+        let point = Tup::point(0.5, 0.0, -0.2);
+        assert_eq!(Tup::vector(1, 0, 0), untextured.tangent_at(point));
+    }
+
+    #[test]
+    fn faces_must_use_vertices_the_mesh_has() {
+        assert_eq!(
+            Some(MeshError::IndexOutOfRange(4)),
+            MeshData::new(square().positions().to_vec(), vec![[0, 1, 4]]).err()
+        );
+    }
+
+    #[test]
+    fn a_mesh_without_faces_has_default_normals() {
+        let mesh = Mesh::new(MeshData::new(Vec::new(), Vec::new()).expect("Valid mesh"));
+        let point = Tup::point(0, 0, 0);
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert!(mesh.intersect(&ray).is_empty());
+        assert_eq!(Tup::vector(0, 1, 0), mesh.normal_at(point));
+        assert_eq!((0.0, 0.0), mesh.uv_at(point));
+        assert_eq!(Tup::vector(1, 0, 0), mesh.tangent_at(point));
+    }
+
+    #[test]
+    fn a_large_mesh_finds_the_same_hits_as_separate_triangles() {
+        let n = 40;
+        let positions = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let (x, z) = (
+                    i as f64 / n as f64 * 2.0 - 1.0,
+                    j as f64 / n as f64 * 2.0 - 1.0,
+                );
+                Tup::point(x, 0.3 * (3.0 * x).sin() * (2.0 * z).cos(), z)
+            })
+            .collect();
+        let faces = (0..n)
+            .flat_map(|j| (0..n).map(move |i| (i, j)))
+            .flat_map(|(i, j)| {
+                let corner = |i: usize, j: usize| j * (n + 1) + i;
+                [
+                    [corner(i, j), corner(i + 1, j), corner(i + 1, j + 1)],
+                    [corner(i, j), corner(i + 1, j + 1), corner(i, j + 1)],
+                ]
+            })
+            .collect();
+        let data = MeshData::new(positions, faces).expect("Valid mesh");
+        let mesh = Mesh::new(data.clone());
+        let triangles = data.triangles(Material::default());
+        for k in 0..25 {
+            let origin = Tup::point(-2.0 + 0.13 * k as f64, 1.0, -1.5 + 0.05 * k as f64);
+            let ray = Ray::new(origin, Tup::vector(1.0, -0.5, 0.7 - 0.04 * k as f64));
+            let mut expected: Vec<f64> = triangles
+                .iter()
+                .flat_map(|t| {
+                    let xs = t.intersect(&ray);
+                    (0..xs.len()).map(|i| xs[i].t()).collect::<Vec<f64>>()
+                })
+                .collect();
+            expected.sort_by(|a, b| a.total_cmp(b));
+            let xs = mesh.intersect(&ray);
+            assert_eq!(expected.len(), xs.len());
+            for (i, t) in expected.iter().enumerate() {
+                assert_nearly_eq(*t, xs[i].t());
+            }
+        }
+    }
+
+    #[test]
+    fn a_glass_mesh_is_entered_and_left_as_one_object() {
+        let glass = Material::default().with_refractive_index(1.5);
+        let mesh = Mesh::new(cube()).with_material(glass);
+        let ray = Ray::new(Tup::point(0.2, 0.3, -5.0), Tup::vector(0, 0, 1));
+        let xs = mesh.intersect(&ray);
+        let entering = xs[0].prepare_computations(&ray, &xs);
+        assert_eq!((1.0, 1.5), (entering.n1(), entering.n2()));
+        let leaving = xs[1].prepare_computations(&ray, &xs);
+        assert_eq!((1.5, 1.0), (leaving.n1(), leaving.n2()));
+    }
+
+    #[test]
+    fn meshes_are_only_equal_to_themselves() {
+        let mesh = Mesh::new(cube());
+        let ray = Ray::new(Tup::point(0.2, 0.3, -5.0), Tup::vector(0, 0, 1));
+        let xs = mesh.intersect(&ray);
+        assert_eq!(format!("{:?}", mesh), format!("{:?}", xs[0].object()));
+        assert_ne!(mesh, Mesh::new(cube()));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::color::Color;
+use std::f64::consts::PI;
+
+// Building blocks of the Cook-Torrance microfacet BRDF with the GGX
+// (Trowbridge-Reitz) distribution. Roughness is the perceptual roughness in
+// [0, 1]; the distribution itself uses its square, which makes a linear
+// roughness slider feel linear
+
+// Perfectly smooth surfaces have a delta distribution that point lights can
+// never hit, so roughness is clamped just above zero
+const MIN_ALPHA: f64 = 1e-3;
+
+fn alpha(roughness: f64) -> f64 {
+    (roughness * roughness).max(MIN_ALPHA)
+}
+
+// The density of microfacets whose normal is the half vector, given the
+// cosine between the half vector and the surface normal
+pub fn ggx_distribution(n_dot_h: f64, roughness: f64) -> f64 {
+    let a2 = alpha(roughness).powi(2);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// The fraction of microfacets visible from one direction, not hidden behind
+// neighboring microfacets
+fn smith_g1(n_dot_x: f64, roughness: f64) -> f64 {
+    let a2 = alpha(roughness).powi(2);
+    2.0 * n_dot_x / (n_dot_x + (a2 + (1.0 - a2) * n_dot_x * n_dot_x).sqrt())
+}
+
+// The fraction of microfacets visible from both the eye and the light
+pub fn smith_geometry(n_dot_v: f64, n_dot_l: f64, roughness: f64) -> f64 {
+    smith_g1(n_dot_v, roughness) * smith_g1(n_dot_l, roughness)
+}
+
+// The fraction of light reflected at an interface, where `f0` is the
+// reflectance when looking straight at the surface
+pub fn fresnel_schlick(cos_theta: f64, f0: Color) -> Color {
+    let factor = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::new(1, 1, 1) - f0) * factor
+}
+
+// The reflectance at normal incidence of an interface between media with the
+// given indices of refraction
+pub fn f0_from_ior(n1: f64, n2: f64) -> f64 {
+    let r = (n1 - n2) / (n1 + n2);
+    r * r
+}
+
+#[cfg(test)]
+mod microfacet_test {
+    use super::*;
+    use crate::color::consts as color;
+    use crate::test_helpers::assert_nearly_eq;
+
+    #[test]
+    fn the_ggx_distribution_is_normalized() {
+        // The projected area of all microfacets equals the macro surface
+        // area, so the distribution weighted by cos(theta_h) integrates to 1
+        for roughness in [0.2, 0.5, 1.0] {
+            let steps = 20000;
+            let d_theta = (PI / 2.0) / steps as f64;
+            let integral: f64 = (0..steps)
+                .map(|i| {
+                    let theta = (i as f64 + 0.5) * d_theta;
+                    let cos = theta.cos();
+                    ggx_distribution(cos, roughness) * cos * theta.sin() * d_theta * 2.0 * PI
+                })
+                .sum();
+            assert!((integral - 1.0).abs() < 1e-3, "{roughness}: {integral}");
+        }
+    }
+
+    #[test]
+    fn a_smoother_surface_has_a_sharper_peak() {
+        assert!(ggx_distribution(1.0, 0.2) > ggx_distribution(1.0, 0.6));
+        assert!(ggx_distribution(0.9, 0.2) < ggx_distribution(0.9, 0.6));
+    }
+
+    #[test]
+    fn nothing_is_shadowed_when_looking_along_the_normal() {
+        assert_nearly_eq(1.0, smith_geometry(1.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn grazing_angles_are_shadowed() {
+        assert!(smith_geometry(0.05, 1.0, 0.5) < 0.5);
+        assert!(smith_geometry(0.05, 1.0, 0.8) < smith_geometry(0.05, 1.0, 0.2));
+    }
+
+    #[test]
+    fn fresnel_reflectance_at_normal_incidence_is_f0() {
+        let f0 = Color::new(0.04, 0.5, 0.9);
+        assert_eq!(f0, fresnel_schlick(1.0, f0));
+    }
+
+    #[test]
+    fn fresnel_reflectance_at_grazing_incidence_is_total() {
+        assert_eq!(
+            color::WHITE,
+            fresnel_schlick(0.0, Color::new(0.04, 0.04, 0.04))
+        );
+    }
+
+    #[test]
+    fn glass_in_air_reflects_four_percent_head_on() {
+        assert_nearly_eq(0.04, f0_from_ior(1.0, 1.5));
+    }
+}
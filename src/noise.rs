@@ -0,0 +1,152 @@
+use crate::tup::Tup;
+
+// Gradient noise for procedural textures and volumes. See Ken Perlin,
+// "Improving Noise"
+
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(i: i64) -> usize {
+    PERMUTATION[i.rem_euclid(256) as usize] as usize
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// The dot product of (x, y, z) with one of twelve gradient directions
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// Smoothly varying noise in roughly [-1, 1], zero at integer coordinates
+pub fn perlin(point: Tup) -> f64 {
+    let (xf, yf, zf) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let (xi, yi, zi) = (xf as i64, yf as i64, zf as i64);
+    let (x, y, z) = (point.x - xf, point.y - yf, point.z - zf);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = hash(xi) as i64 + yi;
+    let aa = hash(a) as i64 + zi;
+    let ab = hash(a + 1) as i64 + zi;
+    let b = hash(xi + 1) as i64 + yi;
+    let ba = hash(b) as i64 + zi;
+    let bb = hash(b + 1) as i64 + zi;
+
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, grad(hash(aa), x, y, z), grad(hash(ba), x - 1.0, y, z)),
+            lerp(
+                u,
+                grad(hash(ab), x, y - 1.0, z),
+                grad(hash(bb), x - 1.0, y - 1.0, z),
+            ),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                grad(hash(aa + 1), x, y, z - 1.0),
+                grad(hash(ba + 1), x - 1.0, y, z - 1.0),
+            ),
+            lerp(
+                u,
+                grad(hash(ab + 1), x, y - 1.0, z - 1.0),
+                grad(hash(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+            ),
+        ),
+    )
+}
+
+// Fractal Brownian motion: octaves of noise, each at twice the frequency
+// and half the amplitude of the last, normalized back into roughly [-1, 1].
+// Gives the billowing detail of clouds and smoke
+pub fn fbm(point: Tup, octaves: usize) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += perlin(point * frequency) * amplitude;
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total_amplitude
+}
+
+#[cfg(test)]
+mod noise_test {
+    use super::*;
+
+    #[test]
+    fn noise_is_zero_at_integer_coordinates() {
+        assert_eq!(0.0, perlin(Tup::point(0, 0, 0)));
+        assert_eq!(0.0, perlin(Tup::point(3, -2, 7)));
+    }
+
+    #[test]
+    fn noise_is_repeatable() {
+        let p = Tup::point(1.3, -0.7, 2.25);
+        assert_eq!(perlin(p), perlin(p));
+    }
+
+    #[test]
+    fn noise_varies_between_integer_coordinates() {
+        let values: Vec<f64> = (0..20)
+            .map(|i| perlin(Tup::point(i as f64 * 0.37, 0.5, 0.25)))
+            .collect();
+        assert!(values.iter().any(|v| *v > 0.05));
+        assert!(values.iter().any(|v| *v < -0.05));
+        assert!(values.iter().all(|v| v.abs() <= 1.0));
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let p = Tup::point(0.4, 1.6, -2.3);
+        let q = Tup::point(0.4001, 1.6, -2.3);
+        assert!((perlin(p) - perlin(q)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fractal_noise_stays_in_range() {
+        for i in 0..50 {
+            let p = Tup::point(i as f64 * 0.31, i as f64 * -0.17, 0.5);
+            assert!(fbm(p, 5).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn a_single_octave_of_fractal_noise_is_plain_noise() {
+        let p = Tup::point(0.3, 0.6, 0.9);
+        assert_eq!(perlin(p), fbm(p, 1));
+    }
+}
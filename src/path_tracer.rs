@@ -0,0 +1,448 @@
+use crate::color::consts as col;
+use crate::color::Color;
+use crate::math_helpers::nearly_eq;
+use crate::rays::Ray;
+use crate::sampling::{self, Rng};
+use crate::subsurface;
+use crate::tup::Tup;
+use crate::world::World;
+
+// An unbiased Monte Carlo alternative to the recursive shading in `World`.
+// Each sample follows a single path through the scene: diffuse surfaces
+// bounce it in a cosine weighted direction, reflective and transparent
+// surfaces bounce it like a mirror or a pane of glass, and the point light is
+// sampled directly at every vertex. Indirect light is therefore computed
+// instead of being faked by each material's `ambient` term, and emissive
+// surfaces act as area lights of whatever shape they happen to be
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct PathTracer {
+    samples_per_pixel: usize,
+    max_depth: usize,
+    russian_roulette_depth: usize,
+}
+
+impl PathTracer {
+    pub fn new(samples_per_pixel: usize) -> Self {
+        Self {
+            samples_per_pixel,
+            ..Self::default()
+        }
+    }
+
+    // A hard limit on path length. Russian roulette usually ends paths long
+    // before this, it only guards against paths trapped between mirrors
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    // The number of bounces after which paths start being randomly
+    // terminated
+    pub fn with_russian_roulette_depth(self, russian_roulette_depth: usize) -> Self {
+        Self {
+            russian_roulette_depth,
+            ..self
+        }
+    }
+
+    pub fn samples_per_pixel(&self) -> usize {
+        self.samples_per_pixel
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn russian_roulette_depth(&self) -> usize {
+        self.russian_roulette_depth
+    }
+
+    pub fn trace(&self, world: &World, ray: Ray, rng: &mut Rng) -> Color {
+        let mut radiance = col::BLACK;
+        let mut throughput = col::WHITE;
+        let mut ray = ray;
+        for depth in 0..self.max_depth {
+            let xs = world.intersect(ray);
+            let hit = xs.hit().map(|hit| hit.prepare_computations(&ray, &xs));
+            // Fog and smoke along the way scatter light from the light source
+            // into the path, and dim whatever lies beyond
+            let (transmittance, in_scattered) = world.media_along(ray, hit.as_ref());
+            radiance = radiance + throughput * in_scattered;
+            throughput = throughput * transmittance;
+            let comps = match hit {
+                Some(comps) => comps,
+                None => {
+                    radiance = radiance + throughput * world.background_color_at(ray);
+                    break;
+                }
+            };
+            let material = comps.material();
+            if let Some(crossed) = comps.n1_material() {
+                throughput =
+                    throughput * crossed.transmittance(comps.t() * ray.direction().magnitude());
+            }
+            // A clearcoat mirrors part of the light off its surface and dims
+            // whatever comes from beneath it by as much, as in `World`
+            let coat = if comps.inside() {
+                0.0
+            } else {
+                material.clearcoat_reflectance(comps.normalv().dot(&comps.eyev()))
+            };
+            radiance = radiance + throughput * material.emission() * (1.0 - coat);
+
+            // Direct light from the point light. Ambient is dropped since the
+            // indirect bounces below account for it
+            let direct = material.clone().with_ambient(0.0).lighting(
+                comps.object().world_to_object(),
+                world.light_for(comps.wavelength()),
+                comps.over_point(),
+                comps.eyev(),
+                comps.normalv(),
+                world.light_transmittance(comps.over_point(), comps.wavelength()),
+            );
+            radiance = radiance + throughput * direct;
+
+            // Bounce off the coat as often as it reflects. Otherwise pick one
+            // of the diffuse, mirror and transmission lobes with probability
+            // proportional to its weight, then divide by that probability to
+            // keep the estimate unbiased. Both already carry the coat's share
+            let on_coat = coat > 0.0 && rng.next_f64() < coat;
+            let diffuse = material.diffuse_weight();
+            let reflective = material.reflective();
+            let transparency = material.transparency();
+            let total = diffuse + reflective + transparency;
+            if total <= 0.0 && !on_coat {
+                break;
+            }
+            let choice = rng.next_f64() * total;
+            let (next_ray, weight) = if on_coat {
+                let direction = self.scatter(
+                    comps.reflectv(),
+                    comps.normalv(),
+                    material.clearcoat_roughness(),
+                    rng,
+                );
+                (comps.spawn_ray(comps.over_point(), direction), col::WHITE)
+            } else if choice < diffuse && material.has_subsurface() {
+                // Light entering a translucent surface comes out somewhere
+                // else, where it picks up direct light and bounces on
+                let color = material.color_at(comps.object().world_to_object(), comps.point());
+                match subsurface::random_walk(
+                    comps.object().as_ref(),
+                    comps.under_point(),
+                    comps.normalv(),
+                    &material,
+                    rng,
+                ) {
+                    Some(exit) => {
+                        let weight = color * exit.throughput() * total;
+                        let direct =
+                            world.direct_light(exit.point(), exit.normal(), comps.wavelength());
+                        radiance = radiance + throughput * weight * direct;
+                        let direction = sampling::cosine_weighted_hemisphere(exit.normal(), rng);
+                        (comps.spawn_ray(exit.point(), direction), weight)
+                    }
+                    None => break,
+                }
+            } else if choice < diffuse {
+                let color = material.color_at(comps.object().world_to_object(), comps.point());
+                let direction = sampling::cosine_weighted_hemisphere(comps.normalv(), rng);
+                (
+                    comps.spawn_ray(comps.over_point(), direction),
+                    color * total,
+                )
+            } else if choice < diffuse + reflective {
+                let direction =
+                    self.scatter(comps.reflectv(), comps.normalv(), material.roughness(), rng);
+                (
+                    comps.spawn_ray(comps.over_point(), direction),
+                    col::WHITE * total,
+                )
+            } else {
+                // Dispersive glass sends each wavelength its own way, so the
+                // path follows one randomly chosen color channel from here on
+                let (comps, channel) = if comps.wavelength().is_none() && comps.is_dispersive() {
+                    let i = ((rng.next_f64() * 3.0) as usize).min(2);
+                    let channel = [col::RED, col::GREEN, col::BLUE][i] * 3.0;
+                    (
+                        comps.at_wavelength(World::DISPERSION_WAVELENGTHS[i]),
+                        channel,
+                    )
+                } else {
+                    (comps, col::WHITE)
+                };
+                match comps.refractv() {
+                    Some(refractv) if rng.next_f64() >= comps.schlick() => {
+                        let direction =
+                            self.scatter(refractv, -comps.normalv(), material.roughness(), rng);
+                        (
+                            comps.spawn_ray(comps.under_point(), direction),
+                            channel * total,
+                        )
+                    }
+                    _ => {
+                        let direction = self.scatter(
+                            comps.reflectv(),
+                            comps.normalv(),
+                            material.roughness(),
+                            rng,
+                        );
+                        (
+                            comps.spawn_ray(comps.over_point(), direction),
+                            channel * total,
+                        )
+                    }
+                }
+            };
+            throughput = throughput * weight;
+
+            if depth >= self.russian_roulette_depth {
+                let survival = throughput
+                    .red()
+                    .max(throughput.green())
+                    .max(throughput.blue())
+                    .clamp(0.05, 0.95);
+                if rng.next_f64() >= survival {
+                    break;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
+            ray = next_ray;
+        }
+        radiance
+    }
+}
+
+impl PathTracer {
+    // Rough surfaces spread mirror and transmission bounces over a cone
+    // around the perfect direction, matching `World`'s glossy reflections
+    fn scatter(&self, direction: Tup, side_normal: Tup, roughness: f64, rng: &mut Rng) -> Tup {
+        if nearly_eq(0.0, roughness) {
+            return direction;
+        }
+        let half_angle = roughness.min(1.0) * World::MAX_GLOSSY_ANGLE;
+        let scattered = sampling::uniform_cone(direction, half_angle, rng);
+        if scattered.dot(&side_normal) > 0.0 {
+            scattered
+        } else {
+            direction
+        }
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: 64,
+            max_depth: 64,
+            russian_roulette_depth: 3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod path_tracer_test {
+    use super::*;
+    use crate::background::Background;
+    use crate::lights::Light;
+    use crate::materials::{Material, ShadingModel};
+    use crate::media::Medium;
+    use crate::planes::Plane;
+    use crate::spheres::Sphere;
+    use crate::test_helpers::default_test_world;
+    use crate::transforms;
+
+    fn average_radiance(world: &World, ray: Ray, samples: usize) -> Color {
+        let tracer = PathTracer::default();
+        let mut rng = Rng::new(1234);
+        let sum = (0..samples).fold(col::BLACK, |sum, _| {
+            sum + tracer.trace(world, ray, &mut rng)
+        });
+        sum * (1.0 / samples as f64)
+    }
+
+    #[test]
+    fn a_path_tracer_has_default_settings() {
+        let tracer = PathTracer::default();
+        assert_eq!(64, tracer.samples_per_pixel());
+        assert_eq!(64, tracer.max_depth());
+        assert_eq!(3, tracer.russian_roulette_depth());
+    }
+
+    #[test]
+    fn path_tracer_settings_can_be_changed() {
+        let tracer = PathTracer::new(16)
+            .with_max_depth(8)
+            .with_russian_roulette_depth(2);
+        assert_eq!(16, tracer.samples_per_pixel());
+        assert_eq!(8, tracer.max_depth());
+        assert_eq!(2, tracer.russian_roulette_depth());
+    }
+
+    #[test]
+    fn a_path_that_misses_everything_sees_the_background() {
+        let world = default_test_world().with_background(Background::solid(col::CYAN));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 1, 0));
+        let mut rng = Rng::new(0);
+        let c = PathTracer::default().trace(&world, ray, &mut rng);
+        assert_eq!(col::CYAN, c);
+    }
+
+    #[test]
+    fn a_diffuse_sphere_in_a_uniform_environment_reflects_its_albedo() {
+        // A convex diffuse object lit only by a uniform white environment
+        // reflects exactly its albedo, a "furnace test" for unbiasedness
+        let material = Material::default()
+            .with_color(Color::new(1.0, 0.5, 0.25))
+            .with_diffuse(1.0)
+            .with_specular(0.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(col::WHITE));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 200);
+        assert!((c.red() - 1.0).abs() < 0.05, "{c:?}");
+        assert!((c.green() - 0.5).abs() < 0.05, "{c:?}");
+        assert!((c.blue() - 0.25).abs() < 0.05, "{c:?}");
+    }
+
+    #[test]
+    fn a_microfacet_sphere_in_a_uniform_environment_ignores_the_phong_diffuse() {
+        let material = Material::default()
+            .with_shading_model(ShadingModel::Microfacet)
+            .with_color(Color::new(1.0, 0.5, 0.25))
+            .with_diffuse(0.2);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(col::WHITE));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 200);
+        assert!((c.red() - 1.0).abs() < 0.05, "{c:?}");
+        assert!((c.blue() - 0.25).abs() < 0.05, "{c:?}");
+    }
+
+    #[test]
+    fn a_mirror_reflects_the_environment() {
+        let material = Material::default()
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_reflective(1.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::gradient(col::BLACK, col::WHITE));
+        // Hits the top of the sphere and is reflected straight up
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        let c = average_radiance(&world, ray, 10);
+        assert_eq!(col::WHITE, c);
+    }
+
+    #[test]
+    fn a_rough_mirror_blurs_the_environment() {
+        let material = Material::default()
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_reflective(1.0)
+            .with_roughness(0.5);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::gradient(col::BLACK, col::WHITE));
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        let c = average_radiance(&world, ray, 50);
+        assert!(c.red() < 0.95 && c.red() > 0.5, "{c:?}");
+    }
+
+    #[test]
+    fn an_emissive_surface_is_seen_directly() {
+        let material = Material::default()
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_emissive(col::YELLOW)
+            .with_emissive_strength(3.0);
+        let world = World::default().with_object(Sphere::default().with_material(material));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 10);
+        assert_eq!(Color::new(3.0, 3.0, 0.0), c);
+    }
+
+    #[test]
+    fn a_clearcoat_reflects_the_environment_over_the_base() {
+        let material = Material::default()
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_emissive(col::RED)
+            .with_clearcoat(1.0);
+        let coat = material.clearcoat_reflectance(1.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(col::YELLOW));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 400);
+        // Red beneath the coat, yellow mirrored off it
+        assert!((c.red() - 1.0).abs() < 0.03, "{c:?}");
+        assert!((c.green() - coat).abs() < 0.03, "{c:?}");
+        assert!(c.green() > 0.0, "{c:?}");
+    }
+
+    #[test]
+    fn an_emissive_surface_lights_its_surroundings() {
+        let lamp = Material::default()
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_emissive(col::WHITE)
+            .with_emissive_strength(10.0);
+        let floor = Material::default().with_specular(0.0);
+        let world = World::default()
+            .with_object(
+                Sphere::default()
+                    .with_transform(transforms::translation(0, 2, 0))
+                    .with_material(lamp),
+            )
+            .with_object(Plane::default().with_material(floor));
+        let ray = Ray::new(Tup::point(0, 1, -5), Tup::vector(0, -1, 5).normalize());
+        let c = average_radiance(&world, ray, 200);
+        assert!(c.red() > 0.1, "{c:?}");
+        // Without a path tracer only the light source itself would glow
+        assert_eq!(col::BLACK, world.color_at(ray, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn paths_pick_up_direct_light() {
+        let world = default_test_world();
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 50);
+        // At least the direct contribution the Whitted tracer sees, minus
+        // its constant ambient term
+        let ambient = world[0].material().color() * world[0].material().ambient();
+        let whitted = world.color_at(ray, World::MAX_BOUNCES) - ambient;
+        assert!(c.red() >= whitted.red() - 1e-4);
+        assert!(c.green() >= whitted.green() - 1e-4);
+    }
+
+    #[test]
+    fn paths_through_lit_fog_pick_up_scattered_light() {
+        let world = World::default()
+            .with_light(Light::point_light(Tup::point(0, 5, 0), col::WHITE))
+            .with_fog(Medium::fog(0.1));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 4);
+        assert!(c.red() > 0.05, "{c:?}");
+    }
+
+    #[test]
+    fn a_translucent_sphere_in_a_uniform_environment_reflects_its_albedo() {
+        // Light wandering under the surface of a material that absorbs
+        // nothing always finds its way back out
+        let material = Material::default()
+            .with_color(Color::new(1.0, 0.5, 0.25))
+            .with_diffuse(1.0)
+            .with_specular(0.0)
+            .with_mean_free_path(0.2);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(col::WHITE));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = average_radiance(&world, ray, 200);
+        assert!((c.red() - 1.0).abs() < 0.05, "{c:?}");
+        assert!((c.green() - 0.5).abs() < 0.05, "{c:?}");
+        assert!((c.blue() - 0.25).abs() < 0.05, "{c:?}");
+    }
+}
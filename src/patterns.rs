@@ -45,8 +45,8 @@ impl Pattern {
         self.transform
     }
 
-    pub fn color_at(&self, object_transform: Mat4, point: Tup) -> Color {
-        let pattern_point = self.transform.inverse() * object_transform.inverse() * point;
+    pub fn color_at(&self, world_to_object: Mat4, point: Tup) -> Color {
+        let pattern_point = self.transform.inverse() * world_to_object * point;
         self.pattern.color(pattern_point)
     }
 }
@@ -90,7 +90,7 @@ impl StripePattern {
 
     fn pattern_at(&self, point: Tup) -> Color {
         let lattice_point = point.x.floor().abs() as u64;
-        if lattice_point % 2 == 0 {
+        if lattice_point.is_multiple_of(2) {
             self.a()
         } else {
             self.b()
@@ -131,7 +131,7 @@ impl RingPattern {
         let x_square = point.x * point.x;
         let z_square = point.z * point.z;
         let lattice_distance = (x_square + z_square).sqrt().floor() as u64;
-        if lattice_distance % 2 == 0 {
+        if lattice_distance.is_multiple_of(2) {
             self.color_a
         } else {
             self.color_b
@@ -154,7 +154,7 @@ impl CheckersPattern {
         let lattice_x = point.x.floor().abs() as u64;
         let lattice_y = point.y.floor().abs() as u64;
         let lattice_z = point.z.floor().abs() as u64;
-        if (lattice_x + lattice_y + lattice_z) % 2 == 0 {
+        if (lattice_x + lattice_y + lattice_z).is_multiple_of(2) {
             self.color_a
         } else {
             self.color_b
@@ -241,7 +241,7 @@ mod patterns_test {
     fn a_pattern_can_have_an_object_transformation() {
         let object = Sphere::default().with_transform(transforms::scaling(2, 2, 2));
         let pattern = Pattern::stripe_pattern(color::WHITE, color::BLACK);
-        let c = pattern.color_at(object.transform(), Tup::point(1.5, 0.0, 0.0));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(1.5, 0.0, 0.0));
         assert_eq!(color::WHITE, c);
     }
 
@@ -250,7 +250,7 @@ mod patterns_test {
         let object = Sphere::default();
         let pattern = Pattern::stripe_pattern(color::WHITE, color::BLACK)
             .with_transform(transforms::scaling(2, 2, 2));
-        let c = pattern.color_at(object.transform(), Tup::point(2.5, 0.0, 0.0));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(2.5, 0.0, 0.0));
         assert_eq!(color::BLACK, c);
     }
 
@@ -259,7 +259,7 @@ mod patterns_test {
         let object = Sphere::default().with_transform(transforms::scaling(2, 2, 2));
         let pattern = Pattern::stripe_pattern(color::WHITE, color::BLACK)
             .with_transform(transforms::translation(0.5, 0.0, 0.0));
-        let c = pattern.color_at(object.transform(), Tup::point(2.5, 0.0, 0.0));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(2.5, 0.0, 0.0));
         assert_eq!(color::WHITE, c);
     }
 
@@ -279,7 +279,7 @@ mod patterns_test {
     fn a_pattern_correctly_handles_an_object_transformation() {
         let object = Sphere::default().with_transform(transforms::scaling(2, 2, 2));
         let pattern = Pattern::default();
-        let c = pattern.color_at(object.transform(), Tup::point(2, 3, 4));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(2, 3, 4));
         assert_eq!(Color::new(1.0, 1.5, 2.0), c);
     }
 
@@ -287,7 +287,7 @@ mod patterns_test {
     fn a_pattern_correctly_handles_a_pattern_transformation() {
         let object = Sphere::default();
         let pattern = Pattern::default().with_transform(transforms::scaling(2, 2, 2));
-        let c = pattern.color_at(object.transform(), Tup::point(2, 3, 4));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(2, 3, 4));
         assert_eq!(Color::new(1.0, 1.5, 2.0), c);
     }
 
@@ -295,7 +295,7 @@ mod patterns_test {
     fn a_pattern_correctly_handles_a_pattern_plus_object_transformation() {
         let object = Sphere::default().with_transform(transforms::scaling(2, 2, 2));
         let pattern = Pattern::default().with_transform(transforms::translation(0.5, 1.0, 1.5));
-        let c = pattern.color_at(object.transform(), Tup::point(2.5, 3.0, 3.5));
+        let c = pattern.color_at(object.world_to_object(), Tup::point(2.5, 3.0, 3.5));
         assert_eq!(Color::new(0.75, 0.5, 0.25), c);
     }
 
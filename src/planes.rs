@@ -6,15 +6,18 @@ use crate::rays::Ray;
 use crate::shapes::Shape;
 use crate::tup::Tup;
 
-#[derive(PartialEq, Copy, Clone, Debug, Default)]
+#[derive(PartialEq, Clone, Debug, Default)]
 pub struct Plane {
     transform: Mat4,
-    material: Material,
+    material: Option<Material>,
 }
 
 impl Plane {
     pub fn with_material(self, material: Material) -> Self {
-        Self { material, ..self }
+        Self {
+            material: Some(material),
+            ..self
+        }
     }
 
     pub fn with_transform(self, transform: Mat4) -> Self {
@@ -32,11 +35,15 @@ impl Shape for Plane {
     }
 
     fn material(&self) -> Material {
-        self.material
+        self.material.clone().unwrap_or_default()
     }
 
     fn set_material(&mut self, material: Material) {
-        self.material = material;
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
     }
 
     fn intersect(&self, ray: &Ray) -> Intersections {
@@ -49,7 +56,7 @@ impl Shape for Plane {
             Intersections::default()
         } else {
             let t = -local_ray.origin().y / local_ray.direction().y;
-            Intersections::new(&[Intersection::new(t, *self)])
+            Intersections::new(&[Intersection::new(t, self.clone())])
         }
     }
 
@@ -66,11 +73,23 @@ impl Shape for Plane {
     fn local_normal_at(&self, _point: Tup) -> Tup {
         Tup::vector(0, 1, 0)
     }
+
+    // Textures repeat every unit along x and z, with the top of the image
+    // towards positive z
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        (point.x.rem_euclid(1.0), (-point.z).rem_euclid(1.0))
+    }
+
+    fn local_tangent_at(&self, _point: Tup) -> Tup {
+        Tup::vector(1, 0, 0)
+    }
 }
 
 #[cfg(test)]
 mod planes_test {
     use super::*;
+    use crate::transforms;
+    use std::f64::consts;
 
     #[test]
     fn the_normal_of_a_plane_is_constant_everywhere() {
@@ -116,4 +135,19 @@ mod planes_test {
         assert_eq!(1, xs.len());
         assert_eq!(1.0, xs[0].t());
     }
+
+    #[test]
+    fn texture_coordinates_repeat_across_a_plane() {
+        let p = Plane::default();
+        let (u, v) = p.local_uv_at(Tup::point(0.25, 0.0, -0.5));
+        assert_eq!((0.25, 0.5), (u, v));
+        let (u, v) = p.local_uv_at(Tup::point(3.25, 0.0, -2.5));
+        assert_eq!((0.25, 0.5), (u, v));
+    }
+
+    #[test]
+    fn the_tangent_of_a_plane_follows_its_transform() {
+        let p = Plane::default().with_transform(transforms::rotation_y(consts::FRAC_PI_2));
+        assert_eq!(Tup::vector(0, 0, -1), p.tangent_at(Tup::point(0, 0, 0)));
+    }
 }
@@ -0,0 +1,501 @@
+use crate::meshes::{MeshData, MeshError};
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+
+// Reader for Stanford PLY (`.ply`) meshes, in ASCII or either byte order of
+// binary. The header lists the elements in the file and the properties of
+// each. Vertices give positions and optionally normals and texture
+// coordinates, and faces give lists of vertex indices. Faces with more than
+// three corners are split into fans of triangles and other elements, like
+// edges or materials, are skipped
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlyError {
+    MissingMagic,
+    UnsupportedFormat(String),
+    InvalidHeader(String),
+    MissingElement(&'static str),
+    MissingProperty(&'static str),
+    UnexpectedEndOfData,
+    InvalidNumber(String),
+    IndexOutOfRange(usize),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::MissingMagic => write!(f, "missing `ply` magic number"),
+            PlyError::UnsupportedFormat(format) => write!(f, "unsupported format `{format}`"),
+            PlyError::InvalidHeader(line) => write!(f, "invalid header line `{line}`"),
+            PlyError::MissingElement(name) => write!(f, "missing `{name}` element"),
+            PlyError::MissingProperty(name) => write!(f, "missing `{name}` property"),
+            PlyError::UnexpectedEndOfData => write!(f, "unexpected end of data"),
+            PlyError::InvalidNumber(text) => write!(f, "`{text}` is not a number"),
+            PlyError::IndexOutOfRange(index) => write!(f, "there is no vertex {index}"),
+        }
+    }
+}
+
+impl Error for PlyError {}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Scalar {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Scalar::Int8),
+            "uchar" | "uint8" => Some(Scalar::Uint8),
+            "short" | "int16" => Some(Scalar::Int16),
+            "ushort" | "uint16" => Some(Scalar::Uint16),
+            "int" | "int32" => Some(Scalar::Int32),
+            "uint" | "uint32" => Some(Scalar::Uint32),
+            "float" | "float32" => Some(Scalar::Float32),
+            "double" | "float64" => Some(Scalar::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::Uint8 => 1,
+            Scalar::Int16 | Scalar::Uint16 => 2,
+            Scalar::Int32 | Scalar::Uint32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    // The type of the length, then the type of the items
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<MeshData, PlyError> {
+    let mut reader = Reader::new(bytes);
+    let (format, elements) = read_header(&mut reader)?;
+    let mut values = Values::new(reader, format)?;
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                read_vertices(element, &mut values, &mut positions, &mut normals, &mut uvs)?
+            }
+            "face" => read_faces(element, &mut values, &mut faces)?,
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        values.property(property)?;
+                    }
+                }
+            }
+        }
+    }
+    if !elements.iter().any(|e| e.name == "vertex") {
+        return Err(PlyError::MissingElement("vertex"));
+    }
+    if !elements.iter().any(|e| e.name == "face") {
+        return Err(PlyError::MissingElement("face"));
+    }
+    let data = MeshData::new(positions, faces)
+        .map_err(|MeshError::IndexOutOfRange(index)| PlyError::IndexOutOfRange(index))?;
+    Ok(data.with_normals(normals).with_uvs(uvs))
+}
+
+fn read_header(reader: &mut Reader) -> Result<(Format, Vec<Element>), PlyError> {
+    if reader.line().as_deref() != Some("ply") {
+        return Err(PlyError::MissingMagic);
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let line = reader.line().ok_or(PlyError::UnexpectedEndOfData)?;
+        let invalid = || PlyError::InvalidHeader(line.clone());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(PlyError::UnsupportedFormat(name.to_string())),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid())?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length, item, name] => {
+                let length = Scalar::parse(length).ok_or_else(invalid)?;
+                let item = Scalar::parse(item).ok_or_else(invalid)?;
+                let element = elements.last_mut().ok_or_else(invalid)?;
+                element
+                    .properties
+                    .push(Property::List(name.to_string(), length, item));
+            }
+            ["property", scalar, name] => {
+                let scalar = Scalar::parse(scalar).ok_or_else(invalid)?;
+                let element = elements.last_mut().ok_or_else(invalid)?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), scalar));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    let format = format.ok_or_else(|| PlyError::UnsupportedFormat(String::new()))?;
+    Ok((format, elements))
+}
+
+fn read_vertices(
+    element: &Element,
+    values: &mut Values,
+    positions: &mut Vec<Tup>,
+    normals: &mut Vec<Tup>,
+    uvs: &mut Vec<(f64, f64)>,
+) -> Result<(), PlyError> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| names.contains(&p.name()))
+    };
+    let missing = PlyError::MissingProperty;
+    let x = find(&["x"]).ok_or(missing("x"))?;
+    let y = find(&["y"]).ok_or(missing("y"))?;
+    let z = find(&["z"]).ok_or(missing("z"))?;
+    let normal = find(&["nx"]).zip(find(&["ny"])).zip(find(&["nz"]));
+    let uv = find(&["u", "s", "texture_u", "texture_s"]).zip(find(&[
+        "v",
+        "t",
+        "texture_v",
+        "texture_t",
+    ]));
+    let mut row = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (value, property) in row.iter_mut().zip(&element.properties) {
+            // Lists on vertices are unusual and not needed here
+            *value = values.property(property)?.first().copied().unwrap_or(0.0);
+        }
+        positions.push(Tup::point(row[x], row[y], row[z]));
+        if let Some(((nx, ny), nz)) = normal {
+            // Some exporters write zero normals for vertices they couldn't
+            // work one out for, and others write tiny ones too short for
+            // `normalize`, so the length is divided out by hand
+            let n = Tup::vector(row[nx], row[ny], row[nz]);
+            let length = n.magnitude();
+            normals.push(if length > 0.0 { n * (1.0 / length) } else { n });
+        }
+        if let Some((u, v)) = uv {
+            uvs.push((row[u], row[v]));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(
+    element: &Element,
+    values: &mut Values,
+    faces: &mut Vec<[usize; 3]>,
+) -> Result<(), PlyError> {
+    let indices = element
+        .properties
+        .iter()
+        .position(|p| p.name() == "vertex_indices" || p.name() == "vertex_index")
+        .ok_or(PlyError::MissingProperty("vertex_indices"))?;
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            let list = values.property(property)?;
+            if i != indices {
+                continue;
+            }
+            let corners: Vec<usize> = list
+                .iter()
+                .map(|&c| {
+                    if c < 0.0 {
+                        Err(PlyError::InvalidNumber(c.to_string()))
+                    } else {
+                        Ok(c as usize)
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+            for k in 2..corners.len() {
+                faces.push([corners[0], corners[k - 1], corners[k]]);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The numbers after the header, as words of text or packed binary
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary(Reader<'a>, bool),
+}
+
+impl<'a> Values<'a> {
+    fn new(reader: Reader<'a>, format: Format) -> Result<Self, PlyError> {
+        match format {
+            Format::Ascii => {
+                let rest = &reader.bytes[reader.position..];
+                let text = std::str::from_utf8(rest)
+                    .map_err(|_| PlyError::InvalidNumber("non UTF-8 text".to_string()))?;
+                Ok(Values::Ascii(text.split_ascii_whitespace()))
+            }
+            Format::BinaryLittleEndian => Ok(Values::Binary(reader, false)),
+            Format::BinaryBigEndian => Ok(Values::Binary(reader, true)),
+        }
+    }
+
+    fn scalar(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        match self {
+            Values::Ascii(words) => {
+                let word = words.next().ok_or(PlyError::UnexpectedEndOfData)?;
+                word.parse()
+                    .map_err(|_| PlyError::InvalidNumber(word.to_string()))
+            }
+            Values::Binary(reader, big_endian) => {
+                let bytes = reader
+                    .take(scalar.size())
+                    .ok_or(PlyError::UnexpectedEndOfData)?;
+                let mut b = [0_u8; 8];
+                b[..bytes.len()].copy_from_slice(bytes);
+                if *big_endian {
+                    b[..bytes.len()].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::Int8 => i8::from_le_bytes([b[0]]) as f64,
+                    Scalar::Uint8 => b[0] as f64,
+                    Scalar::Int16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::Uint16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::Int32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::Uint32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::Float64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    // Every value of a property, one for scalars and any number for lists
+    fn property(&mut self, property: &Property) -> Result<Vec<f64>, PlyError> {
+        match property {
+            Property::Scalar(_, scalar) => Ok(vec![self.scalar(*scalar)?]),
+            Property::List(_, length, item) => {
+                let length = self.scalar(*length)?;
+                if length < 0.0 {
+                    return Err(PlyError::InvalidNumber(length.to_string()));
+                }
+                (0..length as usize).map(|_| self.scalar(*item)).collect()
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn line(&mut self) -> Option<String> {
+        let rest = &self.bytes[self.position..];
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        self.position += end + 1;
+        Some(String::from_utf8_lossy(&rest[..end]).trim().to_string())
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let taken = self.bytes.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(taken)
+    }
+}
+
+#[cfg(test)]
+mod ply_test {
+    use super::*;
+
+    const CUBE_CORNER: &str = "ply
+format ascii 1.0
+comment three faces around a corner
+element vertex 5
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element edge 1
+property int vertex1
+property int vertex2
+element face 2
+property list uchar int vertex_indices
+end_header
+0 0 0 0 1 0
+1 0 0 0 1 0
+1 0 1 0 1 0
+0 0 1 0 2 0
+0 1 0 1 0 0
+0 1
+4 0 1 2 3
+3 0 4 1
+";
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "big" } else { "little" };
+        let mut bytes = format!(
+            "ply\nformat binary_{format}_endian 1.0\nelement vertex 3\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property float u\nproperty float v\n\
+             element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+        )
+        .into_bytes();
+        let vertices: [[f32; 5]; 3] = [
+            [0.0, 0.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0, 1.0],
+        ];
+        for value in vertices.iter().flatten() {
+            if big_endian {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.push(3);
+        for index in [0_u32, 1, 2] {
+            if big_endian {
+                bytes.extend_from_slice(&index.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn an_ascii_mesh_is_read_with_its_normals() {
+        let mesh = decode(CUBE_CORNER.as_bytes()).expect("Valid ply data");
+        assert_eq!(5, mesh.positions().len());
+        assert_eq!(Tup::point(1, 0, 1), mesh.positions()[2]);
+        assert!(mesh.has_normals());
+        assert_eq!(Tup::vector(0, 1, 0), mesh.normals()[3]);
+        assert!(mesh.uvs().is_empty());
+        // The quad is split in two, and the edge element is skipped
+        assert_eq!(&[[0, 1, 2], [0, 2, 3], [0, 4, 1]], mesh.faces());
+    }
+
+    #[test]
+    fn tiny_normals_are_scaled_to_unit_length() {
+        let ply = CUBE_CORNER.replace("0 0 1 0 2 0", "0 0 1 0 0.000001 0");
+        let mesh = decode(ply.as_bytes()).expect("Valid ply data");
+        assert_eq!(Tup::vector(0, 1, 0), mesh.normals()[3]);
+    }
+
+    #[test]
+    fn binary_meshes_are_read_in_either_byte_order() {
+        for big_endian in [false, true] {
+            let mesh = decode(&binary(big_endian)).expect("Valid ply data");
+            assert_eq!(Tup::point(0, 1, 0), mesh.positions()[2]);
+            assert_eq!(&[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], mesh.uvs());
+            assert!(!mesh.has_normals());
+            assert_eq!(&[[0, 1, 2]], mesh.faces());
+        }
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let bytes = binary(false);
+        for length in [bytes.len() - 1, bytes.len() - 13, 40] {
+            assert!(decode(&bytes[..length]).is_err());
+        }
+        assert_eq!(
+            Some(PlyError::UnexpectedEndOfData),
+            decode(&bytes[..bytes.len() - 1]).err()
+        );
+        let ascii = &CUBE_CORNER[..CUBE_CORNER.len() - 4];
+        assert_eq!(
+            Some(PlyError::UnexpectedEndOfData),
+            decode(ascii.as_bytes()).err()
+        );
+    }
+
+    #[test]
+    fn data_without_the_magic_number_is_an_error() {
+        assert_eq!(Some(PlyError::MissingMagic), decode(b"solid cube\n").err());
+    }
+
+    #[test]
+    fn unsupported_formats_are_an_error() {
+        let result = decode(b"ply\nformat binary_middle_endian 1.0\nend_header\n");
+        assert_eq!(
+            Some(PlyError::UnsupportedFormat(
+                "binary_middle_endian".to_string()
+            )),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn faces_must_refer_to_existing_vertices() {
+        let data = CUBE_CORNER.replace("3 0 4 1", "3 0 4 9");
+        assert_eq!(
+            Some(PlyError::IndexOutOfRange(9)),
+            decode(data.as_bytes()).err()
+        );
+    }
+
+    #[test]
+    fn vertices_need_positions() {
+        let data = CUBE_CORNER.replace("property float z\n", "");
+        assert_eq!(
+            Some(PlyError::MissingProperty("z")),
+            decode(data.as_bytes()).err()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+// Real roots of polynomials, for shapes whose intersections can't be
+// solved with the quadratic formula
+
+// Coefficients smaller than this, relative to the largest one, are treated
+// as zero so that nearly degenerate polynomials drop to a lower degree
+const RELATIVE_ZERO: f64 = 1e-12;
+
+// Bisection steps taken to pin down each root. Far more than a double needs,
+// the search stops early once the bracket can't shrink any further
+const MAX_BISECTIONS: usize = 200;
+
+// The value of the polynomial with `coefficients`, highest power first
+pub fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |sum, c| sum * x + c)
+}
+
+fn derivative(coefficients: &[f64]) -> Vec<f64> {
+    let degree = coefficients.len() - 1;
+    coefficients[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect()
+}
+
+// The real roots of the polynomial with `coefficients`, highest power
+// first, in ascending order. Repeated roots are reported once.
+//
+// Rather than closed form solutions, which lose badly to cancellation for
+// quartics, the roots of the derivative are found first. The polynomial is
+// monotonic between them, so each stretch holds at most one root, which is
+// then found by bisection
+pub fn real_roots(coefficients: &[f64]) -> Vec<f64> {
+    let largest = coefficients.iter().fold(0.0_f64, |m, c| m.max(c.abs()));
+    if largest == 0.0 {
+        return Vec::new();
+    }
+    let first = coefficients
+        .iter()
+        .position(|c| c.abs() > largest * RELATIVE_ZERO)
+        .unwrap_or(coefficients.len() - 1);
+    let coefficients = &coefficients[first..];
+    match coefficients {
+        [] | [_] => Vec::new(),
+        [a, b] => vec![-b / a],
+        [a, b, c] => quadratic_roots(*a, *b, *c),
+        _ => {
+            let mut bounds = vec![-cauchy_bound(coefficients)];
+            bounds.extend(real_roots(&derivative(coefficients)));
+            bounds.push(cauchy_bound(coefficients));
+            let mut roots: Vec<f64> = Vec::new();
+            for pair in bounds.windows(2) {
+                if let Some(root) = bisect(coefficients, pair[0], pair[1]) {
+                    if roots.last().is_none_or(|last| *last != root) {
+                        roots.push(root);
+                    }
+                }
+            }
+            roots
+        }
+    }
+}
+
+// Written to avoid subtracting nearly equal numbers. See Numerical Recipes,
+// section 5.6
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    if discriminant == 0.0 {
+        return vec![-b / (2.0 * a)];
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (r1, r2) = if q == 0.0 {
+        // b is zero too, so the roots are symmetric about the origin
+        let r = (-c / a).sqrt();
+        (-r, r)
+    } else {
+        (q / a, c / q)
+    };
+    vec![r1.min(r2), r1.max(r2)]
+}
+
+// Every real root lies within this distance of zero
+fn cauchy_bound(coefficients: &[f64]) -> f64 {
+    let leading = coefficients[0];
+    1.0 + coefficients[1..]
+        .iter()
+        .fold(0.0_f64, |m, c| m.max((c / leading).abs()))
+}
+
+// The root between `lo` and `hi`, if the polynomial changes sign there
+fn bisect(coefficients: &[f64], mut lo: f64, mut hi: f64) -> Option<f64> {
+    let f_lo = evaluate(coefficients, lo);
+    let f_hi = evaluate(coefficients, hi);
+    if f_lo == 0.0 {
+        return Some(lo);
+    }
+    if f_hi == 0.0 {
+        return Some(hi);
+    }
+    if (f_lo < 0.0) == (f_hi < 0.0) {
+        return None;
+    }
+    let lo_negative = f_lo < 0.0;
+    for _ in 0..MAX_BISECTIONS {
+        let middle = lo + (hi - lo) / 2.0;
+        if middle <= lo || middle >= hi {
+            break;
+        }
+        let f = evaluate(coefficients, middle);
+        if f == 0.0 {
+            return Some(middle);
+        }
+        if (f < 0.0) == lo_negative {
+            lo = middle;
+        } else {
+            hi = middle;
+        }
+    }
+    Some(lo + (hi - lo) / 2.0)
+}
+
+#[cfg(test)]
+mod polynomials_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+
+    fn assert_roots(expected: &[f64], actual: Vec<f64>) {
+        assert_eq!(expected.len(), actual.len(), "{actual:?}");
+        for (e, a) in expected.iter().zip(actual) {
+            assert_nearly_eq(*e, a);
+        }
+    }
+
+    #[test]
+    fn polynomials_are_evaluated_highest_power_first() {
+        assert_eq!(21.0, evaluate(&[2.0, 3.0, 7.0], 2.0));
+    }
+
+    #[test]
+    fn linear_and_quadratic_roots() {
+        assert_roots(&[2.0], real_roots(&[3.0, -6.0]));
+        assert_roots(&[-3.0, 2.0], real_roots(&[1.0, 1.0, -6.0]));
+        assert_roots(&[], real_roots(&[1.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn a_quartic_with_four_roots() {
+        // (x + 3)(x + 1)(x - 2)(x - 5)
+        assert_roots(
+            &[-3.0, -1.0, 2.0, 5.0],
+            real_roots(&[1.0, -3.0, -15.0, 19.0, 30.0]),
+        );
+    }
+
+    #[test]
+    fn a_quartic_with_no_roots() {
+        assert_roots(&[], real_roots(&[1.0, 0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn nearly_equal_roots_are_told_apart() {
+        // (x - 1)(x - 1.001)(x - 10)(x + 4)
+        let a = [1.0, 1.001, 10.0, -4.0];
+        let coefficients = [
+            1.0,
+            -(a[0] + a[1] + a[2] + a[3]),
+            a[0] * a[1] + a[0] * a[2] + a[0] * a[3] + a[1] * a[2] + a[1] * a[3] + a[2] * a[3],
+            -(a[0] * a[1] * a[2] + a[0] * a[1] * a[3] + a[0] * a[2] * a[3] + a[1] * a[2] * a[3]),
+            a[0] * a[1] * a[2] * a[3],
+        ];
+        assert_roots(&[-4.0, 1.0, 1.001, 10.0], real_roots(&coefficients));
+    }
+
+    #[test]
+    fn a_vanishing_leading_coefficient_lowers_the_degree() {
+        assert_roots(&[-1.0, 1.0], real_roots(&[0.0, 0.0, 1.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn a_double_root_is_reported_once() {
+        // (x - 2)^2 (x + 1)
+        assert_roots(&[-1.0, 2.0], real_roots(&[1.0, -3.0, 0.0, 4.0]));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::polynomials;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// A general second order surface, the points where
+//   Ax² + By² + Cz² + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0
+// Positive values are outside, so normals point towards them. Many of these
+// surfaces go on forever, and can be clipped to a box to cut them down
+#[derive(PartialEq, Clone, Debug)]
+pub struct Quadric {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    coefficients: [f64; 10],
+    clip: Option<(Tup, Tup)>,
+}
+
+impl Quadric {
+    // The coefficients A to J, in order
+    pub fn new(coefficients: [f64; 10]) -> Self {
+        Self {
+            coefficients,
+            ..Self::default()
+        }
+    }
+
+    // An ellipsoid reaching `a`, `b` and `c` from the center along x, y and
+    // z
+    pub fn ellipsoid(a: f64, b: f64, c: f64) -> Self {
+        Self::new([
+            1.0 / (a * a),
+            1.0 / (b * b),
+            1.0 / (c * c),
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            -1.0,
+        ])
+    }
+
+    // A bowl opening upwards with its lowest point at the origin, y = x² + z².
+    // The reflector of a lamp or a satellite dish
+    pub fn paraboloid() -> Self {
+        Self::new([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0])
+    }
+
+    // An hourglass shaped surface around the y axis, x² + z² - y² = 1, with
+    // a waist of radius one
+    pub fn hyperboloid_of_one_sheet() -> Self {
+        Self::new([1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0])
+    }
+
+    // Two bowls facing away from each other along the y axis,
+    // y² - x² - z² = 1, with their tips one unit from the origin
+    pub fn hyperboloid_of_two_sheets() -> Self {
+        Self::new([-1.0, 1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0])
+    }
+
+    // An endless tube around the y axis, reaching `a` along x and `c` along
+    // z
+    pub fn elliptic_cylinder(a: f64, c: f64) -> Self {
+        Self::new([
+            1.0 / (a * a),
+            0.0,
+            1.0 / (c * c),
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            -1.0,
+        ])
+    }
+
+    // Only the parts of the surface inside the box from `min` to `max` are
+    // kept
+    pub fn with_clip(self, min: Tup, max: Tup) -> Self {
+        Self {
+            clip: Some((min, max)),
+            ..self
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn coefficients(&self) -> [f64; 10] {
+        self.coefficients
+    }
+
+    pub fn clip(&self) -> Option<(Tup, Tup)> {
+        self.clip
+    }
+
+    // The value of the surface's equation at `point`, zero on the surface
+    pub fn value_at(&self, point: Tup) -> f64 {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (x, y, z) = (point.x, point.y, point.z);
+        a * x * x
+            + b * y * y
+            + c * z * z
+            + d * x * y
+            + e * x * z
+            + f * y * z
+            + g * x
+            + h * y
+            + i * z
+            + j
+    }
+
+    fn is_kept(&self, point: Tup) -> bool {
+        match self.clip {
+            Some((min, max)) => {
+                (min.x..=max.x).contains(&point.x)
+                    && (min.y..=max.y).contains(&point.y)
+                    && (min.z..=max.z).contains(&point.z)
+            }
+            None => true,
+        }
+    }
+}
+
+// The unit sphere
+impl Default for Quadric {
+    fn default() -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            coefficients: [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            clip: None,
+        }
+    }
+}
+
+impl Shape for Quadric {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    // Substituting the ray into the equation leaves a quadratic in t. When
+    // the ray runs parallel to an axis of a paraboloid or the like it
+    // degenerates to a linear equation with a single root
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let o = local_ray.origin();
+        let v = local_ray.direction();
+        let quadratic = a * v.x * v.x
+            + b * v.y * v.y
+            + c * v.z * v.z
+            + d * v.x * v.y
+            + e * v.x * v.z
+            + f * v.y * v.z;
+        let linear = 2.0 * (a * o.x * v.x + b * o.y * v.y + c * o.z * v.z)
+            + d * (o.x * v.y + o.y * v.x)
+            + e * (o.x * v.z + o.z * v.x)
+            + f * (o.y * v.z + o.z * v.y)
+            + g * v.x
+            + h * v.y
+            + i * v.z;
+        let constant = self.value_at(o);
+        let xs: Vec<Intersection> = polynomials::real_roots(&[quadratic, linear, constant])
+            .into_iter()
+            .filter(|t| self.is_kept(local_ray.position(*t)))
+            .map(|t| Intersection::new(t, self.clone()))
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    // The gradient of the equation
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let (x, y, z) = (point.x, point.y, point.z);
+        Tup::vector(
+            2.0 * a * x + d * y + e * z + g,
+            2.0 * b * y + d * x + f * z + h,
+            2.0 * c * z + e * x + f * y + i,
+        )
+    }
+}
+
+#[cfg(test)]
+mod quadrics_test {
+    use super::*;
+    use crate::spheres::Sphere;
+    use crate::test_helpers::assert_nearly_eq;
+
+    fn ts(xs: &Intersections) -> Vec<f64> {
+        (0..xs.len()).map(|i| xs[i].t()).collect()
+    }
+
+    fn assert_ts(expected: &[f64], xs: Intersections) {
+        let actual = ts(&xs);
+        assert_eq!(expected.len(), actual.len(), "{actual:?}");
+        for (e, a) in expected.iter().zip(actual) {
+            assert_nearly_eq(*e, a);
+        }
+    }
+
+    #[test]
+    fn the_default_quadric_is_the_unit_sphere() {
+        let q = Quadric::default();
+        let ray = Ray::new(Tup::point(0.3, -0.2, -5.0), Tup::vector(0, 0, 1));
+        let expected = ts(&Sphere::default().intersect(&ray));
+        let actual = ts(&q.intersect(&ray));
+        assert_eq!(2, actual.len());
+        assert_nearly_eq(expected[0], actual[0]);
+        assert_nearly_eq(expected[1], actual[1]);
+        assert_eq!(
+            Tup::vector(0.0, 0.6, 0.8),
+            q.normal_at(Tup::point(0.0, 0.6, 0.8))
+        );
+    }
+
+    #[test]
+    fn an_ellipsoid_is_stretched_along_each_axis() {
+        let q = Quadric::ellipsoid(1.0, 2.0, 3.0);
+        let along_z = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert_ts(&[2.0, 8.0], q.intersect(&along_z));
+        let along_y = Ray::new(Tup::point(0, -5, 0), Tup::vector(0, 1, 0));
+        assert_ts(&[3.0, 7.0], q.intersect(&along_y));
+    }
+
+    #[test]
+    fn a_ray_down_the_axis_of_a_paraboloid_hits_it_once() {
+        let q = Quadric::paraboloid();
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert_ts(&[5.0], q.intersect(&ray));
+        assert_eq!(Tup::vector(0, -1, 0), q.normal_at(Tup::point(0, 0, 0)));
+    }
+
+    #[test]
+    fn a_ray_across_a_paraboloid_crosses_both_walls() {
+        let q = Quadric::paraboloid();
+        let ray = Ray::new(Tup::point(-5, 4, 0), Tup::vector(1, 0, 0));
+        assert_ts(&[3.0, 7.0], q.intersect(&ray));
+    }
+
+    #[test]
+    fn a_ray_through_the_waist_of_a_hyperboloid() {
+        let q = Quadric::hyperboloid_of_one_sheet();
+        let ray = Ray::new(Tup::point(-5, 0, 0), Tup::vector(1, 0, 0));
+        assert_ts(&[4.0, 6.0], q.intersect(&ray));
+        let down_the_middle = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert!(q.intersect(&down_the_middle).is_empty());
+    }
+
+    #[test]
+    fn a_ray_along_the_axis_of_a_hyperboloid_of_two_sheets() {
+        let q = Quadric::hyperboloid_of_two_sheets();
+        let ray = Ray::new(Tup::point(0, -5, 0), Tup::vector(0, 1, 0));
+        assert_ts(&[4.0, 6.0], q.intersect(&ray));
+    }
+
+    #[test]
+    fn an_elliptic_cylinder_goes_on_forever() {
+        let q = Quadric::elliptic_cylinder(2.0, 1.0);
+        let ray = Ray::new(Tup::point(-5, 1000, 0), Tup::vector(1, 0, 0));
+        assert_ts(&[3.0, 7.0], q.intersect(&ray));
+        let along_axis = Ray::new(Tup::point(0, -5, 0), Tup::vector(0, 1, 0));
+        assert!(q.intersect(&along_axis).is_empty());
+    }
+
+    #[test]
+    fn clipping_keeps_only_the_part_inside_the_box() {
+        let q = Quadric::elliptic_cylinder(1.0, 1.0)
+            .with_clip(Tup::point(-2, 0, -2), Tup::point(2, 1, 2));
+        let inside = Ray::new(Tup::point(-5.0, 0.5, 0.0), Tup::vector(1, 0, 0));
+        assert_eq!(2, q.intersect(&inside).len());
+        let above = Ray::new(Tup::point(-5.0, 1.5, 0.0), Tup::vector(1, 0, 0));
+        assert!(q.intersect(&above).is_empty());
+        // A slanted ray can enter the clipped tube and leave through its open
+        // top
+        let slanted = Ray::new(Tup::point(-3.0, -1.5, 0.0), Tup::vector(1, 1, 0));
+        assert_ts(&[2.0], q.intersect(&slanted));
+    }
+
+    #[test]
+    fn quadrics_are_only_equal_to_themselves() {
+        assert_ne!(Quadric::default(), Quadric::default());
+    }
+}
@@ -5,11 +5,34 @@ use crate::tup::Tup;
 pub struct Ray {
     origin: Tup,    // point
     direction: Tup, // vector
+    // In nanometers. Rays without a wavelength carry all colors at once
+    wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Tup, direction: Tup) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            ..self
+        }
+    }
+
+    // A ray that carries on from this one in a new direction, keeping its
+    // wavelength
+    pub fn redirected(&self, origin: Tup, direction: Tup) -> Self {
+        Self {
+            origin,
+            direction,
+            ..*self
+        }
     }
 
     pub fn origin(&self) -> Tup {
@@ -20,12 +43,16 @@ impl Ray {
         self.direction
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn position(&self, distance: f64) -> Tup {
         self.direction() * distance + self.origin()
     }
 
     pub fn transform(&self, mat: &Mat4) -> Self {
-        Self::new(*mat * self.origin(), *mat * self.direction())
+        self.redirected(*mat * self.origin(), *mat * self.direction())
     }
 }
 
@@ -90,4 +117,26 @@ mod rays_test {
         let r2 = ray.transform(&m);
         assert_eq!(Tup::vector(0, 3, 0), r2.direction());
     }
+
+    #[test]
+    fn a_ray_has_no_wavelength_by_default() {
+        let ray = Ray::new(Tup::point(1, 2, 3), Tup::vector(0, 1, 0));
+        assert_eq!(None, ray.wavelength());
+    }
+
+    #[test]
+    fn a_redirected_ray_keeps_its_wavelength() {
+        let ray = Ray::new(Tup::point(1, 2, 3), Tup::vector(0, 1, 0)).with_wavelength(550.0);
+        let redirected = ray.redirected(Tup::point(0, 0, 0), Tup::vector(1, 0, 0));
+        assert_eq!(Tup::point(0, 0, 0), redirected.origin());
+        assert_eq!(Tup::vector(1, 0, 0), redirected.direction());
+        assert_eq!(Some(550.0), redirected.wavelength());
+    }
+
+    #[test]
+    fn transforming_a_ray_keeps_its_wavelength() {
+        let ray = Ray::new(Tup::point(1, 2, 3), Tup::vector(0, 1, 0)).with_wavelength(450.0);
+        let r2 = ray.transform(&transforms::translation(3, 4, 5));
+        assert_eq!(Some(450.0), r2.wavelength());
+    }
 }
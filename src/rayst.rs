@@ -1,17 +1,49 @@
+// The tests build single hit lists with `&[i.clone()]`, which is clearer
+// than the `std::slice::from_ref` clippy would have instead
+#![cfg_attr(test, allow(clippy::cloned_ref_to_slice_refs))]
+
+pub mod background;
+pub mod bezier;
+pub mod bounds;
+pub mod bump;
+pub mod bvh;
 pub mod camera;
 pub mod canvas;
 pub mod color;
+pub mod disks;
+pub mod groups;
+pub mod hdr;
+pub mod heightfields;
+pub mod instances;
 pub mod intersections;
+pub mod irradiance;
 pub mod lights;
 pub mod materials;
 pub mod math_helpers;
 pub mod matrix;
+pub mod media;
+pub mod meshes;
+pub mod microfacet;
+pub mod noise;
+pub mod path_tracer;
 pub mod patterns;
 pub mod planes;
+pub mod ply;
+pub mod polynomials;
+pub mod quadrics;
 pub mod rays;
+pub mod rectangles;
+pub mod sampling;
+pub mod sdf;
 pub mod shapes;
+pub mod spectrum;
 pub mod spheres;
+pub mod stl;
+pub mod subsurface;
 pub mod test_helpers;
+pub mod tori;
 pub mod transforms;
+pub mod triangles;
 pub mod tup;
+pub mod volumes;
 pub mod world;
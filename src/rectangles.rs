@@ -0,0 +1,175 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// A flat rectangle in the xz plane centered on the origin, facing up.
+// `width` runs along x and `height` along z
+#[derive(PartialEq, Clone, Debug)]
+pub struct Rectangle {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    width: f64,
+    height: f64,
+}
+
+impl Rectangle {
+    pub fn new(width: f64, height: f64) -> Self {
+        Self {
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+}
+
+// A two by two square, as wide as the default sphere
+impl Default for Rectangle {
+    fn default() -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            width: 2.0,
+            height: 2.0,
+        }
+    }
+}
+
+impl Shape for Rectangle {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        if local_ray.direction().y.abs() < EPSILON {
+            return Intersections::default();
+        }
+        let t = -local_ray.origin().y / local_ray.direction().y;
+        let point = local_ray.position(t);
+        if point.x.abs() > self.width / 2.0 || point.z.abs() > self.height / 2.0 {
+            Intersections::default()
+        } else {
+            Intersections::new(&[Intersection::new(t, self.clone())])
+        }
+    }
+
+    fn local_normal_at(&self, _point: Tup) -> Tup {
+        Tup::vector(0, 1, 0)
+    }
+
+    // The whole image is stretched over the rectangle, with its top towards
+    // positive z like on a plane
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        (point.x / self.width + 0.5, 0.5 - point.z / self.height)
+    }
+
+    fn local_tangent_at(&self, _point: Tup) -> Tup {
+        Tup::vector(1, 0, 0)
+    }
+}
+
+#[cfg(test)]
+mod rectangles_test {
+    use super::*;
+    use crate::transforms;
+
+    #[test]
+    fn a_rectangle_is_a_two_by_two_square_by_default() {
+        let r = Rectangle::default();
+        assert_eq!(2.0, r.width());
+        assert_eq!(2.0, r.height());
+    }
+
+    #[test]
+    fn a_ray_can_hit_a_rectangle() {
+        let r = Rectangle::new(4.0, 1.0);
+        let ray = Ray::new(Tup::point(1.9, -2.0, 0.4), Tup::vector(0, 1, 0));
+        let xs = r.local_intersect(ray);
+        assert_eq!(1, xs.len());
+        assert_eq!(2.0, xs[0].t());
+    }
+
+    #[test]
+    fn rays_beyond_the_edges_miss_a_rectangle() {
+        let r = Rectangle::new(4.0, 1.0);
+        for origin in [Tup::point(2.1, 1.0, 0.0), Tup::point(0.0, 1.0, -0.6)] {
+            let ray = Ray::new(origin, Tup::vector(0, -1, 0));
+            assert!(r.local_intersect(ray).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_ray_parallel_to_a_rectangle_misses_it() {
+        let r = Rectangle::default();
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert!(r.local_intersect(ray).is_empty());
+    }
+
+    #[test]
+    fn a_rectangle_can_hang_on_a_wall() {
+        let r = Rectangle::new(1.0, 1.0)
+            .with_transform(transforms::rotation_x(-std::f64::consts::FRAC_PI_2));
+        let ray = Ray::new(Tup::point(0.2, 0.3, -5.0), Tup::vector(0, 0, 1));
+        let xs = r.intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_eq!(
+            Tup::vector(0, 0, -1),
+            r.normal_at(Tup::point(0.2, 0.3, 0.0))
+        );
+    }
+
+    #[test]
+    fn an_image_covers_the_whole_rectangle() {
+        let r = Rectangle::new(4.0, 2.0);
+        assert_eq!((0.0, 0.0), r.local_uv_at(Tup::point(-2, 0, 1)));
+        assert_eq!((1.0, 1.0), r.local_uv_at(Tup::point(2, 0, -1)));
+        assert_eq!((0.5, 0.5), r.local_uv_at(Tup::point(0, 0, 0)));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::tup::Tup;
+use std::f64::consts::PI;
+
+// A small, fast pseudo random number generator (SplitMix64). Renders are
+// deterministic because every pixel seeds its own generator
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Seeds a generator from the position of a point so that sampling at a
+    // given surface location is repeatable from render to render
+    pub fn for_point(point: Tup) -> Self {
+        let seed = [point.x, point.y, point.z]
+            .iter()
+            .fold(0_u64, |seed, c| Self::new(seed ^ c.to_bits()).next_u64());
+        Self::new(seed)
+    }
+
+    // Uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+// Two unit vectors that together with `normal` form an orthonormal basis.
+// See Duff et al., "Building an Orthonormal Basis, Revisited"
+pub fn orthonormal_basis(normal: Tup) -> (Tup, Tup) {
+    let sign = 1.0_f64.copysign(normal.z);
+    let a = -1.0 / (sign + normal.z);
+    let b = normal.x * normal.y * a;
+    let tangent = Tup::vector(
+        1.0 + sign * normal.x * normal.x * a,
+        sign * b,
+        -sign * normal.x,
+    );
+    let bitangent = Tup::vector(b, sign + normal.y * normal.y * a, -normal.y);
+    (tangent, bitangent)
+}
+
+// A direction in the hemisphere around `normal` with probability
+// proportional to the cosine of its angle to the normal, which is exactly
+// the distribution of light leaving a perfectly diffuse surface
+pub fn cosine_weighted_hemisphere(normal: Tup, rng: &mut Rng) -> Tup {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let r = rng.next_f64().sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    let x = r * phi.cos();
+    let y = r * phi.sin();
+    let z = (1.0 - r * r).max(0.0).sqrt();
+    (tangent * x + bitangent * y + normal * z).normalize()
+}
+
+// A direction chosen uniformly from all directions
+pub fn uniform_sphere(rng: &mut Rng) -> Tup {
+    let z = 1.0 - 2.0 * rng.next_f64();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    Tup::vector(r * phi.cos(), r * phi.sin(), z)
+}
+
+// A direction uniformly distributed over the solid angle of the cone around
+// `axis` with the given half angle
+pub fn uniform_cone(axis: Tup, half_angle: f64, rng: &mut Rng) -> Tup {
+    let axis = axis.normalize();
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    let cos_max = half_angle.min(PI).cos();
+    let cos_theta = 1.0 - rng.next_f64() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta)
+        .normalize()
+}
+
+#[cfg(test)]
+mod sampling_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+
+    #[test]
+    fn generators_with_the_same_seed_produce_the_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn generators_with_different_seeds_produce_different_numbers() {
+        let mut a = Rng::new(1);
+        let mut b = Rng::new(2);
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn generators_seeded_from_the_same_point_produce_the_same_numbers() {
+        let mut a = Rng::for_point(Tup::point(1.0, -2.0, 0.5));
+        let mut b = Rng::for_point(Tup::point(1.0, -2.0, 0.5));
+        let mut c = Rng::for_point(Tup::point(1.0, -2.0, 0.50001));
+        let first = a.next_u64();
+        assert_eq!(first, b.next_u64());
+        assert_ne!(first, c.next_u64());
+    }
+
+    #[test]
+    fn random_floats_are_in_the_unit_interval() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+
+    #[test]
+    fn random_floats_are_evenly_spread() {
+        let mut rng = Rng::new(7);
+        let n = 10000;
+        let mean = (0..n).map(|_| rng.next_f64()).sum::<f64>() / n as f64;
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn an_orthonormal_basis_is_perpendicular_to_the_normal() {
+        let normal = Tup::vector(1, 2, -3).normalize();
+        let (t, b) = orthonormal_basis(normal);
+        assert_nearly_eq(0.0, t.dot(&normal));
+        assert_nearly_eq(0.0, b.dot(&normal));
+        assert_nearly_eq(0.0, t.dot(&b));
+        assert_nearly_eq(1.0, t.magnitude());
+        assert_nearly_eq(1.0, b.magnitude());
+    }
+
+    #[test]
+    fn an_orthonormal_basis_handles_a_normal_down_the_negative_z_axis() {
+        let normal = Tup::vector(0, 0, -1);
+        let (t, b) = orthonormal_basis(normal);
+        assert_nearly_eq(0.0, t.dot(&normal));
+        assert_nearly_eq(0.0, b.dot(&normal));
+        assert_nearly_eq(1.0, t.magnitude());
+    }
+
+    #[test]
+    fn cosine_weighted_samples_are_unit_vectors_above_the_surface() {
+        let normal = Tup::vector(0, 1, 0);
+        let mut rng = Rng::new(3);
+        for _ in 0..1000 {
+            let d = cosine_weighted_hemisphere(normal, &mut rng);
+            assert_nearly_eq(1.0, d.magnitude());
+            assert!(d.dot(&normal) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_samples_favor_the_normal() {
+        // The expected cosine under a cosine weighted distribution is 2/3
+        let normal = Tup::vector(1, 0, 0);
+        let mut rng = Rng::new(11);
+        let n = 10000;
+        let mean_cos = (0..n)
+            .map(|_| cosine_weighted_hemisphere(normal, &mut rng).dot(&normal))
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn cone_samples_stay_within_the_cone() {
+        let axis = Tup::vector(0, 1, 1).normalize();
+        let half_angle = 0.3_f64;
+        let mut rng = Rng::new(5);
+        for _ in 0..1000 {
+            let d = uniform_cone(axis, half_angle, &mut rng);
+            assert_nearly_eq(1.0, d.magnitude());
+            assert!(d.dot(&axis) >= half_angle.cos() - 1e-9);
+        }
+    }
+
+    #[test]
+    fn a_cone_with_no_width_is_its_axis() {
+        let axis = Tup::vector(1, 0, 0);
+        let mut rng = Rng::new(5);
+        assert_eq!(axis, uniform_cone(axis, 0.0, &mut rng));
+    }
+
+    #[test]
+    fn sphere_samples_are_unit_vectors_spread_in_every_direction() {
+        let mut rng = Rng::new(9);
+        let n = 10000;
+        let samples: Vec<Tup> = (0..n).map(|_| uniform_sphere(&mut rng)).collect();
+        assert!(samples.iter().all(|d| (d.magnitude() - 1.0).abs() < 1e-9));
+        let mean = samples.iter().fold(Tup::vector(0, 0, 0), |sum, d| sum + *d) * (1.0 / n as f64);
+        assert!(mean.magnitude() < 0.03);
+    }
+}
@@ -0,0 +1,517 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// A shape described by a signed distance function: the distance from a
+// point to the nearest point on the surface, negative inside. Rays are
+// intersected by sphere tracing, stepping forward by the distance to the
+// surface, which can never overshoot it. Anything with a distance function
+// can be rendered this way, including smooth blends of shapes and fractals.
+// The functions below build distance functions and combine them
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+type DistanceFn = Arc<dyn Fn(Tup) -> f64 + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Sdf {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    distance: DistanceFn,
+    bound: f64,
+    step_scale: f64,
+}
+
+impl Sdf {
+    // Marching stops after this many steps, in case a ray creeps along a
+    // surface without ever crossing it
+    const MAX_STEPS: usize = 512;
+
+    // The smallest step taken, which also decides how thin a sliver of the
+    // shape a ray can pass through unnoticed
+    const MIN_STEP: f64 = 1e-4;
+
+    // Halvings of the last step once it crosses the surface
+    const BISECTIONS: usize = 40;
+
+    // Spacing of the samples used to find the gradient
+    const NORMAL_DELTA: f64 = 1e-5;
+
+    // `distance` takes a point in object space
+    pub fn new<F: Fn(Tup) -> f64 + Send + Sync + 'static>(distance: F) -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            distance: Arc::new(distance),
+            bound: 10.0,
+            step_scale: 1.0,
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    // The radius of a sphere around the origin that holds the whole shape.
+    // Only that sphere is marched through, so tighter bounds are faster
+    pub fn with_bound(self, bound: f64) -> Self {
+        Self { bound, ..self }
+    }
+
+    // Distance functions that aren't exact, like twisted or fractal shapes,
+    // can overstate the distance to the surface. Scaling every step down by
+    // this factor keeps rays from jumping through such surfaces
+    pub fn with_step_scale(self, step_scale: f64) -> Self {
+        Self { step_scale, ..self }
+    }
+
+    pub fn bound(&self) -> f64 {
+        self.bound
+    }
+
+    pub fn step_scale(&self) -> f64 {
+        self.step_scale
+    }
+
+    pub fn distance(&self, point: Tup) -> f64 {
+        (self.distance)(point)
+    }
+
+    // The span of t in which `ray` is inside the bounding sphere
+    fn bounding_span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let to_ray = ray.origin() - Tup::point(0, 0, 0);
+        let a = ray.direction().dot(&ray.direction());
+        let b = 2.0 * ray.direction().dot(&to_ray);
+        let c = to_ray.dot(&to_ray) - self.bound * self.bound;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            Some(((-b - root) / (2.0 * a), (-b + root) / (2.0 * a)))
+        }
+    }
+
+    // Narrows down where the distance changes sign between `t1` and `t2`
+    fn refine(&self, ray: &Ray, mut t1: f64, mut t2: f64) -> f64 {
+        let inside = |t: f64| self.distance(ray.position(t)) < 0.0;
+        let inside_at_t1 = inside(t1);
+        for _ in 0..Self::BISECTIONS {
+            let middle = (t1 + t2) / 2.0;
+            if inside(middle) == inside_at_t1 {
+                t1 = middle;
+            } else {
+                t2 = middle;
+            }
+        }
+        (t1 + t2) / 2.0
+    }
+}
+
+impl Shape for Sdf {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    // Every crossing of the surface along the ray is an intersection, both
+    // in front of and behind the origin, like the analytic shapes
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let (start, end) = match self.bounding_span(&local_ray) {
+            Some(span) => span,
+            None => return Intersections::default(),
+        };
+        let speed = local_ray.direction().magnitude();
+        let mut xs = Vec::new();
+        let mut t = start;
+        let mut d = self.distance(local_ray.position(t));
+        for _ in 0..Self::MAX_STEPS {
+            if t >= end {
+                break;
+            }
+            let step = (d.abs() * self.step_scale).max(Self::MIN_STEP) / speed;
+            let next = (t + step).min(end);
+            let next_d = self.distance(local_ray.position(next));
+            if (next_d < 0.0) != (d < 0.0) {
+                xs.push(Intersection::new(
+                    self.refine(&local_ray, t, next),
+                    self.clone(),
+                ));
+            }
+            t = next;
+            d = next_d;
+        }
+        Intersections::new(&xs)
+    }
+
+    // The gradient of the distance, by central differences
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let h = Self::NORMAL_DELTA;
+        let slope = |offset: Tup| self.distance(point + offset) - self.distance(point - offset);
+        let gradient = Tup::vector(
+            slope(Tup::vector(h, 0.0, 0.0)),
+            slope(Tup::vector(0.0, h, 0.0)),
+            slope(Tup::vector(0.0, 0.0, h)),
+        );
+        // Estimated distances, like those of fractals, can change far slower
+        // than the true distance, leaving a difference too short for
+        // `normalize`. Its length is divided out directly instead
+        let length = gradient.magnitude();
+        if length > 0.0 {
+            gradient * (1.0 / length)
+        } else {
+            // Flat spots have no slope to follow, any direction will do
+            Tup::vector(0, 1, 0)
+        }
+    }
+}
+
+// The distance function can't be printed or compared, so shapes go by
+// their id like spheres do
+impl fmt::Debug for Sdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sdf")
+            .field("id", &self.id)
+            .field("transform", &self.transform)
+            .field("material", &self.material)
+            .field("bound", &self.bound)
+            .field("step_scale", &self.step_scale)
+            .finish()
+    }
+}
+
+impl PartialEq for Sdf {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+fn length(x: f64, y: f64, z: f64) -> f64 {
+    (x * x + y * y + z * z).sqrt()
+}
+
+// Primitives, each centered on the origin
+
+pub fn sphere(radius: f64) -> impl Fn(Tup) -> f64 {
+    move |p| length(p.x, p.y, p.z) - radius
+}
+
+// A ring lying in the xz plane. `major` is the distance from the center to
+// the middle of the tube and `minor` the radius of the tube
+pub fn torus(major: f64, minor: f64) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - major;
+        (ring * ring + p.y * p.y).sqrt() - minor
+    }
+}
+
+// A box reaching `half_extents` from the center along each axis, with its
+// edges and corners rounded off to `radius`
+pub fn rounded_box(half_extents: Tup, radius: f64) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let qx = p.x.abs() - half_extents.x + radius;
+        let qy = p.y.abs() - half_extents.y + radius;
+        let qz = p.z.abs() - half_extents.z + radius;
+        let outside = length(qx.max(0.0), qy.max(0.0), qz.max(0.0));
+        let inside = qx.max(qy).max(qz).min(0.0);
+        outside + inside - radius
+    }
+}
+
+// A cylinder from `a` to `b` with hemispherical caps
+pub fn capsule(a: Tup, b: Tup, radius: f64) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let pa = p - a;
+        let ba = b - a;
+        let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0.0, 1.0);
+        (pa - ba * h).magnitude() - radius
+    }
+}
+
+// The Mandelbulb fractal, which fits inside a sphere of radius 1.2 for the
+// classic power of 8. The distance is only an estimate, so shapes using it
+// need a step scale below one
+pub fn mandelbulb(power: f64, iterations: usize) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let (mut x, mut y, mut z) = (p.x, p.y, p.z);
+        let mut dr = 1.0;
+        let mut r = length(x, y, z);
+        for _ in 0..iterations {
+            if r > 2.0 || r == 0.0 {
+                break;
+            }
+            let theta = (z / r).acos() * power;
+            let phi = y.atan2(x) * power;
+            dr = r.powf(power - 1.0) * power * dr + 1.0;
+            let zr = r.powf(power);
+            x = zr * theta.sin() * phi.cos() + p.x;
+            y = zr * theta.sin() * phi.sin() + p.y;
+            z = zr * theta.cos() + p.z;
+            r = length(x, y, z);
+        }
+        if r == 0.0 {
+            return -1.0;
+        }
+        0.5 * r.ln() * r / dr
+    }
+}
+
+// Operators, combining or distorting other distance functions
+
+pub fn union(a: impl Fn(Tup) -> f64, b: impl Fn(Tup) -> f64) -> impl Fn(Tup) -> f64 {
+    move |p| a(p).min(b(p))
+}
+
+pub fn intersection(a: impl Fn(Tup) -> f64, b: impl Fn(Tup) -> f64) -> impl Fn(Tup) -> f64 {
+    move |p| a(p).max(b(p))
+}
+
+// `a` with `b` carved out of it
+pub fn subtract(a: impl Fn(Tup) -> f64, b: impl Fn(Tup) -> f64) -> impl Fn(Tup) -> f64 {
+    move |p| a(p).max(-b(p))
+}
+
+// Like `union`, but the shapes melt into each other where they are within
+// `k` of touching
+pub fn smooth_union(a: impl Fn(Tup) -> f64, b: impl Fn(Tup) -> f64, k: f64) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let (da, db) = (a(p), b(p));
+        let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+        db + (da - db) * h - k * h * (1.0 - h)
+    }
+}
+
+// Twists `a` around the y axis by `rate` radians per unit of height. The
+// result overstates distances, more so the faster the twist
+pub fn twist(a: impl Fn(Tup) -> f64, rate: f64) -> impl Fn(Tup) -> f64 {
+    move |p| {
+        let angle = rate * p.y;
+        let (sin, cos) = angle.sin_cos();
+        a(Tup::point(
+            cos * p.x - sin * p.z,
+            p.y,
+            sin * p.x + cos * p.z,
+        ))
+    }
+}
+
+// Copies of `a` repeated forever every `period` along each axis. A period
+// of zero leaves that axis alone. Copies should fit within their cell
+pub fn repeat(a: impl Fn(Tup) -> f64, period: Tup) -> impl Fn(Tup) -> f64 {
+    let wrap = |c: f64, period: f64| {
+        if period > 0.0 {
+            c - period * (c / period).round()
+        } else {
+            c
+        }
+    };
+    move |p| {
+        a(Tup::point(
+            wrap(p.x, period.x),
+            wrap(p.y, period.y),
+            wrap(p.z, period.z),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod sdf_test {
+    use super::*;
+    use crate::spheres::Sphere;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+
+    #[test]
+    fn primitives_are_zero_on_their_surface() {
+        assert_nearly_eq(0.0, sphere(2.0)(Tup::point(0, 2, 0)));
+        assert_nearly_eq(0.0, torus(1.0, 0.25)(Tup::point(1.25, 0.0, 0.0)));
+        assert_nearly_eq(
+            0.0,
+            rounded_box(Tup::vector(1.0, 2.0, 3.0), 0.1)(Tup::point(1, 0, 0)),
+        );
+        assert_nearly_eq(
+            0.0,
+            capsule(Tup::point(0, 0, 0), Tup::point(0, 2, 0), 0.5)(Tup::point(0.0, 2.5, 0.0)),
+        );
+    }
+
+    #[test]
+    fn primitives_are_negative_inside_and_positive_outside() {
+        let t = torus(1.0, 0.25);
+        assert!(t(Tup::point(0, 0, 0)) > 0.0);
+        assert!(t(Tup::point(0, 0, 1)) < 0.0);
+        let b = rounded_box(Tup::vector(1, 1, 1), 0.2);
+        assert_nearly_eq(-1.0, b(Tup::point(0, 0, 0)));
+        assert_nearly_eq(1.0, b(Tup::point(2, 0, 0)));
+        // The rounded corner sits inside the sharp one
+        assert!(b(Tup::point(0.98, 0.98, 0.98)) > 0.0);
+    }
+
+    #[test]
+    fn subtracting_carves_one_shape_out_of_another() {
+        let hollow = subtract(sphere(1.0), sphere(0.5));
+        assert!(hollow(Tup::point(0, 0, 0)) > 0.0);
+        assert!(hollow(Tup::point(0.75, 0.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn a_smooth_union_fills_in_between_shapes() {
+        let a = |p: Tup| sphere(1.0)(p - Tup::vector(-1.2, 0.0, 0.0));
+        let b = |p: Tup| sphere(1.0)(p - Tup::vector(1.2, 0.0, 0.0));
+        let between = Tup::point(0, 0, 0);
+        assert!(union(a, b)(between) > 0.0);
+        assert!(smooth_union(a, b, 1.0)(between) < 0.0);
+        let far = Tup::point(0.0, 0.0, -5.0);
+        assert!(smooth_union(a, b, 1.0)(far) <= union(a, b)(far));
+    }
+
+    #[test]
+    fn repeating_makes_copies_every_period() {
+        let r = repeat(sphere(0.5), Tup::vector(3.0, 0.0, 3.0));
+        assert_nearly_eq(-0.5, r(Tup::point(3, 0, -6)));
+        assert_nearly_eq(-0.5, r(Tup::point(0, 0, 0)));
+        assert_nearly_eq(0.5, r(Tup::point(0, 1, 3)));
+    }
+
+    #[test]
+    fn twisting_turns_the_shape_with_height() {
+        let bar = rounded_box(Tup::vector(1.0, 5.0, 0.1), 0.0);
+        let twisted = twist(bar, std::f64::consts::FRAC_PI_2);
+        assert!(twisted(Tup::point(0.9, 0.0, 0.0)) < 0.0);
+        // A quarter turn up the bar lies along z instead
+        assert!(twisted(Tup::point(0.9, 1.0, 0.0)) > 0.0);
+        assert!(twisted(Tup::point(0.0, 1.0, 0.9)) < 0.0);
+    }
+
+    #[test]
+    fn a_distance_field_sphere_matches_an_analytic_one() {
+        let ray = Ray::new(Tup::point(0.3, 0.2, -5.0), Tup::vector(0, 0, 1));
+        let xs = Sdf::new(sphere(1.0)).intersect(&ray);
+        let expected = Sphere::default().intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(expected[0].t(), xs[0].t());
+        assert_nearly_eq(expected[1].t(), xs[1].t());
+    }
+
+    #[test]
+    fn a_ray_can_miss_a_distance_field_shape() {
+        let s = Sdf::new(torus(1.0, 0.25));
+        // Straight down through the hole in the middle
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert!(s.intersect(&ray).is_empty());
+        // Outside the bounding sphere altogether
+        let ray = Ray::new(Tup::point(0, 20, 0), Tup::vector(0, 0, 1));
+        assert!(s.intersect(&ray).is_empty());
+    }
+
+    #[test]
+    fn every_crossing_of_a_torus_is_found() {
+        let s = Sdf::new(torus(1.0, 0.25));
+        let ray = Ray::new(Tup::point(-5, 0, 0), Tup::vector(1, 0, 0));
+        let xs = s.intersect(&ray);
+        assert_eq!(4, xs.len());
+        assert_nearly_eq(3.75, xs[0].t());
+        assert_nearly_eq(4.25, xs[1].t());
+        assert_nearly_eq(5.75, xs[2].t());
+        assert_nearly_eq(6.25, xs[3].t());
+    }
+
+    #[test]
+    fn intersections_behind_the_ray_are_found_too() {
+        let s = Sdf::new(sphere(1.0));
+        let ray = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, 0, 1));
+        let xs = s.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(-1.0, xs[0].t());
+        assert_nearly_eq(1.0, xs[1].t());
+    }
+
+    #[test]
+    fn the_normal_is_the_gradient_of_the_distance() {
+        let s = Sdf::new(sphere(1.0));
+        let n = s.normal_at(Tup::point(0.0, 0.6, 0.8));
+        assert_eq!(Tup::vector(0.0, 0.6, 0.8), n);
+        let b = Sdf::new(rounded_box(Tup::vector(1, 1, 1), 0.1));
+        assert_eq!(
+            Tup::vector(1, 0, 0),
+            b.normal_at(Tup::point(1.0, 0.2, -0.3))
+        );
+    }
+
+    #[test]
+    fn slowly_changing_distances_still_give_unit_normals() {
+        let ball = sphere(1.0);
+        let s = Sdf::new(move |p| ball(p) * 0.1);
+        let n = s.normal_at(Tup::point(0.0, 0.6, 0.8));
+        assert_eq!(Tup::vector(0.0, 0.6, 0.8), n);
+        let flat = Sdf::new(|_| 1.0);
+        assert_nearly_eq(1.0, flat.normal_at(Tup::point(1, 2, 3)).magnitude());
+    }
+
+    #[test]
+    fn distance_field_shapes_can_be_transformed() {
+        let s = Sdf::new(sphere(1.0)).with_transform(transforms::translation(0, 0, 3));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let xs = s.intersect(&ray);
+        assert_nearly_eq(7.0, xs[0].t());
+        let s = Sdf::new(sphere(1.0)).with_transform(transforms::scaling(2, 2, 2));
+        let xs = s.intersect(&ray);
+        assert_nearly_eq(3.0, xs[0].t());
+    }
+
+    #[test]
+    fn the_mandelbulb_can_be_hit() {
+        let s = Sdf::new(mandelbulb(8.0, 8))
+            .with_bound(1.5)
+            .with_step_scale(0.5);
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let hit = s
+            .intersect(&ray)
+            .hit()
+            .map(|i| i.t())
+            .expect("Hits the bulb");
+        assert!(hit > 3.8 && hit < 5.0, "{hit}");
+    }
+
+    #[test]
+    fn shapes_are_only_equal_to_themselves() {
+        let a = Sdf::new(sphere(1.0));
+        let b = Sdf::new(sphere(1.0));
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+    }
+}
@@ -2,10 +2,13 @@ use crate::intersections::Intersections;
 use crate::materials::Material;
 use crate::matrix::Mat4;
 use crate::rays::Ray;
+use crate::sampling;
 use crate::tup::Tup;
 use std::fmt::Debug;
 
-pub trait Shape: ShapeClone + Debug {
+// Shapes can be sent between threads so groups and instances can share them
+// through `Arc`
+pub trait Shape: ShapeClone + Debug + Send + Sync {
     fn transform(&self) -> Mat4;
 
     fn set_transform(&mut self, transform: Mat4);
@@ -14,6 +17,17 @@ pub trait Shape: ShapeClone + Debug {
 
     fn set_material(&mut self, material: Material);
 
+    // The material the shape was given, if it was given one. Shapes without
+    // one are drawn in the default material, or in that of the group they
+    // belong to
+    fn own_material(&self) -> Option<Material>;
+
+    // Takes points in the world to the space the shape's surface is defined
+    // in. For shapes made of others, that of the one that was hit
+    fn world_to_object(&self) -> Mat4 {
+        self.transform().inverse()
+    }
+
     fn intersect(&self, ray: &Ray) -> Intersections {
         let local_ray = ray.transform(&self.transform().inverse());
         self.local_intersect(local_ray)
@@ -32,6 +46,33 @@ pub trait Shape: ShapeClone + Debug {
     }
 
     fn local_normal_at(&self, point: Tup) -> Tup;
+
+    // Texture coordinates of a point on the surface, used to look up normal
+    // maps. Shapes without a natural parameterization map everything to the
+    // origin
+    fn uv_at(&self, point: Tup) -> (f64, f64) {
+        self.local_uv_at(self.transform().inverse() * point)
+    }
+
+    fn local_uv_at(&self, _point: Tup) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    // A unit vector along the surface pointing in the direction of
+    // increasing u, which orients tangent space normal maps. Tangents are
+    // carried along with the surface by the transform, unlike normals
+    fn tangent_at(&self, point: Tup) -> Tup {
+        let local_tangent = self.local_tangent_at(self.transform().inverse() * point);
+        let world_tangent = self.transform() * local_tangent;
+        Tup::vector(world_tangent.x, world_tangent.y, world_tangent.z).normalize()
+    }
+
+    // Any direction along the surface will do for shapes without texture
+    // coordinates
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        let (tangent, _) = sampling::orthonormal_basis(self.local_normal_at(point).normalize());
+        tangent
+    }
 }
 
 pub trait ShapeClone {
@@ -56,6 +97,7 @@ impl Clone for Box<dyn Shape> {
 #[cfg(test)]
 mod shape_tests {
     use super::*;
+    use crate::test_helpers::assert_nearly_eq;
     use crate::transforms;
     use core::f64::consts;
 
@@ -77,13 +119,17 @@ mod shape_tests {
         }
 
         fn material(&self) -> Material {
-            self.material.unwrap_or_default()
+            self.material.clone().unwrap_or_default()
         }
 
         fn set_material(&mut self, material: Material) {
             self.material = Some(material);
         }
 
+        fn own_material(&self) -> Option<Material> {
+            self.material.clone()
+        }
+
         fn local_intersect(&self, local_ray: Ray) -> Intersections {
             unsafe {
                 SAVED_RAY = Some(local_ray);
@@ -120,7 +166,7 @@ mod shape_tests {
     fn a_material_should_be_assignable_to_a_shape() {
         let mut shape = TestShape::default();
         let material = Material::default().with_ambient(1.0);
-        shape.set_material(material);
+        shape.set_material(material.clone());
         assert_eq!(material, shape.material());
     }
 
@@ -184,4 +230,21 @@ mod shape_tests {
         ));
         assert_eq!(Tup::vector(0.0, 0.97014, -0.24254), n);
     }
+
+    #[test]
+    fn shapes_have_a_tangent_along_their_surface() {
+        let shape = TestShape::default();
+        let point = Tup::point(0.0, 0.6, 0.8);
+        let t = shape.tangent_at(point);
+        assert_nearly_eq(1.0, t.magnitude());
+        assert_nearly_eq(0.0, t.dot(&shape.normal_at(point)));
+    }
+
+    #[test]
+    fn tangents_are_transformed_with_the_shape() {
+        let mut shape = TestShape::default();
+        shape.set_transform(transforms::rotation_z(consts::FRAC_PI_2));
+        let t = shape.tangent_at(Tup::point(-1, 0, 0));
+        assert_nearly_eq(0.0, t.dot(&shape.normal_at(Tup::point(-1, 0, 0))));
+    }
 }
@@ -0,0 +1,293 @@
+use crate::color::Color;
+
+// Support for rendering with individual wavelengths of light instead of red,
+// green and blue. Colors are turned into spectra by upsampling, and radiance
+// sampled at a set of wavelengths is turned back into a color through the
+// CIE 1931 color matching functions and the XYZ to linear sRGB matrix
+
+// The visible range covered by spectra, in nanometers
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 730.0;
+
+const SAMPLE_COUNT: usize = 36;
+const SAMPLE_SPACING: f64 = (MAX_WAVELENGTH - MIN_WAVELENGTH) / (SAMPLE_COUNT - 1) as f64;
+
+// A reflectance or emission spectrum sampled every 10nm across the visible
+// range
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Spectrum {
+    samples: [f64; SAMPLE_COUNT],
+}
+
+impl Spectrum {
+    pub fn constant(value: f64) -> Self {
+        Self {
+            samples: [value; SAMPLE_COUNT],
+        }
+    }
+
+    // Samples `f`, a function of the wavelength in nanometers
+    pub fn from_fn(f: impl Fn(f64) -> f64) -> Self {
+        let mut samples = [0.0; SAMPLE_COUNT];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = f(Self::wavelength_of_sample(i));
+        }
+        Self { samples }
+    }
+
+    // Values at evenly spaced wavelengths from `start` to `end`. Outside that
+    // range the nearest value is used
+    pub fn from_samples(start: f64, end: f64, values: &[f64]) -> Self {
+        match values {
+            [] => Self::constant(0.0),
+            [value] => Self::constant(*value),
+            _ => {
+                let spacing = (end - start) / (values.len() - 1) as f64;
+                Self::from_fn(|wavelength| interpolate(values, (wavelength - start) / spacing))
+            }
+        }
+    }
+
+    // A smooth spectrum that looks like `color`
+    pub fn from_rgb(color: Color) -> Self {
+        Self::from_fn(|wavelength| rgb_to_spectral(color, wavelength))
+    }
+
+    fn wavelength_of_sample(i: usize) -> f64 {
+        MIN_WAVELENGTH + i as f64 * SAMPLE_SPACING
+    }
+
+    pub fn value_at(&self, wavelength: f64) -> f64 {
+        interpolate(
+            &self.samples,
+            (wavelength - MIN_WAVELENGTH) / SAMPLE_SPACING,
+        )
+    }
+
+    pub fn to_color(&self) -> Color {
+        wavelengths_to_color(
+            self.samples
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (Self::wavelength_of_sample(i), *value)),
+        )
+    }
+}
+
+// Linear interpolation between `values` at fractional index `position`,
+// clamped to the ends
+fn interpolate(values: &[f64], position: f64) -> f64 {
+    let last = values.len() - 1;
+    if position <= 0.0 {
+        return values[0];
+    }
+    if position >= last as f64 {
+        return values[last];
+    }
+    let i = position.floor() as usize;
+    let fraction = position - i as f64;
+    values[i] * (1.0 - fraction) + values[i + 1] * fraction
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// The value at `wavelength` of a spectrum that looks like `color`. Red,
+// green and blue each own a smooth band of the spectrum and the bands add
+// up to one everywhere, so white becomes a flat spectrum and reflectances
+// stay between zero and one
+pub fn rgb_to_spectral(color: Color, wavelength: f64) -> f64 {
+    let blue = 1.0 - smoothstep(470.0, 510.0, wavelength);
+    let red = smoothstep(570.0, 610.0, wavelength);
+    let green = 1.0 - blue - red;
+    color.red() * red + color.green() * green + color.blue() * blue
+}
+
+// A gray color holding the value of `color`'s spectrum at `wavelength`, or
+// `color` itself for rays that carry all wavelengths
+pub fn monochrome(color: Color, wavelength: Option<f64>) -> Color {
+    match wavelength {
+        Some(wavelength) => {
+            let value = rgb_to_spectral(color, wavelength);
+            Color::new(value, value, value)
+        }
+        None => color,
+    }
+}
+
+// A piecewise Gaussian, wider on one side of its peak than the other
+fn lobe(wavelength: f64, peak: f64, width_below: f64, width_above: f64) -> f64 {
+    let width = if wavelength < peak {
+        width_below
+    } else {
+        width_above
+    };
+    let t = (wavelength - peak) / width;
+    (-0.5 * t * t).exp()
+}
+
+// The CIE 1931 standard observer color matching functions. See Wyman, Sloan
+// & Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching
+// Functions"
+pub fn color_matching(wavelength: f64) -> (f64, f64, f64) {
+    let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// The color seen by an eye that receives light of a single wavelength. Some
+// pure spectral colors are more saturated than any sRGB color, in which case
+// a channel goes negative
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = color_matching(wavelength);
+    xyz_to_rgb(x, y, z)
+}
+
+// Combines radiance sampled at a set of (wavelength, radiance) pairs into a
+// color. Each channel is normalized by the total weight of the wavelengths
+// sampled, so a flat spectrum of one comes out exactly white whatever the
+// wavelengths were
+pub fn wavelengths_to_color(samples: impl IntoIterator<Item = (f64, f64)>) -> Color {
+    let (sum, weights) = samples.into_iter().fold(
+        (Color::new(0, 0, 0), Color::new(0, 0, 0)),
+        |(sum, weights), (wavelength, radiance)| {
+            let weight = wavelength_to_rgb(wavelength);
+            (sum + weight * radiance, weights + weight)
+        },
+    );
+    let normalize = |value: f64, weight: f64| if weight > 0.0 { value / weight } else { 0.0 };
+    Color::new(
+        normalize(sum.red(), weights.red()),
+        normalize(sum.green(), weights.green()),
+        normalize(sum.blue(), weights.blue()),
+    )
+}
+
+// `count` evenly spaced wavelengths across the visible range, each in the
+// middle of its own slice of the spectrum
+pub fn stratified_wavelengths(count: usize) -> impl Iterator<Item = f64> {
+    (0..count).map(move |i| wavelength_in_stratum(i, count, 0.5))
+}
+
+// A wavelength in the `i`th of `count` equal slices of the visible range, at
+// fractional `offset` into the slice
+pub fn wavelength_in_stratum(i: usize, count: usize, offset: f64) -> f64 {
+    MIN_WAVELENGTH + (i as f64 + offset) / count as f64 * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+#[cfg(test)]
+mod spectrum_test {
+    use super::*;
+    use crate::color::consts as color;
+    use crate::test_helpers::assert_nearly_eq;
+
+    #[test]
+    fn a_constant_spectrum_has_the_same_value_everywhere() {
+        let s = Spectrum::constant(0.25);
+        assert_eq!(0.25, s.value_at(400.0));
+        assert_eq!(0.25, s.value_at(555.5));
+        assert_eq!(0.25, s.value_at(900.0));
+    }
+
+    #[test]
+    fn spectra_are_interpolated_between_samples() {
+        let s = Spectrum::from_samples(400.0, 700.0, &[0.0, 1.0]);
+        assert_nearly_eq(0.0, s.value_at(400.0));
+        assert_nearly_eq(0.5, s.value_at(550.0));
+        assert_nearly_eq(1.0, s.value_at(700.0));
+        assert_nearly_eq(1.0, s.value_at(720.0));
+        assert_nearly_eq(0.0, s.value_at(380.0));
+    }
+
+    #[test]
+    fn white_is_upsampled_to_a_flat_spectrum() {
+        let s = Spectrum::from_rgb(color::WHITE);
+        for wavelength in [400.0, 480.0, 550.0, 600.0, 700.0] {
+            assert_nearly_eq(1.0, s.value_at(wavelength));
+        }
+    }
+
+    #[test]
+    fn upsampled_colors_reflect_their_own_part_of_the_spectrum() {
+        let red = Spectrum::from_rgb(color::RED);
+        assert_nearly_eq(1.0, red.value_at(650.0));
+        assert_nearly_eq(0.0, red.value_at(450.0));
+        let blue = Spectrum::from_rgb(color::BLUE);
+        assert_nearly_eq(0.0, blue.value_at(650.0));
+        assert_nearly_eq(1.0, blue.value_at(450.0));
+    }
+
+    #[test]
+    fn monochrome_colors_are_gray() {
+        let c = monochrome(Color::new(1.0, 0.5, 0.0), Some(650.0));
+        assert_eq!(Color::new(1, 1, 1), c);
+        let c = monochrome(Color::new(1.0, 0.5, 0.0), Some(540.0));
+        assert_eq!(Color::new(0.5, 0.5, 0.5), c);
+    }
+
+    #[test]
+    fn colors_without_a_wavelength_are_unchanged() {
+        let c = Color::new(1.0, 0.5, 0.0);
+        assert_eq!(c, monochrome(c, None));
+    }
+
+    #[test]
+    fn the_color_matching_functions_peak_where_expected() {
+        let (_, y, _) = color_matching(555.0);
+        assert!((y - 1.0).abs() < 0.02);
+        let (x, _, _) = color_matching(600.0);
+        assert!((x - 1.06).abs() < 0.02);
+        let (_, _, z) = color_matching(445.0);
+        assert!((z - 1.78).abs() < 0.05);
+    }
+
+    #[test]
+    fn a_flat_spectrum_is_white() {
+        assert_eq!(color::WHITE, Spectrum::constant(1.0).to_color());
+        assert_eq!(
+            Color::new(0.5, 0.5, 0.5),
+            Spectrum::constant(0.5).to_color()
+        );
+    }
+
+    #[test]
+    fn upsampled_colors_keep_their_hue() {
+        let c = Spectrum::from_rgb(Color::new(0.9, 0.2, 0.1)).to_color();
+        assert!(c.red() > c.green() && c.green() > c.blue(), "{c:?}");
+        let c = Spectrum::from_rgb(Color::new(0.1, 0.2, 0.9)).to_color();
+        assert!(c.blue() > c.green() && c.green() > c.red(), "{c:?}");
+    }
+
+    #[test]
+    fn single_wavelengths_are_rainbow_colors() {
+        let red = wavelength_to_rgb(650.0);
+        assert!(red.red() > red.green() && red.red() > red.blue());
+        let green = wavelength_to_rgb(530.0);
+        assert!(green.green() > green.red() && green.green() > green.blue());
+        let blue = wavelength_to_rgb(450.0);
+        assert!(blue.blue() > blue.red() && blue.blue() > blue.green());
+    }
+
+    #[test]
+    fn stratified_wavelengths_cover_the_visible_range() {
+        let wavelengths: Vec<f64> = stratified_wavelengths(7).collect();
+        assert_eq!(7, wavelengths.len());
+        assert_nearly_eq(405.0, wavelengths[0]);
+        assert_nearly_eq(705.0, wavelengths[6]);
+    }
+}
@@ -1,18 +1,20 @@
 use crate::intersections::{Intersection, Intersections};
 use crate::materials::Material;
+use crate::math_helpers::EPSILON;
 use crate::matrix::Mat4;
 use crate::rays::Ray;
 use crate::shapes::Shape;
 use crate::tup::Tup;
+use std::f64::consts::PI;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static ID_GEN: AtomicUsize = AtomicUsize::new(0);
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Sphere {
     id: usize,
     transform: Mat4,
-    material: Material,
+    material: Option<Material>,
 }
 
 fn get_id() -> usize {
@@ -28,7 +30,10 @@ impl Sphere {
     }
 
     pub fn with_material(self, material: Material) -> Self {
-        Self { material, ..self }
+        Self {
+            material: Some(material),
+            ..self
+        }
     }
 
     pub fn with_transform(self, transform: Mat4) -> Self {
@@ -41,7 +46,7 @@ impl Default for Sphere {
         Self {
             id: get_id(),
             transform: Mat4::identity_matrix(),
-            material: Material::default(),
+            material: None,
         }
     }
 }
@@ -56,11 +61,15 @@ impl Shape for Sphere {
     }
 
     fn material(&self) -> Material {
-        self.material
+        self.material.clone().unwrap_or_default()
     }
 
     fn set_material(&mut self, material: Material) {
-        self.material = material;
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
     }
 
     fn local_intersect(&self, local_ray: Ray) -> Intersections {
@@ -73,8 +82,8 @@ impl Shape for Sphere {
         if discriminant < 0.0 {
             Intersections::default()
         } else {
-            let t1 = Intersection::new((-b - discriminant.sqrt()) / (2.0 * a), *self);
-            let t2 = Intersection::new((-b + discriminant.sqrt()) / (2.0 * a), *self);
+            let t1 = Intersection::new((-b - discriminant.sqrt()) / (2.0 * a), self.clone());
+            let t2 = Intersection::new((-b + discriminant.sqrt()) / (2.0 * a), self.clone());
             Intersections::new(&[t1, t2])
         }
     }
@@ -82,6 +91,27 @@ impl Shape for Sphere {
     fn local_normal_at(&self, point: Tup) -> Tup {
         point - Tup::point(0, 0, 0)
     }
+
+    // Longitude and latitude, laid out like an equirectangular image. The
+    // middle of the image faces the negative z axis and the top row is the
+    // north pole
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        let radius = (point - Tup::point(0, 0, 0)).magnitude();
+        let u = 0.5 + point.x.atan2(-point.z) / (2.0 * PI);
+        let v = (point.y / radius).clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    // Eastward along the line of latitude, except at the poles where every
+    // direction is east
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        let east = Tup::vector(-point.z, 0.0, point.x);
+        if east.magnitude() < EPSILON {
+            Tup::vector(1, 0, 0)
+        } else {
+            east.normalize()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -318,7 +348,7 @@ mod spheres_test {
     #[test]
     fn a_sphere_can_be_assigned_a_material() {
         let m = Material::default().with_ambient(1.0);
-        let s = Sphere::default().with_material(m);
+        let s = Sphere::default().with_material(m.clone());
         assert_eq!(m, s.material());
     }
 
@@ -334,4 +364,35 @@ mod spheres_test {
 
         assert_eq!(1.5, s.material().refractive_index());
     }
+
+    #[test]
+    fn texture_coordinates_on_a_sphere_follow_longitude_and_latitude() {
+        let s = Sphere::default();
+        let (u, v) = s.local_uv_at(Tup::point(0, 0, -1));
+        assert_nearly_eq(0.5, u);
+        assert_nearly_eq(0.5, v);
+        let (u, _) = s.local_uv_at(Tup::point(1, 0, 0));
+        assert_nearly_eq(0.75, u);
+        let (_, v) = s.local_uv_at(Tup::point(0, 1, 0));
+        assert_nearly_eq(0.0, v);
+        let (_, v) = s.local_uv_at(Tup::point(0, -1, 0));
+        assert_nearly_eq(1.0, v);
+    }
+
+    #[test]
+    fn the_tangent_on_a_sphere_points_east() {
+        let s = Sphere::default();
+        assert_eq!(
+            Tup::vector(1, 0, 0),
+            s.local_tangent_at(Tup::point(0, 0, -1))
+        );
+        assert_eq!(
+            Tup::vector(0, 0, 1),
+            s.local_tangent_at(Tup::point(1, 0, 0))
+        );
+        assert_eq!(
+            Tup::vector(1, 0, 0),
+            s.local_tangent_at(Tup::point(0, 1, 0))
+        );
+    }
 }
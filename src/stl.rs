@@ -0,0 +1,257 @@
+use crate::meshes::MeshData;
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+
+// Reader for STL (`.stl`) meshes, the usual output of CAD and 3D printing
+// tools, in binary or ASCII. Every triangle lists its own three corners, so
+// nothing is shared between faces. The facet normals stored alongside are
+// often left as zero, so the triangles' own normals are used instead
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StlError {
+    UnexpectedEndOfData,
+    UnexpectedWord {
+        expected: &'static str,
+        found: String,
+    },
+    InvalidNumber(String),
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::UnexpectedEndOfData => write!(f, "unexpected end of data"),
+            StlError::UnexpectedWord { expected, found } => {
+                write!(f, "expected `{expected}` but found `{found}`")
+            }
+            StlError::InvalidNumber(text) => write!(f, "`{text}` is not a number"),
+        }
+    }
+}
+
+impl Error for StlError {}
+
+const HEADER_SIZE: usize = 80;
+
+// Twelve 32 bit floats for the normal and corners, then two bytes of
+// attributes
+const TRIANGLE_SIZE: usize = 50;
+
+// Binary files can begin with `solid` too, so they are told apart by their
+// size, which is exact for binary files
+pub fn decode(bytes: &[u8]) -> Result<MeshData, StlError> {
+    let is_binary =
+        triangle_count(bytes).is_some_and(|count| bytes.len() as u64 == binary_size(count));
+    if is_binary || !bytes.trim_ascii_start().starts_with(b"solid") {
+        decode_binary(bytes)
+    } else {
+        decode_ascii(bytes)
+    }
+}
+
+fn triangle_count(bytes: &[u8]) -> Option<u32> {
+    let count = bytes.get(HEADER_SIZE..HEADER_SIZE + 4)?;
+    Some(u32::from_le_bytes([count[0], count[1], count[2], count[3]]))
+}
+
+fn binary_size(count: u32) -> u64 {
+    (HEADER_SIZE + 4) as u64 + count as u64 * TRIANGLE_SIZE as u64
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<MeshData, StlError> {
+    let count = triangle_count(bytes).ok_or(StlError::UnexpectedEndOfData)?;
+    if (bytes.len() as u64) < binary_size(count) {
+        return Err(StlError::UnexpectedEndOfData);
+    }
+    let float = |b: &[u8], i: usize| {
+        f32::from_le_bytes([b[4 * i], b[4 * i + 1], b[4 * i + 2], b[4 * i + 3]]) as f64
+    };
+    let mut positions = Vec::with_capacity(3 * count as usize);
+    for triangle in bytes[HEADER_SIZE + 4..]
+        .chunks_exact(TRIANGLE_SIZE)
+        .take(count as usize)
+    {
+        for corner in 1..4 {
+            let i = 3 * corner;
+            positions.push(Tup::point(
+                float(triangle, i),
+                float(triangle, i + 1),
+                float(triangle, i + 2),
+            ));
+        }
+    }
+    Ok(mesh(positions))
+}
+
+fn decode_ascii(bytes: &[u8]) -> Result<MeshData, StlError> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut words = Words(text.split_ascii_whitespace().peekable());
+    words.expect("solid")?;
+    // The name of the solid, if any, runs up to the first facet
+    while words
+        .0
+        .peek()
+        .is_some_and(|w| *w != "facet" && *w != "endsolid")
+    {
+        words.0.next();
+    }
+    let mut positions = Vec::new();
+    loop {
+        match words.next()? {
+            "facet" => {}
+            "endsolid" => break,
+            word => {
+                return Err(StlError::UnexpectedWord {
+                    expected: "facet",
+                    found: word.to_string(),
+                })
+            }
+        }
+        words.expect("normal")?;
+        for _ in 0..3 {
+            words.number()?;
+        }
+        words.expect("outer")?;
+        words.expect("loop")?;
+        for _ in 0..3 {
+            words.expect("vertex")?;
+            let (x, y, z) = (words.number()?, words.number()?, words.number()?);
+            positions.push(Tup::point(x, y, z));
+        }
+        words.expect("endloop")?;
+        words.expect("endfacet")?;
+    }
+    Ok(mesh(positions))
+}
+
+struct Words<'a>(std::iter::Peekable<std::str::SplitAsciiWhitespace<'a>>);
+
+impl<'a> Words<'a> {
+    fn next(&mut self) -> Result<&'a str, StlError> {
+        self.0.next().ok_or(StlError::UnexpectedEndOfData)
+    }
+
+    fn expect(&mut self, expected: &'static str) -> Result<(), StlError> {
+        match self.next()? {
+            word if word == expected => Ok(()),
+            word => Err(StlError::UnexpectedWord {
+                expected,
+                found: word.to_string(),
+            }),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, StlError> {
+        let word = self.next()?;
+        word.parse()
+            .map_err(|_| StlError::InvalidNumber(word.to_string()))
+    }
+}
+
+fn mesh(positions: Vec<Tup>) -> MeshData {
+    let faces = (0..positions.len() / 3)
+        .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+        .collect();
+    MeshData::new(positions, faces).expect("Every face has three vertices of its own")
+}
+
+#[cfg(test)]
+mod stl_test {
+    use super::*;
+
+    const TETRAHEDRON_SIDE: &str = "solid side
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 0 1
+      vertex 1.5e0 0 0
+    endloop
+  endfacet
+endsolid side
+";
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend_from_slice(&2_u32.to_le_bytes());
+        for corners in [[0.0_f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], [0.0; 9]] {
+            bytes.extend_from_slice(&[0; 12]);
+            for value in corners {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn an_ascii_file_is_read() {
+        let mesh = decode(TETRAHEDRON_SIDE.as_bytes()).expect("Valid stl data");
+        assert_eq!(6, mesh.positions().len());
+        assert_eq!(Tup::point(1.5, 0.0, 0.0), mesh.positions()[5]);
+        assert_eq!(&[[0, 1, 2], [3, 4, 5]], mesh.faces());
+        assert!(!mesh.has_normals());
+    }
+
+    #[test]
+    fn a_binary_file_is_read() {
+        let mesh = decode(&binary(b"exported by a cad tool")).expect("Valid stl data");
+        assert_eq!(6, mesh.positions().len());
+        assert_eq!(Tup::point(0, 1, 0), mesh.positions()[2]);
+        assert_eq!(2, mesh.faces().len());
+    }
+
+    #[test]
+    fn a_binary_file_may_start_like_an_ascii_one() {
+        let mesh = decode(&binary(b"solid but binary")).expect("Valid stl data");
+        assert_eq!(2, mesh.faces().len());
+    }
+
+    #[test]
+    fn truncated_files_are_an_error() {
+        let bytes = binary(b"binary");
+        for length in [0, 50, HEADER_SIZE + 4 + TRIANGLE_SIZE] {
+            assert_eq!(
+                Some(StlError::UnexpectedEndOfData),
+                decode(&bytes[..length]).err()
+            );
+        }
+        let text = &TETRAHEDRON_SIDE[..TETRAHEDRON_SIDE.len() - 40];
+        assert_eq!(
+            Some(StlError::UnexpectedEndOfData),
+            decode(text.as_bytes()).err()
+        );
+    }
+
+    #[test]
+    fn a_huge_triangle_count_in_a_short_file_is_an_error() {
+        let mut bytes = binary(b"binary");
+        bytes[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Some(StlError::UnexpectedEndOfData), decode(&bytes).err());
+    }
+
+    #[test]
+    fn malformed_text_is_an_error() {
+        let misspelt = TETRAHEDRON_SIDE.replacen("outer loop", "outer lop", 1);
+        assert_eq!(
+            Some(StlError::UnexpectedWord {
+                expected: "loop",
+                found: "lop".to_string()
+            }),
+            decode(misspelt.as_bytes()).err()
+        );
+        let not_a_number = TETRAHEDRON_SIDE.replacen("vertex 1 0 0", "vertex one 0 0", 1);
+        assert_eq!(
+            Some(StlError::InvalidNumber("one".to_string())),
+            decode(not_a_number.as_bytes()).err()
+        );
+    }
+}
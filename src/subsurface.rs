@@ -0,0 +1,163 @@
+use crate::color::Color;
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::rays::Ray;
+use crate::sampling::{self, Rng};
+use crate::shapes::Shape;
+use crate::tup::Tup;
+
+// Subsurface scattering by random walk. Light entering a translucent object
+// is followed as it bounces from particle to particle inside, until it
+// finds its way back out through the surface or is lost
+
+// Walks still inside after this many scattering events are given up on.
+// Little light survives that long unless the scatter color is nearly white
+const MAX_STEPS: usize = 64;
+
+// Where a walk left the object, and how much of the light that entered
+// made it out
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct SubsurfaceExit {
+    point: Tup,
+    normal: Tup,
+    throughput: Color,
+}
+
+impl SubsurfaceExit {
+    // Just outside the surface, ready to cast shadow rays from
+    pub fn point(&self) -> Tup {
+        self.point
+    }
+
+    pub fn normal(&self) -> Tup {
+        self.normal
+    }
+
+    pub fn throughput(&self) -> Color {
+        self.throughput
+    }
+}
+
+// Follows light entering `object` at `entry`, just under the surface with
+// outward `normal`. Step lengths are drawn from an exponential distribution
+// with the material's mean free path and each scattering event sends the
+// light off in a random direction, tinted by the scatter color. Absorbing
+// materials dim the light along the way too. Returns `None` if the light
+// never gets out
+pub fn random_walk(
+    object: &dyn Shape,
+    entry: Tup,
+    normal: Tup,
+    material: &Material,
+    rng: &mut Rng,
+) -> Option<SubsurfaceExit> {
+    let mut point = entry;
+    let mut direction = sampling::cosine_weighted_hemisphere(-normal, rng);
+    let mut throughput = material.scatter_color();
+    for _ in 0..MAX_STEPS {
+        let step = -(1.0 - rng.next_f64()).ln() * material.mean_free_path();
+        let ray = Ray::new(point, direction);
+        let xs = object.intersect(&ray);
+        if let Some(hit) = xs.hit() {
+            let exit = hit.t();
+            if exit < step {
+                let surface = ray.position(exit);
+                // The part of the object that was hit, which for groups
+                // needn't be the one the light came in through
+                let normal = hit.object().normal_at(surface);
+                return Some(SubsurfaceExit {
+                    point: surface + normal * EPSILON,
+                    normal,
+                    throughput: throughput * material.transmittance(exit),
+                });
+            }
+        }
+        point = ray.position(step);
+        throughput = throughput * material.scatter_color() * material.transmittance(step);
+        direction = sampling::uniform_sphere(rng);
+    }
+    None
+}
+
+#[cfg(test)]
+mod subsurface_test {
+    use super::*;
+    use crate::spheres::Sphere;
+    use crate::transforms;
+
+    #[test]
+    fn walks_leave_through_the_surface() {
+        let sphere = Sphere::default().with_material(
+            Material::default()
+                .with_mean_free_path(0.3)
+                .with_scatter_color(Color::new(0.9, 0.8, 0.7)),
+        );
+        let material = sphere.material();
+        let entry = Tup::point(0.0, 0.0, -1.0 + EPSILON);
+        let normal = Tup::vector(0, 0, -1);
+        let mut rng = Rng::new(3);
+        for _ in 0..100 {
+            if let Some(exit) = random_walk(&sphere, entry, normal, &material, &mut rng) {
+                let radius = (exit.point() - Tup::point(0, 0, 0)).magnitude();
+                assert!((radius - 1.0).abs() < 1e-3, "{radius}");
+                assert!(exit.normal().dot(&(exit.point() - Tup::point(0, 0, 0))) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn walks_never_gain_light() {
+        let sphere = Sphere::default().with_material(
+            Material::default()
+                .with_mean_free_path(0.3)
+                .with_scatter_color(Color::new(0.9, 0.8, 0.7)),
+        );
+        let material = sphere.material();
+        let entry = Tup::point(0.0, 0.0, -1.0 + EPSILON);
+        let normal = Tup::vector(0, 0, -1);
+        let mut rng = Rng::new(5);
+        for _ in 0..100 {
+            if let Some(exit) = random_walk(&sphere, entry, normal, &material, &mut rng) {
+                let t = exit.throughput();
+                assert!(t.red() <= 0.9 && t.green() <= 0.8 && t.blue() <= 0.7);
+            }
+        }
+    }
+
+    #[test]
+    fn a_short_mean_free_path_exits_near_the_entry() {
+        let sphere = Sphere::default().with_material(
+            Material::default()
+                .with_mean_free_path(0.01)
+                .with_scatter_color(Color::new(0.9, 0.8, 0.7)),
+        );
+        let material = sphere.material();
+        let entry = Tup::point(0.0, 0.0, -1.0 + EPSILON);
+        let normal = Tup::vector(0, 0, -1);
+        let mut rng = Rng::new(7);
+        let exit = (0..20)
+            .find_map(|_| random_walk(&sphere, entry, normal, &material, &mut rng))
+            .expect("Some light escapes");
+        assert!((exit.point() - entry).magnitude() < 0.5);
+    }
+
+    #[test]
+    fn walks_follow_the_objects_transform() {
+        let sphere = Sphere::default()
+            .with_transform(transforms::translation(0, 0, 5))
+            .with_material(
+                Material::default()
+                    .with_mean_free_path(0.5)
+                    .with_scatter_color(Color::new(0.9, 0.8, 0.7)),
+            );
+        let material = sphere.material();
+        let entry = Tup::point(0.0, 0.0, 4.0 + EPSILON);
+        let normal = Tup::vector(0, 0, -1);
+        let mut rng = Rng::new(11);
+        let exit = (0..20)
+            .find_map(|_| random_walk(&sphere, entry, normal, &material, &mut rng))
+            .expect("Some light escapes");
+        let radius = (exit.point() - Tup::point(0, 0, 5)).magnitude();
+        assert!((radius - 1.0).abs() < 1e-3);
+    }
+}
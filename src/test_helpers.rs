@@ -1,11 +1,17 @@
+use crate::background::Background;
 use crate::color::Color;
+use crate::intersections::{Computations, Intersection, Intersections};
 use crate::lights::Light;
 use crate::materials::Material;
 use crate::math_helpers::nearly_eq;
+use crate::planes::Plane;
+use crate::rays::Ray;
+use crate::shapes::Shape;
 use crate::spheres::Sphere;
 use crate::transforms;
 use crate::tup::Tup;
 use crate::world::World;
+use std::f64::consts;
 
 pub fn assert_nearly_eq(a: f64, b: f64) {
     assert!(
@@ -31,3 +37,37 @@ pub fn default_test_world() -> World {
         .with_object(s1)
         .with_object(s2)
 }
+
+// Glass that shows nothing of its own, only what is seen through it
+pub fn clear_glass(refractive_index: f64) -> Material {
+    Material::default()
+        .with_ambient(0.0)
+        .with_diffuse(0.0)
+        .with_specular(0.0)
+        .with_transparency(1.0)
+        .with_refractive_index(refractive_index)
+}
+
+// A world of nothing but `object` in front of `background`
+pub fn lone_object_world<T: 'static + Shape>(object: T, background: Background) -> World {
+    World::default()
+        .with_object(object)
+        .with_background(background)
+}
+
+// A world of nothing but a plane in front of `background`, and the plane as
+// seen by a ray looking down on it at 45 degrees
+pub fn plane_seen_at_45_degrees(
+    material: Material,
+    background: Background,
+) -> (World, Computations) {
+    let plane = Plane::default().with_material(material);
+    let rad_2_over_2 = consts::SQRT_2 / 2.0;
+    let r = Ray::new(
+        Tup::point(0, 1, -1),
+        Tup::vector(0.0, -rad_2_over_2, rad_2_over_2),
+    );
+    let i = Intersection::new(consts::SQRT_2, plane.clone());
+    let comps = i.prepare_computations(&r, &Intersections::new(std::slice::from_ref(&i)));
+    (lone_object_world(plane, background), comps)
+}
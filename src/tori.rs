@@ -0,0 +1,298 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::matrix::Mat4;
+use crate::polynomials;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// A ring lying in the xz plane, centered on the origin. `major` is the
+// distance from the center to the middle of the tube and `minor` is the
+// radius of the tube
+#[derive(PartialEq, Clone, Debug)]
+pub struct Torus {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    major: f64,
+    minor: f64,
+}
+
+impl Torus {
+    pub fn new(major: f64, minor: f64) -> Self {
+        Self {
+            major,
+            minor,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn major(&self) -> f64 {
+        self.major
+    }
+
+    pub fn minor(&self) -> f64 {
+        self.minor
+    }
+
+    // The span of t in which `ray` is inside a sphere just holding the torus
+    fn bounding_span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let radius = self.major + self.minor;
+        let to_ray = ray.origin() - Tup::point(0, 0, 0);
+        let a = ray.direction().dot(&ray.direction());
+        let b = 2.0 * ray.direction().dot(&to_ray);
+        let c = to_ray.dot(&to_ray) - radius * radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            Some(((-b - root) / (2.0 * a), (-b + root) / (2.0 * a)))
+        }
+    }
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            major: 1.0,
+            minor: 0.25,
+        }
+    }
+}
+
+impl Shape for Torus {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    // Points on the torus satisfy
+    //   (x² + y² + z² + R² - r²)² = 4R²(x² + z²)
+    // which along a ray is a quartic in t. The ray's origin is first moved up
+    // to the bounding sphere, keeping the coefficients small for rays that
+    // start far away
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        let (near, _) = match self.bounding_span(&local_ray) {
+            Some(span) => span,
+            None => return Intersections::default(),
+        };
+        let o = local_ray.position(near) - Tup::point(0, 0, 0);
+        let d = local_ray.direction();
+        let (r2, big_r2) = (self.minor * self.minor, self.major * self.major);
+        let g = d.dot(&d);
+        let h = 2.0 * o.dot(&d);
+        let i = o.dot(&o) + big_r2 - r2;
+        let j = d.x * d.x + d.z * d.z;
+        let k = 2.0 * (o.x * d.x + o.z * d.z);
+        let l = o.x * o.x + o.z * o.z;
+        let coefficients = [
+            g * g,
+            2.0 * g * h,
+            h * h + 2.0 * g * i - 4.0 * big_r2 * j,
+            2.0 * h * i - 4.0 * big_r2 * k,
+            i * i - 4.0 * big_r2 * l,
+        ];
+        let xs: Vec<Intersection> = polynomials::real_roots(&coefficients)
+            .into_iter()
+            .map(|t| Intersection::new(near + t, self.clone()))
+            .collect();
+        Intersections::new(&xs)
+    }
+
+    // The normal points away from the nearest point on the circle running
+    // through the middle of the tube. It is about as long as the tube is
+    // thick, which on small tori is too short for `normalize`
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let around = (point.x * point.x + point.z * point.z).sqrt();
+        let center = if around > 0.0 {
+            let scale = self.major / around;
+            Tup::point(point.x * scale, 0.0, point.z * scale)
+        } else {
+            Tup::point(self.major, 0.0, 0.0)
+        };
+        let normal = point - center;
+        normal * (1.0 / normal.magnitude())
+    }
+
+    // u runs around the ring like longitude on a sphere and v runs around
+    // the tube, starting from its outer edge and heading down, so the top
+    // of an image faces up like it does on a sphere
+    fn local_uv_at(&self, point: Tup) -> (f64, f64) {
+        let u = 0.5 + point.x.atan2(-point.z) / (2.0 * PI);
+        let ring = (point.x * point.x + point.z * point.z).sqrt() - self.major;
+        let v = (-point.y.atan2(ring) / (2.0 * PI)).rem_euclid(1.0);
+        (u, v)
+    }
+
+    fn local_tangent_at(&self, point: Tup) -> Tup {
+        let around = Tup::vector(-point.z, 0.0, point.x);
+        if around.magnitude() < EPSILON {
+            Tup::vector(1, 0, 0)
+        } else {
+            around.normalize()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tori_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+    use crate::transforms;
+
+    fn ts(xs: &Intersections) -> Vec<f64> {
+        (0..xs.len()).map(|i| xs[i].t()).collect()
+    }
+
+    #[test]
+    fn a_torus_has_default_radii() {
+        let t = Torus::default();
+        assert_eq!(1.0, t.major());
+        assert_eq!(0.25, t.minor());
+    }
+
+    #[test]
+    fn a_ray_through_the_ring_crosses_it_four_times() {
+        let t = Torus::new(1.0, 0.25);
+        let ray = Ray::new(Tup::point(-5, 0, 0), Tup::vector(1, 0, 0));
+        let xs = t.intersect(&ray);
+        let t = ts(&xs);
+        assert_eq!(4, t.len());
+        assert_nearly_eq(3.75, t[0]);
+        assert_nearly_eq(4.25, t[1]);
+        assert_nearly_eq(5.75, t[2]);
+        assert_nearly_eq(6.25, t[3]);
+    }
+
+    #[test]
+    fn a_ray_through_the_hole_misses() {
+        let t = Torus::new(1.0, 0.25);
+        let ray = Ray::new(Tup::point(0, 5, 0), Tup::vector(0, -1, 0));
+        assert!(t.intersect(&ray).is_empty());
+    }
+
+    #[test]
+    fn a_ray_down_through_the_tube_crosses_it_twice() {
+        let t = Torus::new(2.0, 0.5);
+        let ray = Ray::new(Tup::point(2, 5, 0), Tup::vector(0, -1, 0));
+        let xs = t.intersect(&ray);
+        let t = ts(&xs);
+        assert_eq!(2, t.len());
+        assert_nearly_eq(4.5, t[0]);
+        assert_nearly_eq(5.5, t[1]);
+    }
+
+    #[test]
+    fn a_ray_from_far_away_still_hits_precisely() {
+        let t = Torus::new(1.0, 0.1);
+        let ray = Ray::new(Tup::point(-1e5, 0.0, 0.0), Tup::vector(1, 0, 0));
+        let xs = t.intersect(&ray);
+        assert_eq!(4, xs.len());
+        assert_nearly_eq(1e5 - 1.1, xs[0].t());
+    }
+
+    #[test]
+    fn a_ray_from_inside_the_tube() {
+        let t = Torus::new(1.0, 0.25);
+        let ray = Ray::new(Tup::point(1, 0, 0), Tup::vector(0, 0, 1));
+        let xs = t.intersect(&ray);
+        let hit = xs.hit().map(|i| i.t()).expect("Leaves the tube");
+        // The tube's surface is 0.25 from the ring at 1, so it is left where
+        // the distance from the center reaches 1.25
+        assert_nearly_eq(0.75, hit);
+    }
+
+    #[test]
+    fn the_normal_points_away_from_the_middle_of_the_tube() {
+        let t = Torus::new(1.0, 0.25);
+        assert_eq!(
+            Tup::vector(1, 0, 0),
+            t.normal_at(Tup::point(1.25, 0.0, 0.0))
+        );
+        assert_eq!(
+            Tup::vector(-1, 0, 0),
+            t.normal_at(Tup::point(0.75, 0.0, 0.0))
+        );
+        assert_eq!(
+            Tup::vector(0, 1, 0),
+            t.normal_at(Tup::point(0.0, 0.25, 1.0))
+        );
+        let n = t.normal_at(Tup::point(
+            1.0 + 0.25 * std::f64::consts::FRAC_1_SQRT_2,
+            0.25 * std::f64::consts::FRAC_1_SQRT_2,
+            0.0,
+        ));
+        assert_eq!(Tup::vector(1, 1, 0).normalize(), n);
+    }
+
+    #[test]
+    fn a_tiny_torus_has_unit_normals() {
+        let t = Torus::new(0.02, 0.005);
+        assert_eq!(
+            Tup::vector(1, 0, 0),
+            t.normal_at(Tup::point(0.025, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn a_transformed_torus_stands_upright() {
+        let t =
+            Torus::default().with_transform(transforms::rotation_x(std::f64::consts::FRAC_PI_2));
+        // Now lying in the xy plane, the hole faces the z axis
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        assert!(t.intersect(&ray).is_empty());
+        let ray = Ray::new(Tup::point(1, 0, -5), Tup::vector(0, 0, 1));
+        let xs = t.intersect(&ray);
+        assert_eq!(2, xs.len());
+        assert_nearly_eq(4.75, xs[0].t());
+    }
+
+    #[test]
+    fn the_tube_is_mapped_all_the_way_around() {
+        let t = Torus::new(1.0, 0.25);
+        let (u, v) = t.local_uv_at(Tup::point(0.0, 0.0, -1.25));
+        assert_nearly_eq(0.5, u);
+        assert_nearly_eq(0.0, v);
+        let (_, v) = t.local_uv_at(Tup::point(0.0, -0.25, -1.0));
+        assert_nearly_eq(0.25, v);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::intersections::{Intersection, Intersections};
+use crate::materials::Material;
+use crate::math_helpers::EPSILON;
+use crate::matrix::Mat4;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tup::Tup;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ID_GEN: AtomicUsize = AtomicUsize::new(0);
+
+fn get_id() -> usize {
+    ID_GEN.fetch_add(1, Ordering::Relaxed)
+}
+
+// A flat triangle between three points, with the same normal everywhere
+#[derive(PartialEq, Clone, Debug)]
+pub struct Triangle {
+    id: usize,
+    transform: Mat4,
+    material: Option<Material>,
+    p1: Tup,
+    p2: Tup,
+    p3: Tup,
+    e1: Tup,
+    e2: Tup,
+    normal: Tup,
+}
+
+impl Triangle {
+    pub fn new(p1: Tup, p2: Tup, p3: Tup) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        // Finely detailed models have triangles small enough that
+        // `normalize` would refuse their normals
+        let normal = e2.cross(&e1);
+        Self {
+            id: get_id(),
+            transform: Mat4::identity_matrix(),
+            material: None,
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal: normal * (1.0 / normal.magnitude()),
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn p1(&self) -> Tup {
+        self.p1
+    }
+
+    pub fn p2(&self) -> Tup {
+        self.p2
+    }
+
+    pub fn p3(&self) -> Tup {
+        self.p3
+    }
+
+    pub fn normal(&self) -> Tup {
+        self.normal
+    }
+
+    // Where `ray` crosses the triangle as (t, u, v), with u and v the
+    // weights of the second and third corners
+    pub fn barycentric_hit(&self, ray: &Ray) -> Option<(f64, f64, f64)> {
+        barycentric_hit(ray, self.p1, self.e1, self.e2)
+    }
+
+    // The weights of the second and third corners at a point in the
+    // triangle's plane
+    pub fn barycentric(&self, point: Tup) -> (f64, f64) {
+        let to_point = point - self.p1;
+        let (d11, d12, d22) = (
+            self.e1.dot(&self.e1),
+            self.e1.dot(&self.e2),
+            self.e2.dot(&self.e2),
+        );
+        let (dp1, dp2) = (to_point.dot(&self.e1), to_point.dot(&self.e2));
+        let denominator = d11 * d22 - d12 * d12;
+        (
+            (d22 * dp1 - d12 * dp2) / denominator,
+            (d11 * dp2 - d12 * dp1) / denominator,
+        )
+    }
+}
+
+// Where `ray` crosses the triangle with a corner at `p1` and edges `e1` and
+// `e2` from it, as (t, u, v). Möller and Trumbore's test
+pub fn barycentric_hit(ray: &Ray, p1: Tup, e1: Tup, e2: Tup) -> Option<(f64, f64, f64)> {
+    let dir_cross_e2 = ray.direction().cross(&e2);
+    let det = e1.dot(&dir_cross_e2);
+    if det.abs() < EPSILON * EPSILON {
+        return None;
+    }
+    let f = 1.0 / det;
+    let p1_to_origin = ray.origin() - p1;
+    let u = f * p1_to_origin.dot(&dir_cross_e2);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(&e1);
+    let v = f * ray.direction().dot(&origin_cross_e1);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some((f * e2.dot(&origin_cross_e1), u, v))
+}
+
+impl Shape for Triangle {
+    fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        match self.barycentric_hit(&local_ray) {
+            Some((t, _, _)) => Intersections::new(&[Intersection::new(t, self.clone())]),
+            None => Intersections::default(),
+        }
+    }
+
+    fn local_normal_at(&self, _point: Tup) -> Tup {
+        self.normal
+    }
+
+    fn local_tangent_at(&self, _point: Tup) -> Tup {
+        self.e1.normalize()
+    }
+}
+
+// A triangle with a normal at each corner, blended across its face so a
+// mesh of them shades like the smooth surface it approximates
+#[derive(PartialEq, Clone, Debug)]
+pub struct SmoothTriangle {
+    triangle: Triangle,
+    n1: Tup,
+    n2: Tup,
+    n3: Tup,
+}
+
+impl SmoothTriangle {
+    pub fn new(p1: Tup, p2: Tup, p3: Tup, n1: Tup, n2: Tup, n3: Tup) -> Self {
+        Self {
+            triangle: Triangle::new(p1, p2, p3),
+            n1,
+            n2,
+            n3,
+        }
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self {
+            triangle: self.triangle.with_material(material),
+            ..self
+        }
+    }
+
+    pub fn with_transform(self, transform: Mat4) -> Self {
+        Self {
+            triangle: self.triangle.with_transform(transform),
+            ..self
+        }
+    }
+
+    pub fn triangle(&self) -> Triangle {
+        self.triangle.clone()
+    }
+
+    pub fn n1(&self) -> Tup {
+        self.n1
+    }
+
+    pub fn n2(&self) -> Tup {
+        self.n2
+    }
+
+    pub fn n3(&self) -> Tup {
+        self.n3
+    }
+
+    // The blended normal at the point with weights u and v on the second
+    // and third corners
+    pub fn normal_at_uv(&self, u: f64, v: f64) -> Tup {
+        (self.n2 * u + self.n3 * v + self.n1 * (1.0 - u - v)).normalize()
+    }
+}
+
+impl Shape for SmoothTriangle {
+    fn transform(&self) -> Mat4 {
+        self.triangle.transform
+    }
+
+    fn set_transform(&mut self, transform: Mat4) {
+        self.triangle.transform = transform;
+    }
+
+    fn material(&self) -> Material {
+        self.triangle.material.clone().unwrap_or_default()
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.triangle.material = Some(material);
+    }
+
+    fn own_material(&self) -> Option<Material> {
+        self.triangle.material.clone()
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Intersections {
+        match self.triangle.barycentric_hit(&local_ray) {
+            Some((t, _, _)) => Intersections::new(&[Intersection::new(t, self.clone())]),
+            None => Intersections::default(),
+        }
+    }
+
+    fn local_normal_at(&self, point: Tup) -> Tup {
+        let (u, v) = self.triangle.barycentric(point);
+        self.normal_at_uv(u, v)
+    }
+
+    fn local_tangent_at(&self, _point: Tup) -> Tup {
+        self.triangle.e1.normalize()
+    }
+}
+
+#[cfg(test)]
+mod triangles_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Tup::point(0, 1, 0),
+            Tup::point(-1, 0, 0),
+            Tup::point(1, 0, 0),
+        )
+    }
+
+    fn smooth_triangle() -> SmoothTriangle {
+        SmoothTriangle::new(
+            Tup::point(0, 1, 0),
+            Tup::point(-1, 0, 0),
+            Tup::point(1, 0, 0),
+            Tup::vector(0, 1, 0),
+            Tup::vector(-1, 0, 0),
+            Tup::vector(1, 0, 0),
+        )
+    }
+
+    #[test]
+    fn a_triangle_has_the_same_normal_everywhere() {
+        let t = triangle();
+        assert_eq!(Tup::vector(0, 0, -1), t.normal());
+        assert_eq!(t.normal(), t.local_normal_at(Tup::point(0.0, 0.5, 0.0)));
+    }
+
+    #[test]
+    fn a_ray_parallel_to_a_triangle_misses_it() {
+        let ray = Ray::new(Tup::point(0, -1, -2), Tup::vector(0, 1, 0));
+        assert!(triangle().local_intersect(ray).is_empty());
+    }
+
+    #[test]
+    fn rays_beyond_each_edge_miss_a_triangle() {
+        for origin in [
+            Tup::point(1, 1, -2),
+            Tup::point(-1, 1, -2),
+            Tup::point(0, -1, -2),
+        ] {
+            let ray = Ray::new(origin, Tup::vector(0, 0, 1));
+            assert!(triangle().local_intersect(ray).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_ray_strikes_a_triangle() {
+        let ray = Ray::new(Tup::point(0.0, 0.5, -2.0), Tup::vector(0, 0, 1));
+        let xs = triangle().local_intersect(ray);
+        assert_eq!(1, xs.len());
+        assert_eq!(2.0, xs[0].t());
+    }
+
+    #[test]
+    fn a_hit_records_the_weights_of_the_corners() {
+        let ray = Ray::new(Tup::point(-0.2, 0.3, -2.0), Tup::vector(0, 0, 1));
+        let (t, u, v) = triangle().barycentric_hit(&ray).expect("Hits the triangle");
+        assert_nearly_eq(2.0, t);
+        assert_nearly_eq(0.45, u);
+        assert_nearly_eq(0.25, v);
+        let (u, v) = triangle().barycentric(Tup::point(-0.2, 0.3, 0.0));
+        assert_nearly_eq(0.45, u);
+        assert_nearly_eq(0.25, v);
+    }
+
+    #[test]
+    fn a_smooth_triangle_blends_its_corner_normals() {
+        let t = smooth_triangle();
+        assert_eq!(
+            Tup::vector(-0.5547, 0.83205, 0.0),
+            t.local_normal_at(Tup::point(-0.2, 0.3, 0.0))
+        );
+        assert_eq!(Tup::vector(0, 1, 0), t.local_normal_at(Tup::point(0, 1, 0)));
+    }
+
+    #[test]
+    fn a_smooth_triangle_is_hit_like_a_flat_one() {
+        let ray = Ray::new(Tup::point(-0.2, 0.3, -2.0), Tup::vector(0, 0, 1));
+        let xs = smooth_triangle().intersect(&ray);
+        assert_eq!(1, xs.len());
+        assert_nearly_eq(2.0, xs[0].t());
+    }
+}
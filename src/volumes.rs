@@ -0,0 +1,348 @@
+use crate::noise;
+use crate::tup::Tup;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+// How the density of a medium varies through space. Densities scale the
+// medium's absorption and scattering, so they are usually between zero and
+// one. They are looked up in the space of the object that bounds the
+// medium, or in world space for fog
+#[derive(PartialEq, Clone, Debug, Default)]
+pub enum Density {
+    // The same everywhere
+    #[default]
+    Uniform,
+    // Fractal noise, like clouds or billowing smoke. `frequency` sets the
+    // size of the largest puffs, `octaves` the amount of finer detail, and
+    // noise below `threshold` is cut away to leave gaps of clear air
+    Noise {
+        frequency: f64,
+        octaves: usize,
+        threshold: f64,
+    },
+    // Densities sampled on a grid, see `DensityGrid`
+    Grid(Arc<DensityGrid>),
+}
+
+impl Density {
+    pub fn noise(frequency: f64, octaves: usize, threshold: f64) -> Self {
+        Self::Noise {
+            frequency,
+            octaves,
+            threshold,
+        }
+    }
+
+    // Grids are shared between every copy of the medium
+    pub fn grid(grid: DensityGrid) -> Self {
+        Self::Grid(Arc::new(grid))
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        *self == Self::Uniform
+    }
+
+    pub fn at(&self, point: Tup) -> f64 {
+        match self {
+            Self::Uniform => 1.0,
+            Self::Noise {
+                frequency,
+                octaves,
+                threshold,
+            } => {
+                // Cutting away everything up to one leaves nothing
+                if *threshold >= 1.0 {
+                    return 0.0;
+                }
+                let value = 0.5 + 0.5 * noise::fbm(point * *frequency, *octaves);
+                ((value - threshold) / (1.0 - threshold)).clamp(0.0, 1.0)
+            }
+            Self::Grid(grid) => grid.at(point),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VoxelError {
+    EmptyGrid,
+    SizeMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for VoxelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxelError::EmptyGrid => write!(f, "voxel grid has no cells"),
+            VoxelError::SizeMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "voxel data has the wrong size, expected {expected} but got {actual}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for VoxelError {}
+
+// Densities on a regular grid of voxels filling the cube from -1 to 1 on
+// every axis, the bounds of the default sphere. Densities between voxel
+// centers are interpolated and outside the cube they are zero
+pub struct DensityGrid {
+    width: usize,
+    height: usize,
+    depth: usize,
+    densities: Vec<f64>,
+}
+
+impl DensityGrid {
+    // Densities ordered with x varying fastest, then y, then z
+    pub fn new(
+        width: usize,
+        height: usize,
+        depth: usize,
+        densities: Vec<f64>,
+    ) -> Result<Self, VoxelError> {
+        let expected = Self::voxel_count(width, height, depth, 1);
+        if expected == Some(0) {
+            return Err(VoxelError::EmptyGrid);
+        }
+        Self::check_len(expected, densities.len())?;
+        Ok(Self {
+            width,
+            height,
+            depth,
+            densities,
+        })
+    }
+
+    // A raw voxel file of one byte per voxel, where 255 is a density of one
+    pub fn from_raw_u8(
+        width: usize,
+        height: usize,
+        depth: usize,
+        bytes: &[u8],
+    ) -> Result<Self, VoxelError> {
+        Self::check_len(Self::voxel_count(width, height, depth, 1), bytes.len())?;
+        let densities = bytes.iter().map(|&b| b as f64 / 255.0).collect();
+        Self::new(width, height, depth, densities)
+    }
+
+    // A raw voxel file of little endian 32 bit floats
+    pub fn from_raw_f32(
+        width: usize,
+        height: usize,
+        depth: usize,
+        bytes: &[u8],
+    ) -> Result<Self, VoxelError> {
+        Self::check_len(Self::voxel_count(width, height, depth, 4), bytes.len())?;
+        let densities = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect();
+        Self::new(width, height, depth, densities)
+    }
+
+    // The number of values a grid of this size takes, or `None` if there are
+    // too many to count
+    fn voxel_count(width: usize, height: usize, depth: usize, each: usize) -> Option<usize> {
+        [height, depth, each]
+            .into_iter()
+            .try_fold(width, |count, n| count.checked_mul(n))
+    }
+
+    // Sizes too large to count can't be matched by any data
+    fn check_len(expected: Option<usize>, actual: usize) -> Result<(), VoxelError> {
+        match expected {
+            Some(expected) if expected == actual => Ok(()),
+            expected => Err(VoxelError::SizeMismatch {
+                expected: expected.unwrap_or(usize::MAX),
+                actual,
+            }),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.densities[(z * self.height + y) * self.width + x]
+    }
+
+    // Trilinear interpolation between the centers of the nearest voxels
+    pub fn at(&self, point: Tup) -> f64 {
+        let inside = |c: f64| (-1.0..=1.0).contains(&c);
+        if !(inside(point.x) && inside(point.y) && inside(point.z)) {
+            return 0.0;
+        }
+        // Continuous voxel coordinates, with voxel centers at whole numbers
+        let coordinate = |c: f64, size: usize| {
+            let v = (c + 1.0) / 2.0 * size as f64 - 0.5;
+            let v = v.clamp(0.0, (size - 1) as f64);
+            let i = (v.floor() as usize).min(size.saturating_sub(2));
+            (i, (v - i as f64).clamp(0.0, 1.0))
+        };
+        let (x0, fx) = coordinate(point.x, self.width);
+        let (y0, fy) = coordinate(point.y, self.height);
+        let (z0, fz) = coordinate(point.z, self.depth);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let z1 = (z0 + 1).min(self.depth - 1);
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+        let along_x = |y, z| lerp(fx, self.voxel(x0, y, z), self.voxel(x1, y, z));
+        let along_y = |z| lerp(fy, along_x(y0, z), along_x(y1, z));
+        lerp(fz, along_y(z0), along_y(z1))
+    }
+}
+
+// Grids can hold millions of voxels, far too many to print or compare. Two
+// grids are equal only if they are the same grid
+impl fmt::Debug for DensityGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DensityGrid {{ width: {}, height: {}, depth: {}, at: {:p} }}",
+            self.width, self.height, self.depth, self
+        )
+    }
+}
+
+impl PartialEq for DensityGrid {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod volumes_test {
+    use super::*;
+    use crate::test_helpers::assert_nearly_eq;
+
+    #[test]
+    fn uniform_density_is_one_everywhere() {
+        let d = Density::default();
+        assert!(d.is_uniform());
+        assert_eq!(1.0, d.at(Tup::point(0, 0, 0)));
+        assert_eq!(1.0, d.at(Tup::point(100, -3, 2)));
+    }
+
+    #[test]
+    fn noise_density_varies_between_zero_and_one() {
+        let d = Density::noise(2.0, 4, 0.5);
+        assert!(!d.is_uniform());
+        let values: Vec<f64> = (0..200)
+            .map(|i| d.at(Tup::point(i as f64 * 0.13, i as f64 * 0.07, 0.3)))
+            .collect();
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(values.contains(&0.0));
+        assert!(values.iter().any(|v| *v > 0.1));
+    }
+
+    #[test]
+    fn noise_cut_away_at_one_leaves_clear_air() {
+        let d = Density::noise(2.0, 4, 1.0);
+        for i in 0..50 {
+            let p = Tup::point(i as f64 * 0.13, i as f64 * 0.07, 0.3);
+            assert_eq!(0.0, d.at(p));
+        }
+    }
+
+    #[test]
+    fn a_grid_must_have_one_density_per_voxel() {
+        assert_eq!(
+            Some(VoxelError::SizeMismatch {
+                expected: 8,
+                actual: 7
+            }),
+            DensityGrid::new(2, 2, 2, vec![0.0; 7]).err()
+        );
+        assert_eq!(
+            Some(VoxelError::EmptyGrid),
+            DensityGrid::new(0, 2, 2, vec![]).err()
+        );
+    }
+
+    #[test]
+    fn a_grid_is_read_from_raw_bytes() {
+        let grid = DensityGrid::from_raw_u8(2, 1, 1, &[0, 255]).expect("Valid voxel data");
+        assert_eq!(0.0, grid.voxel(0, 0, 0));
+        assert_eq!(1.0, grid.voxel(1, 0, 0));
+    }
+
+    #[test]
+    fn a_grid_is_read_from_raw_floats() {
+        let bytes: Vec<u8> = [0.25_f32, 0.5, 0.75, 1.0]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let grid = DensityGrid::from_raw_f32(1, 2, 2, &bytes).expect("Valid voxel data");
+        assert_eq!(0.25, grid.voxel(0, 0, 0));
+        assert_eq!(0.5, grid.voxel(0, 1, 0));
+        assert_eq!(0.75, grid.voxel(0, 0, 1));
+        assert_eq!(1.0, grid.voxel(0, 1, 1));
+    }
+
+    #[test]
+    fn truncated_raw_data_is_an_error() {
+        assert_eq!(
+            Some(VoxelError::SizeMismatch {
+                expected: 16,
+                actual: 12
+            }),
+            DensityGrid::from_raw_f32(2, 2, 1, &[0; 12]).err()
+        );
+    }
+
+    #[test]
+    fn a_grid_is_interpolated_between_voxel_centers() {
+        let grid = DensityGrid::from_raw_u8(2, 1, 1, &[0, 255]).expect("Valid voxel data");
+        // Voxel centers are at x = -0.5 and x = 0.5
+        assert_nearly_eq(0.0, grid.at(Tup::point(-0.5, 0.0, 0.0)));
+        assert_nearly_eq(0.5, grid.at(Tup::point(0.0, 0.0, 0.0)));
+        assert_nearly_eq(1.0, grid.at(Tup::point(0.5, 0.0, 0.0)));
+        assert_nearly_eq(0.0, grid.at(Tup::point(-0.9, 0.0, 0.0)));
+        assert_nearly_eq(1.0, grid.at(Tup::point(0.9, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn sizes_too_large_to_count_are_an_error() {
+        let huge = usize::MAX / 2;
+        let mismatch = Some(VoxelError::SizeMismatch {
+            expected: usize::MAX,
+            actual: 8,
+        });
+        assert_eq!(mismatch, DensityGrid::new(huge, 2, 2, vec![0.0; 8]).err());
+        assert_eq!(
+            mismatch,
+            DensityGrid::from_raw_u8(huge, 2, 2, &[0; 8]).err()
+        );
+        assert_eq!(
+            mismatch,
+            DensityGrid::from_raw_f32(huge, 1, 1, &[0; 8]).err()
+        );
+    }
+
+    #[test]
+    fn a_grid_is_empty_outside_its_bounds() {
+        let grid = DensityGrid::new(1, 1, 1, vec![1.0]).expect("Valid voxel data");
+        assert_eq!(1.0, grid.at(Tup::point(0, 0, 0)));
+        assert_eq!(0.0, grid.at(Tup::point(1.5, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn grids_are_only_equal_to_themselves() {
+        let a = Density::grid(DensityGrid::new(1, 1, 1, vec![1.0]).expect("Valid"));
+        let b = Density::grid(DensityGrid::new(1, 1, 1, vec![1.0]).expect("Valid"));
+        assert_eq!(a, a);
+        assert_ne!(a, b);
+    }
+}
@@ -1,10 +1,19 @@
+use crate::background::Background;
 use crate::color::consts as col;
 use crate::color::Color;
 use crate::intersections::{Computations, Intersections};
+use crate::irradiance::IrradianceMap;
 use crate::lights::Light;
+use crate::materials::Material;
 use crate::math_helpers::nearly_eq;
+use crate::matrix::Mat4;
+use crate::media::Medium;
+use crate::meshes::{Mesh, MeshData};
 use crate::rays::Ray;
+use crate::sampling::{self, Rng};
 use crate::shapes::Shape;
+use crate::spectrum;
+use crate::subsurface;
 use crate::tup::Tup;
 use std::ops::{Index, IndexMut};
 
@@ -14,11 +23,40 @@ type Object = Box<dyn Shape>;
 pub struct World {
     light: Light,
     objects: Vec<Object>,
+    background: Background,
+    irradiance: Option<IrradianceMap>,
+    // Fills all the space outside of objects
+    fog: Option<Medium>,
 }
 
 impl World {
     pub const MAX_BOUNCES: usize = 5;
 
+    // Rays averaged for a rough reflection or refraction seen directly by the
+    // camera. Every further bounce halves the count so nested glossy surfaces
+    // don't multiply the work beyond reason
+    pub const GLOSSY_SAMPLES: usize = 16;
+
+    // The half angle of the cone rough reflections and refractions are
+    // scattered into when a material's roughness is 1.0
+    pub const MAX_GLOSSY_ANGLE: f64 = std::f64::consts::FRAC_PI_2;
+
+    // The wavelengths, in nanometers, standing in for the red, green and
+    // blue channels when light is split by a dispersive material
+    pub const DISPERSION_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+    // Points sampled along a ray through fog or smoke to gather the light
+    // scattered towards the eye
+    pub const MEDIUM_SAMPLES: usize = 16;
+
+    // Steps taken through media whose density varies, which need more to
+    // catch the detail
+    pub const VOLUME_STEPS: usize = 32;
+
+    // Random walks averaged to find the light scattered out of a
+    // translucent material under the surface
+    pub const SUBSURFACE_WALKS: usize = 16;
+
     pub fn with_light(self, light: Light) -> Self {
         Self { light, ..self }
     }
@@ -28,10 +66,68 @@ impl World {
         self
     }
 
+    // Every face of the mesh as a separate triangle
+    pub fn with_mesh(mut self, mesh: &MeshData, material: Material) -> Self {
+        self.objects.extend(mesh.triangles(material));
+        self
+    }
+
+    // The whole mesh as a single shape, which is far quicker to intersect
+    // for meshes of more than a few faces
+    pub fn with_indexed_mesh(self, mesh: MeshData, material: Material) -> Self {
+        self.with_object(Mesh::new(mesh).with_material(material))
+    }
+
+    // Image based lighting, if on, follows the new background
+    pub fn with_background(self, background: Background) -> Self {
+        let irradiance = self
+            .irradiance
+            .as_ref()
+            .map(|_| IrradianceMap::from_background(&background));
+        Self {
+            background,
+            irradiance,
+            ..self
+        }
+    }
+
+    // Lights diffuse surfaces with the background instead of the constant
+    // ambient term of each material
+    pub fn with_image_based_lighting(self) -> Self {
+        let irradiance = IrradianceMap::from_background(&self.background);
+        Self {
+            irradiance: Some(irradiance),
+            ..self
+        }
+    }
+
+    pub fn with_fog(self, fog: Medium) -> Self {
+        Self {
+            fog: Some(fog),
+            ..self
+        }
+    }
+
     pub fn light(&self) -> Light {
         self.light
     }
 
+    pub fn fog(&self) -> Option<Medium> {
+        self.fog.clone()
+    }
+
+    // The light as seen by rays of the given wavelength, if any
+    pub fn light_for(&self, wavelength: Option<f64>) -> Light {
+        match wavelength {
+            Some(wavelength) => self.light.at_wavelength(wavelength),
+            None => self.light,
+        }
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
     pub fn num_objects(&self) -> usize {
         self.objects.len()
     }
@@ -46,33 +142,166 @@ impl World {
     }
 
     pub fn shade_hit(&self, comps: &Computations, remaining_bounces: usize) -> Color {
-        let shadowed = self.is_shadowed(comps.over_point());
-        let surface = comps.object().material().lighting(
-            comps.object().transform(),
-            self.light,
-            comps.over_point(),
-            comps.eyev(),
-            comps.normalv(),
-            shadowed,
-        );
+        let light_factor = self.light_transmittance(comps.over_point(), comps.wavelength());
+        let material = comps.material();
+        let light = self.light_for(comps.wavelength());
+        let surface = match self.irradiance {
+            Some(irradiance) => material.lighting_with_irradiance(
+                comps.object().world_to_object(),
+                light,
+                comps.over_point(),
+                comps.eyev(),
+                comps.normalv(),
+                light_factor,
+                spectrum::monochrome(
+                    irradiance.irradiance_at(comps.normalv()),
+                    comps.wavelength(),
+                ),
+            ),
+            None => material.lighting(
+                comps.object().world_to_object(),
+                light,
+                comps.over_point(),
+                comps.eyev(),
+                comps.normalv(),
+                light_factor,
+            ),
+        };
+        // Light from beneath the clearcoat is dimmed by what the coat
+        // reflects. `lighting` has already taken care of the direct light
+        let coat = if comps.inside() {
+            0.0
+        } else {
+            material.clearcoat_reflectance(comps.normalv().dot(&comps.eyev()))
+        };
+        let beneath = material.emission();
+        let beneath = if material.has_subsurface() && !comps.inside() {
+            beneath + self.subsurface_color(comps, &material)
+        } else {
+            beneath
+        };
         let reflection = self.reflected_color(comps, remaining_bounces);
         let refraction = self.refracted_color(comps, remaining_bounces);
-        let material = comps.object().material();
-        if material.reflective() > 0.0 && material.transparency() > 0.0 {
+        let beneath = if material.reflective() > 0.0 && material.transparency() > 0.0 {
             let reflectance = comps.schlick();
-            return surface + (reflection * reflectance) + (refraction * (1.0 - reflectance));
+            beneath + (reflection * reflectance) + (refraction * (1.0 - reflectance))
+        } else {
+            beneath + reflection + refraction
+        };
+        let color = if coat > 0.0 {
+            surface
+                + beneath * (1.0 - coat)
+                + self.clearcoat_color(comps, &material, remaining_bounces) * coat
+        } else {
+            surface + beneath
+        };
+        // Whatever the ray carries is dimmed by the material it crossed to
+        // reach this point, which need not be the one it hit
+        match comps.n1_material() {
+            Some(crossed) => color * crossed.transmittance(comps.t() * comps.eyev().magnitude()),
+            None => color,
         }
-        surface + reflection + refraction
+    }
+
+    // Light from the light source that entered the object somewhere nearby
+    // and wandered under the surface to come out here. Walks are traced
+    // from this point into the object, which by symmetry finds where the
+    // light could have entered
+    fn subsurface_color(&self, comps: &Computations, material: &Material) -> Color {
+        let mut rng = Rng::for_point(comps.point());
+        let total =
+            (0..Self::SUBSURFACE_WALKS).fold(col::BLACK, |total, _| match subsurface::random_walk(
+                comps.object().as_ref(),
+                comps.under_point(),
+                comps.normalv(),
+                material,
+                &mut rng,
+            ) {
+                Some(exit) => {
+                    total
+                        + exit.throughput()
+                            * self.direct_light(exit.point(), exit.normal(), comps.wavelength())
+                }
+                None => total,
+            });
+        let albedo = material.subsurface_albedo(comps.object().world_to_object(), comps.point());
+        albedo * total * (1.0 / Self::SUBSURFACE_WALKS as f64)
+    }
+
+    // The light arriving at a surface point with the given normal straight
+    // from the light source, scaled by the angle it arrives at
+    pub fn direct_light(&self, point: Tup, normal: Tup, wavelength: Option<f64>) -> Color {
+        let light = self.light_for(wavelength);
+        let lightv = (light.position() - point).normalize();
+        let light_dot_normal = lightv.dot(&normal);
+        if light_dot_normal <= 0.0 {
+            return col::BLACK;
+        }
+        light.intensity() * light_dot_normal * self.light_transmittance(point, wavelength)
     }
 
     fn calc_reflected(&self, comps: &Computations, remaining_bounces: usize) -> Color {
-        let reflective = comps.object().material().reflective();
+        let reflective = comps.material().reflective();
         if nearly_eq(0.0, reflective) {
             col::BLACK
         } else {
-            let r = Ray::new(comps.over_point(), comps.reflectv());
-            self.color_at(r, remaining_bounces - 1) * reflective
+            let r = comps.spawn_ray(comps.over_point(), comps.reflectv());
+            let roughness = comps.material().roughness();
+            self.glossy_color_at(r, comps.normalv(), roughness, remaining_bounces) * reflective
+        }
+    }
+
+    // What the clearcoat mirrors, blurred by the coat's own roughness
+    fn clearcoat_color(
+        &self,
+        comps: &Computations,
+        material: &Material,
+        remaining_bounces: usize,
+    ) -> Color {
+        if remaining_bounces == 0 {
+            return col::BLACK;
+        }
+        let r = comps.spawn_ray(comps.over_point(), comps.reflectv());
+        self.glossy_color_at(
+            r,
+            comps.normalv(),
+            material.clearcoat_roughness(),
+            remaining_bounces,
+        )
+    }
+
+    // The color seen along `ray`, or for rough materials the average color
+    // seen along rays scattered in a cone around it. Scattered rays that
+    // would cross to the wrong side of `side_normal` are replaced by `ray`
+    fn glossy_color_at(
+        &self,
+        ray: Ray,
+        side_normal: Tup,
+        roughness: f64,
+        remaining_bounces: usize,
+    ) -> Color {
+        if nearly_eq(0.0, roughness) {
+            return self.color_at(ray, remaining_bounces - 1);
+        }
+        let depth = Self::MAX_BOUNCES.saturating_sub(remaining_bounces);
+        let samples = (Self::GLOSSY_SAMPLES >> depth.min(usize::BITS as usize - 1)).max(1);
+        let half_angle = roughness.min(1.0) * Self::MAX_GLOSSY_ANGLE;
+        let mut rng = Rng::for_point(ray.origin());
+        let mut sum = col::BLACK;
+        for _ in 0..samples {
+            let direction = sampling::uniform_cone(ray.direction(), half_angle, &mut rng);
+            let direction = if direction.dot(&side_normal) > 0.0 {
+                direction
+            } else {
+                ray.direction()
+            };
+            sum = sum
+                + self.color_at(
+                    ray.redirected(ray.origin(), direction),
+                    remaining_bounces - 1,
+                );
         }
+        sum * (1.0 / samples as f64)
     }
 
     pub fn reflected_color(&self, comps: &Computations, remaining_bounces: usize) -> Color {
@@ -85,38 +314,211 @@ impl World {
 
     pub fn color_at(&self, ray: Ray, remaining_bounces: usize) -> Color {
         let intersections = self.intersect(ray);
-        intersections
+        let hit = intersections
             .hit()
-            .map(|i| {
-                self.shade_hit(
-                    &i.prepare_computations(&ray, &intersections),
-                    remaining_bounces,
-                )
-            })
-            .unwrap_or(col::BLACK)
+            .map(|i| i.prepare_computations(&ray, &intersections));
+        let color = match &hit {
+            Some(comps) => self.shade_hit(comps, remaining_bounces),
+            None => self.background_color_at(ray),
+        };
+        let (transmittance, in_scattered) = self.media_along(ray, hit.as_ref());
+        color * transmittance + in_scattered
+    }
+
+    // The effect of any fog or smoke between the origin of `ray` and `hit`,
+    // or all the way out of the scene when nothing is hit. Returns the
+    // fraction of the light from the far end that gets through, and the
+    // light scattered towards the ray origin along the way
+    pub fn media_along(&self, ray: Ray, hit: Option<&Computations>) -> (Color, Color) {
+        let crossed = hit.and_then(|comps| Some((comps.n1_object()?, comps.n1_material()?)));
+        let (medium, to_local) = match crossed {
+            // The ray was travelling through an object, so it is inside the
+            // object's medium rather than the fog
+            Some((object, material)) => (material.medium(), object.world_to_object()),
+            None => (self.fog.clone(), Mat4::identity_matrix()),
+        };
+        let medium = match medium {
+            Some(medium) => medium.at_wavelength(ray.wavelength()),
+            None => return (col::WHITE, col::BLACK),
+        };
+        let speed = ray.direction().magnitude();
+        // Varying fog is cut off where fog of full density would hide
+        // everything beyond, which may be short of where it really does
+        let distance = match hit {
+            Some(comps) => comps.t() * speed,
+            None => medium.extent(),
+        };
+        if !distance.is_finite() {
+            return (col::WHITE, col::BLACK);
+        }
+        let scatters = medium.scattering() != col::BLACK;
+        if medium.is_homogeneous() && !scatters {
+            return (medium.transmittance(distance), col::BLACK);
+        }
+
+        // March along the ray, dimming the light from beyond by the medium
+        // crossed at each step. With single scattering, light arriving
+        // straight from the light source is also scattered towards the
+        // origin at each step. Points in shadow receive none, which is what
+        // makes shafts of light
+        let light = self.light_for(ray.wavelength());
+        let steps = if medium.is_homogeneous() {
+            Self::MEDIUM_SAMPLES
+        } else {
+            Self::VOLUME_STEPS
+        };
+        let step = distance / steps as f64;
+        let jitter = Rng::for_point(ray.origin()).next_f64();
+        let mut transmittance = col::WHITE;
+        let mut in_scattered = col::BLACK;
+        for i in 0..steps {
+            let s = (i as f64 + jitter) * step;
+            let point = ray.position(s / speed);
+            let density = medium.density_at(to_local * point);
+            if scatters && density > 0.0 {
+                let arriving =
+                    light.intensity() * self.light_transmittance(point, ray.wavelength());
+                let to_point = transmittance * medium.transmittance(density * jitter * step);
+                in_scattered = in_scattered
+                    + arriving * to_point * medium.scattering() * (density * Medium::PHASE * step);
+            }
+            transmittance = transmittance * medium.transmittance(density * step);
+        }
+        (transmittance, in_scattered)
+    }
+
+    // The fraction of light that gets through `medium` along the straight
+    // line from `from` to `to`. `to_local` takes points to the space the
+    // medium's density is defined in
+    fn medium_transmittance(medium: Medium, to_local: Mat4, from: Tup, to: Tup) -> Color {
+        let distance = (to - from).magnitude();
+        if medium.is_homogeneous() {
+            return medium.transmittance(distance);
+        }
+        let step = distance / Self::VOLUME_STEPS as f64;
+        (0..Self::VOLUME_STEPS).fold(col::WHITE, |transmittance, i| {
+            let fraction = (i as f64 + 0.5) / Self::VOLUME_STEPS as f64;
+            let point = from + (to - from) * fraction;
+            transmittance * medium.transmittance(medium.density_at(to_local * point) * step)
+        })
+    }
+
+    // The color of the background seen along `ray`
+    pub fn background_color_at(&self, ray: Ray) -> Color {
+        spectrum::monochrome(self.background.color_at(ray.direction()), ray.wavelength())
     }
 
     pub fn is_shadowed(&self, point: Tup) -> bool {
+        self.light_transmittance(point, None) == col::BLACK
+    }
+
+    // The fraction of the light that reaches `point` in each channel. Every
+    // surface crossed on the way dims the light by its transparency, and
+    // absorbing materials tint it over the distance travelled inside them.
+    // Fog and smoke dim it too. Objects that don't cast shadows are skipped
+    // entirely, along with any smoke inside them. When given a
+    // wavelength the result is gray, the transmittance at that wavelength
+    pub fn light_transmittance(&self, point: Tup, wavelength: Option<f64>) -> Color {
         let point_to_lightv = self.light().position() - point;
         let distance = point_to_lightv.magnitude();
         let ray = Ray::new(point, point_to_lightv.normalize());
         let inters = self.intersect(ray);
-        inters.hit().map_or(false, |i| i.t() < distance)
+        // Objects the shadow ray is currently inside, with the t at which it
+        // entered them. Shapes are cloned into their intersections, so each
+        // is known by its debug output, worked out once per hit
+        let mut containers: Vec<(String, f64)> = Vec::new();
+        let mut transmittance = col::WHITE;
+        for inter in inters
+            .iter()
+            .filter(|i| i.object().material().casts_shadow())
+        {
+            if inter.t() >= distance {
+                break;
+            }
+            let object = inter.object().as_ref();
+            let material = match wavelength {
+                Some(wavelength) => object.material().at_wavelength(wavelength),
+                None => object.material(),
+            };
+            let key = format!("{:?}", object);
+            let entered = containers
+                .iter()
+                .position(|(container, _)| *container == key);
+            let crossed_inside = match entered {
+                Some(j) => Some(containers.remove(j).1),
+                None => {
+                    containers.push((key, inter.t()));
+                    None
+                }
+            };
+            if inter.t() <= 0.0 {
+                continue;
+            }
+            // Each object dims the light by its transparency once, where the
+            // ray goes in, or where it comes out if it started inside
+            match crossed_inside {
+                Some(entry_t) if entry_t > 0.0 => {}
+                _ => transmittance = transmittance * material.transparency(),
+            }
+            if let Some(entry_t) = crossed_inside {
+                let inside = inter.t() - entry_t.max(0.0);
+                transmittance = transmittance * material.transmittance(inside);
+                if let Some(medium) = material.medium() {
+                    transmittance = transmittance
+                        * Self::medium_transmittance(
+                            medium.at_wavelength(wavelength),
+                            object.world_to_object(),
+                            ray.position(entry_t.max(0.0)),
+                            ray.position(inter.t()),
+                        );
+                }
+            }
+            if transmittance == col::BLACK {
+                break;
+            }
+        }
+        match &self.fog {
+            Some(fog) => {
+                transmittance
+                    * Self::medium_transmittance(
+                        fog.at_wavelength(wavelength),
+                        Mat4::identity_matrix(),
+                        point,
+                        self.light.position(),
+                    )
+            }
+            None => transmittance,
+        }
     }
 
     pub fn refracted_color(&self, comps: &Computations, remaining_bounces: usize) -> Color {
-        let transparency = comps.object().material().transparency();
-        let n_ratio = comps.n1() / comps.n2();
-        let cos_i = comps.eyev().dot(&comps.normalv());
-        let sin2_t = (n_ratio * n_ratio) * (1.0 - (cos_i * cos_i));
-        let total_internal_reflection = sin2_t > 1.0;
-        if remaining_bounces < 1 || total_internal_reflection || nearly_eq(0.0, transparency) {
+        let transparency = comps.material().transparency();
+        if remaining_bounces < 1 || nearly_eq(0.0, transparency) {
             return col::BLACK;
         }
-        let cos_t = (1.0 - sin2_t).sqrt();
-        let direction = comps.normalv() * (n_ratio * cos_i - cos_t) - comps.eyev() * n_ratio;
-        let refract_ray = Ray::new(comps.under_point(), direction);
-        self.color_at(refract_ray, remaining_bounces - 1) * comps.object().material().transparency()
+        if comps.wavelength().is_none() && comps.is_dispersive() {
+            // Each channel is refracted at its own angle and traced on its
+            // own, which is what fans white light out into a spectrum
+            return Self::DISPERSION_WAVELENGTHS
+                .iter()
+                .zip([col::RED, col::GREEN, col::BLUE])
+                .fold(col::BLACK, |sum, (&wavelength, channel)| {
+                    let comps = comps.at_wavelength(wavelength);
+                    sum + self.refracted_color(&comps, remaining_bounces) * channel
+                });
+        }
+        match comps.refractv() {
+            Some(direction) => {
+                let refract_ray = comps.spawn_ray(comps.under_point(), direction);
+                self.glossy_color_at(
+                    refract_ray,
+                    -comps.normalv(),
+                    comps.material().roughness(),
+                    remaining_bounces,
+                ) * transparency
+            }
+            None => col::BLACK,
+        }
     }
 }
 
@@ -125,6 +527,9 @@ impl Default for World {
         Self {
             light: Light::point_light(Tup::point(0, 0, 0), col::BLACK),
             objects: Vec::new(),
+            background: Background::default(),
+            irradiance: None,
+            fog: None,
         }
     }
 }
@@ -146,14 +551,23 @@ impl IndexMut<usize> for World {
 #[cfg(test)]
 mod world_test {
     use super::*;
+    use crate::groups::Group;
+    use crate::instances::Instance;
     use crate::intersections::Intersection;
     use crate::materials::Material;
+    use crate::media::Medium;
     use crate::patterns::Pattern;
     use crate::planes::Plane;
+    use crate::spectrum::Spectrum;
     use crate::spheres::Sphere;
-    use crate::test_helpers::{assert_nearly_eq, default_test_world};
+    use crate::test_helpers::{
+        assert_nearly_eq, clear_glass, default_test_world, lone_object_world,
+        plane_seen_at_45_degrees,
+    };
     use crate::transforms::{self, translation};
+    use crate::volumes::{Density, DensityGrid};
     use std::f64::consts;
+    use std::sync::Arc;
 
     #[test]
     fn an_new_world_has_default_black_light_source() {
@@ -170,6 +584,49 @@ mod world_test {
         assert_eq!(0, world.num_objects())
     }
 
+    #[test]
+    fn a_mesh_is_added_a_triangle_at_a_time() {
+        let mesh = crate::stl::decode(
+            b"solid quad
+              facet normal 0 0 -1 outer loop
+                vertex -1 -1 0 vertex 1 -1 0 vertex 1 1 0
+              endloop endfacet
+              facet normal 0 0 -1 outer loop
+                vertex -1 -1 0 vertex 1 1 0 vertex -1 1 0
+              endloop endfacet
+            endsolid quad",
+        )
+        .expect("Valid stl data");
+        let m = Material::default().with_color(Color::new(1, 0, 0));
+        let world = World::default().with_mesh(&mesh, m.clone());
+        assert_eq!(2, world.num_objects());
+        let xs = world.intersect(Ray::new(Tup::point(0.5, -0.2, -5.0), Tup::vector(0, 0, 1)));
+        assert_eq!(1, xs.len());
+        assert_eq!(m, xs[0].object().material());
+    }
+
+    #[test]
+    fn an_indexed_mesh_is_added_as_one_shape() {
+        let mesh = crate::stl::decode(
+            b"solid quad
+              facet normal 0 0 -1 outer loop
+                vertex -1 -1 0 vertex 1 -1 0 vertex 1 1 0
+              endloop endfacet
+              facet normal 0 0 -1 outer loop
+                vertex -1 -1 0 vertex 1 1 0 vertex -1 1 0
+              endloop endfacet
+            endsolid quad",
+        )
+        .expect("Valid stl data");
+        let m = Material::default().with_color(Color::new(1, 0, 0));
+        let world = World::default().with_indexed_mesh(mesh, m.clone());
+        assert_eq!(1, world.num_objects());
+        let xs = world.intersect(Ray::new(Tup::point(0.5, -0.2, -5.0), Tup::vector(0, 0, 1)));
+        assert_eq!(1, xs.len());
+        assert_eq!(m, xs[0].object().material());
+        assert_eq!(Some(0), xs[0].face());
+    }
+
     #[test]
     fn intersect_a_world_with_a_ray() {
         let world = default_test_world();
@@ -215,6 +672,57 @@ mod world_test {
         assert_eq!(Color::new(0, 0, 0), c);
     }
 
+    #[test]
+    fn the_color_when_a_ray_misses_an_object_is_the_background() {
+        let w = default_test_world().with_background(Background::solid(col::CYAN));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 1, 0));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert_eq!(col::CYAN, c);
+    }
+
+    #[test]
+    fn the_background_is_looked_up_by_ray_direction() {
+        let w = World::default().with_background(Background::gradient(col::WHITE, col::BLUE));
+        let up = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, 1, 0));
+        let level = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, 0, 1));
+        assert_eq!(col::BLUE, w.color_at(up, World::MAX_BOUNCES));
+        assert_eq!(col::WHITE, w.color_at(level, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn image_based_lighting_replaces_the_ambient_term() {
+        let material = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.5)
+            .with_specular(0.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(Color::new(1.0, 0.5, 0.0)))
+            .with_image_based_lighting();
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = world.color_at(r, World::MAX_BOUNCES);
+        assert!((c.red() - 0.5).abs() < 1e-3);
+        assert!((c.green() - 0.25).abs() < 1e-3);
+        assert!(c.blue().abs() < 1e-3);
+    }
+
+    #[test]
+    fn image_based_lighting_follows_a_later_background() {
+        let material = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.5)
+            .with_specular(0.0);
+        let world = World::default()
+            .with_object(Sphere::default().with_material(material))
+            .with_background(Background::solid(Color::new(1.0, 0.5, 0.0)))
+            .with_image_based_lighting()
+            .with_background(Background::solid(Color::new(0.0, 0.0, 1.0)));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = world.color_at(r, World::MAX_BOUNCES);
+        assert!(c.red().abs() < 1e-3);
+        assert!((c.blue() - 0.5).abs() < 1e-3);
+    }
+
     #[test]
     fn the_color_when_a_ray_hits_an_object_from_outside() {
         let w = default_test_world();
@@ -228,7 +736,7 @@ mod world_test {
         let mut w = default_test_world();
         let material = Material::default().with_ambient(1.0);
         let outer = &mut w[0];
-        outer.set_material(material);
+        outer.set_material(material.clone());
         let inner = &mut w[1];
         inner.set_material(material);
         let inner_color = inner.material().color();
@@ -265,6 +773,99 @@ mod world_test {
         assert!(!world.is_shadowed(p));
     }
 
+    #[test]
+    fn an_opaque_object_blocks_all_light() {
+        let world = lone_object_world(Sphere::default(), Background::default())
+            .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0, -5, 0);
+        assert_eq!(col::BLACK, world.light_transmittance(p, None));
+    }
+
+    #[test]
+    fn an_unobstructed_point_receives_all_light() {
+        let world = lone_object_world(Sphere::default(), Background::default())
+            .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(5, -5, 0);
+        assert_eq!(col::WHITE, world.light_transmittance(p, None));
+    }
+
+    #[test]
+    fn a_transparent_object_dims_the_light_once() {
+        let world = lone_object_world(
+            Sphere::default().with_material(Material::default().with_transparency(0.5)),
+            Background::default(),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0, -5, 0);
+        assert_eq!(
+            Color::new(0.5, 0.5, 0.5),
+            world.light_transmittance(p, None)
+        );
+        assert!(!world.is_shadowed(p));
+    }
+
+    #[test]
+    fn a_transparent_object_dims_light_from_inside_it_once() {
+        let world = lone_object_world(
+            Sphere::default().with_material(Material::default().with_transparency(0.5)),
+            Background::default(),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0.0, -0.5, 0.0);
+        assert_eq!(
+            Color::new(0.5, 0.5, 0.5),
+            world.light_transmittance(p, None)
+        );
+    }
+
+    #[test]
+    fn colored_glass_casts_a_colored_shadow() {
+        let glass = Material::default()
+            .with_transparency(1.0)
+            .with_absorption(Color::new(0.0, 1.0, 1.0));
+        let world = lone_object_world(
+            Sphere::default().with_material(glass),
+            Background::default(),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0, -5, 0);
+        let expected = (-2.0_f64).exp();
+        assert_eq!(
+            Color::new(1.0, expected, expected),
+            world.light_transmittance(p, None)
+        );
+    }
+
+    #[test]
+    fn light_is_absorbed_from_a_point_inside_colored_glass() {
+        let glass = Material::default()
+            .with_transparency(1.0)
+            .with_absorption(Color::new(0.0, 1.0, 1.0));
+        let world = lone_object_world(
+            Sphere::default().with_material(glass),
+            Background::default(),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0.0, -0.5, 0.0);
+        let expected = (-1.5_f64).exp();
+        assert_eq!(
+            Color::new(1.0, expected, expected),
+            world.light_transmittance(p, None)
+        );
+    }
+
+    #[test]
+    fn objects_that_dont_cast_shadows_let_all_light_through() {
+        let world = lone_object_world(
+            Sphere::default().with_material(Material::default().with_casts_shadow(false)),
+            Background::default(),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0, -5, 0);
+        assert_eq!(col::WHITE, world.light_transmittance(p, None));
+        assert!(!world.is_shadowed(p));
+    }
+
     #[test]
     fn shade_hit_responds_correctly_when_given_an_intersection_in_shadow() {
         let light = Light::point_light(Tup::point(0, 0, -10), col::WHITE);
@@ -273,7 +874,7 @@ mod world_test {
         let world = World::default()
             .with_light(light)
             .with_object(s1)
-            .with_object(s2);
+            .with_object(s2.clone());
         let ray = Ray::new(Tup::point(0, 0, 5), Tup::vector(0, 0, 1));
         let i = Intersection::new(4, s2);
         let comps = i.prepare_computations(&ray, &Intersections::new(&[i.clone()]));
@@ -281,6 +882,27 @@ mod world_test {
         assert_eq!(Color::new(0.1, 0.1, 0.1), color);
     }
 
+    #[test]
+    fn shade_hit_adds_emission_even_in_shadow() {
+        let light = Light::point_light(Tup::point(0, 0, -10), col::WHITE);
+        let glowing = Material::default()
+            .with_emissive(col::RED)
+            .with_emissive_strength(2.0);
+        let s1 = Sphere::default();
+        let s2 = Sphere::default()
+            .with_transform(translation(0, 0, 10))
+            .with_material(glowing);
+        let world = World::default()
+            .with_light(light)
+            .with_object(s1)
+            .with_object(s2.clone());
+        let ray = Ray::new(Tup::point(0, 0, 5), Tup::vector(0, 0, 1));
+        let i = Intersection::new(4, s2);
+        let comps = i.prepare_computations(&ray, &Intersections::new(std::slice::from_ref(&i)));
+        let color = world.shade_hit(&comps, World::MAX_BOUNCES);
+        assert_eq!(Color::new(2.1, 0.1, 0.1), color);
+    }
+
     #[test]
     fn the_reflected_color_for_a_nonreflective_material_is_black() {
         let world = default_test_world();
@@ -300,7 +922,7 @@ mod world_test {
         let shape = Plane::default()
             .with_material(material)
             .with_transform(translation(0, -1, 0));
-        let world = default_test_world().with_object(shape);
+        let world = default_test_world().with_object(shape.clone());
         let rad_2 = 2.0_f64.sqrt();
         let rad_2_over_2 = rad_2 / 2.0;
         let r = Ray::new(
@@ -319,7 +941,7 @@ mod world_test {
         let shape = Plane::default()
             .with_material(material)
             .with_transform(translation(0, -1, 0));
-        let world = default_test_world().with_object(shape);
+        let world = default_test_world().with_object(shape.clone());
         let rad_2 = 2.0_f64.sqrt();
         let rad_2_over_2 = rad_2 / 2.0;
         let r = Ray::new(
@@ -332,6 +954,41 @@ mod world_test {
         assert_eq!(Color::new(0.87676, 0.92435, 0.82918), color);
     }
 
+    #[test]
+    fn a_smooth_reflection_is_a_perfect_mirror_image() {
+        let mirror = Material::default().with_reflective(1.0).with_roughness(0.0);
+        let (world, comps) =
+            plane_seen_at_45_degrees(mirror, Background::gradient(col::BLACK, col::WHITE));
+        let color = world.reflected_color(&comps, World::MAX_BOUNCES);
+        let expected = consts::SQRT_2 / 2.0;
+        assert_eq!(Color::new(expected, expected, expected), color);
+    }
+
+    #[test]
+    fn a_rough_reflection_averages_a_cone_of_directions() {
+        let mirror = Material::default().with_reflective(1.0).with_roughness(0.3);
+        let (world, comps) =
+            plane_seen_at_45_degrees(mirror, Background::gradient(col::BLACK, col::WHITE));
+        let color = world.reflected_color(&comps, World::MAX_BOUNCES);
+        let mirror_image = consts::SQRT_2 / 2.0;
+        assert_ne!(Color::new(mirror_image, mirror_image, mirror_image), color);
+        // Every sample stays within the cone around the mirror direction
+        let half_angle = 0.3 * std::f64::consts::FRAC_PI_2;
+        let lowest = (std::f64::consts::FRAC_PI_4 - half_angle).sin();
+        let highest = (std::f64::consts::FRAC_PI_4 + half_angle).sin();
+        assert!(color.red() > lowest && color.red() < highest);
+    }
+
+    #[test]
+    fn rough_reflections_are_repeatable() {
+        let mirror = Material::default().with_reflective(1.0).with_roughness(0.5);
+        let (world, comps) =
+            plane_seen_at_45_degrees(mirror, Background::gradient(col::BLACK, col::WHITE));
+        let first = world.reflected_color(&comps, World::MAX_BOUNCES);
+        let second = world.reflected_color(&comps, World::MAX_BOUNCES);
+        assert_eq!(first, second);
+    }
+
     #[test]
     fn mutually_reflective_surfaces_dont_cause_infinite_recursion() {
         let lower = Plane::default()
@@ -349,12 +1006,80 @@ mod world_test {
         assert!(result.red() > 1.0 && result.green() > 1.0 && result.blue() > 1.0);
     }
 
+    #[test]
+    fn the_reflected_color_picks_up_the_background() {
+        let mirror = Plane::default()
+            .with_material(Material::default().with_reflective(1.0))
+            .with_transform(translation(0, -1, 0));
+        let world = World::default()
+            .with_background(Background::solid(col::YELLOW))
+            .with_object(mirror.clone());
+        let rad_2_over_2 = consts::SQRT_2 / 2.0;
+        let r = Ray::new(
+            Tup::point(0, 0, -3),
+            Tup::vector(0.0, -rad_2_over_2, rad_2_over_2),
+        );
+        let i = Intersection::new(consts::SQRT_2, mirror);
+        let comps = i.prepare_computations(&r, &Intersections::new(std::slice::from_ref(&i)));
+        let color = world.reflected_color(&comps, World::MAX_BOUNCES);
+        assert_eq!(col::YELLOW, color);
+    }
+
+    // A plane glowing red under a clearcoat, in an unlit world with a
+    // yellow sky, seen at 45 degrees
+    fn clearcoat_world(clearcoat: f64) -> (World, Computations, Material) {
+        let material = Material::default()
+            .with_ambient(0.0)
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_emissive(col::RED)
+            .with_clearcoat(clearcoat);
+        let plane = Plane::default()
+            .with_material(material.clone())
+            .with_transform(translation(0, -1, 0));
+        let world = World::default()
+            .with_background(Background::solid(col::YELLOW))
+            .with_object(plane.clone());
+        let rad_2_over_2 = consts::SQRT_2 / 2.0;
+        let r = Ray::new(
+            Tup::point(0, 0, -3),
+            Tup::vector(0.0, -rad_2_over_2, rad_2_over_2),
+        );
+        let i = Intersection::new(consts::SQRT_2, plane);
+        let comps = i.prepare_computations(&r, &Intersections::new(std::slice::from_ref(&i)));
+        (world, comps, material)
+    }
+
+    #[test]
+    fn without_a_clearcoat_nothing_is_reflected() {
+        let (world, comps, _) = clearcoat_world(0.0);
+        assert_eq!(col::RED, world.shade_hit(&comps, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn a_clearcoat_reflects_the_scene_over_the_base() {
+        let (world, comps, material) = clearcoat_world(1.0);
+        let coat = material.clearcoat_reflectance(consts::SQRT_2 / 2.0);
+        assert!(coat > 0.04 && coat < 0.05);
+        assert_eq!(
+            col::RED * (1.0 - coat) + col::YELLOW * coat,
+            world.shade_hit(&comps, World::MAX_BOUNCES)
+        );
+    }
+
+    #[test]
+    fn a_clearcoat_reflects_nothing_at_max_recursion_depth() {
+        let (world, comps, material) = clearcoat_world(1.0);
+        let coat = material.clearcoat_reflectance(consts::SQRT_2 / 2.0);
+        assert_eq!(col::RED * (1.0 - coat), world.shade_hit(&comps, 0));
+    }
+
     #[test]
     fn the_reflected_color_at_max_recursion_depth_is_black() {
         let shape = Plane::default()
             .with_material(Material::default().with_reflective(0.5))
             .with_transform(translation(0, -1, 0));
-        let world = default_test_world().with_object(shape);
+        let world = default_test_world().with_object(shape.clone());
         // let rad_2 = 2.0_f64.sqrt();
         let rad_2 = std::f64::consts::SQRT_2;
         let rad_2_over_2 = rad_2 / 2.0;
@@ -455,6 +1180,40 @@ mod world_test {
         assert_eq!(Color::new(0.0, 0.99888, 0.04722), color);
     }
 
+    #[test]
+    fn a_smooth_refraction_is_perfectly_clear() {
+        let pane = Material::default()
+            .with_transparency(1.0)
+            .with_refractive_index(1.0)
+            .with_roughness(0.0);
+        // Brightest looking straight down, black looking horizontally
+        let sky_below =
+            Background::gradient(col::BLACK, col::WHITE).with_transform(transforms::reflect_y());
+        let (world, comps) = plane_seen_at_45_degrees(pane, sky_below);
+        let color = world.refracted_color(&comps, World::MAX_BOUNCES);
+        let expected = consts::SQRT_2 / 2.0;
+        assert_eq!(Color::new(expected, expected, expected), color);
+    }
+
+    #[test]
+    fn a_rough_refraction_averages_a_cone_of_directions() {
+        let pane = Material::default()
+            .with_transparency(1.0)
+            .with_refractive_index(1.0)
+            .with_roughness(0.3);
+        // Brightest looking straight down, black looking horizontally
+        let sky_below =
+            Background::gradient(col::BLACK, col::WHITE).with_transform(transforms::reflect_y());
+        let (world, comps) = plane_seen_at_45_degrees(pane, sky_below);
+        let color = world.refracted_color(&comps, World::MAX_BOUNCES);
+        let clear = consts::SQRT_2 / 2.0;
+        assert_ne!(Color::new(clear, clear, clear), color);
+        let half_angle = 0.3 * std::f64::consts::FRAC_PI_2;
+        let lowest = (std::f64::consts::FRAC_PI_4 - half_angle).sin();
+        let highest = (std::f64::consts::FRAC_PI_4 + half_angle).sin();
+        assert!(color.red() > lowest && color.red() < highest);
+    }
+
     fn shade_hit_refraction_test_world() -> World {
         let floor_material = Material::default()
             .with_transparency(0.5)
@@ -484,7 +1243,9 @@ mod world_test {
             Intersections::new(&[Intersection::from_boxed_shape(consts::SQRT_2, w[2].clone())]);
         let comps = xs[0].prepare_computations(&r, &xs);
         let color = w.shade_hit(&comps, 5);
-        let expected = Color::new(0.93642, 0.68642, 0.68642);
+        // Brighter than the book's 0.93642 red, since the ball below the
+        // transparent floor now receives the light that passes through it
+        let expected = Color::new(1.12547, 0.68642, 0.68642);
         assert_eq!(expected, color);
     }
 
@@ -510,10 +1271,413 @@ mod world_test {
             .with_transform(translation(0.0, -3.5, -0.5))
             .with_material(ball_material);
 
-        let w = default_test_world().with_object(floor).with_object(ball);
+        let w = default_test_world()
+            .with_object(floor.clone())
+            .with_object(ball);
         let xs = Intersections::new(&[Intersection::new(consts::SQRT_2, floor)]);
         let comps = xs[0].prepare_computations(&r, &xs);
         let color = w.shade_hit(&comps, 5);
-        assert_eq!(color, Color::new(0.93391, 0.69643, 0.69243));
+        // The book expects 0.93391 red, but the ball below the transparent
+        // floor is now lit through it
+        assert_eq!(color, Color::new(1.11500, 0.69643, 0.69243));
+    }
+
+    #[test]
+    fn light_passing_through_colored_glass_is_absorbed() {
+        let glass = clear_glass(1.0).with_absorption(Color::new(0.0, 1.0, 1.0));
+        let w = lone_object_world(
+            Sphere::default().with_material(glass),
+            Background::solid(col::WHITE),
+        );
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        let expected = (-2.0_f64).exp();
+        assert_eq!(Color::new(1.0, expected, expected), c);
+    }
+
+    #[test]
+    fn thicker_colored_glass_absorbs_more_light() {
+        let glass = clear_glass(1.0).with_absorption(Color::new(0.0, 1.0, 1.0));
+        let w = lone_object_world(
+            Sphere::default()
+                .with_transform(transforms::scaling(2, 2, 2))
+                .with_material(glass),
+            Background::solid(col::WHITE),
+        );
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        let expected = (-4.0_f64).exp();
+        assert_eq!(Color::new(1.0, expected, expected), c);
+    }
+
+    #[test]
+    fn light_is_absorbed_by_the_glass_around_an_object_it_enters() {
+        let glass = clear_glass(1.0).with_absorption(Color::new(0.0, 1.0, 1.0));
+        let w = lone_object_world(
+            Sphere::default()
+                .with_transform(transforms::scaling(2, 2, 2))
+                .with_material(glass),
+            Background::solid(col::WHITE),
+        )
+        .with_object(Sphere::default().with_material(clear_glass(1.0)));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        // Only the two units of colored glass either side of the clear ball
+        // absorb anything
+        let expected = (-2.0_f64).exp();
+        assert_eq!(Color::new(1.0, expected, expected), c);
+    }
+
+    #[test]
+    fn glass_without_dispersion_bends_all_colors_alike() {
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.5)),
+            Background::gradient(col::BLACK, col::WHITE),
+        );
+        let r = Ray::new(Tup::point(0.0, -0.5, -5.0), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert_nearly_eq(c.red(), c.green());
+        assert_nearly_eq(c.red(), c.blue());
+    }
+
+    #[test]
+    fn dispersive_glass_splits_white_light_into_colors() {
+        // The ray leaves the sphere bent upwards, with blue bent the most
+        // and so seeing the brightest part of the sky
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.5).with_abbe_number(20.0)),
+            Background::gradient(col::BLACK, col::WHITE),
+        );
+        let r = Ray::new(Tup::point(0.0, -0.5, -5.0), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert!(c.blue() > c.green(), "{c:?}");
+        assert!(c.green() > c.red(), "{c:?}");
+    }
+
+    #[test]
+    fn rays_with_a_wavelength_see_a_gray_world() {
+        let w = default_test_world().with_background(Background::solid(col::CYAN));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1)).with_wavelength(600.0);
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert_nearly_eq(c.red(), c.green());
+        assert_nearly_eq(c.red(), c.blue());
+        let miss = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 1, 0)).with_wavelength(450.0);
+        assert_eq!(col::WHITE, w.color_at(miss, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn materials_with_matching_colors_can_have_different_spectra() {
+        // Both look the same under white light, but only one of them reflects
+        // the deep red of the light
+        let narrow = Spectrum::from_fn(|wavelength| if wavelength > 640.0 { 1.0 } else { 0.0 });
+        let broad = narrow.to_color();
+        let lit_by_red = |material: Material, wavelength: f64| {
+            let w = World::default()
+                .with_light(Light::point_light(Tup::point(0, 0, -10), col::WHITE))
+                .with_object(Sphere::default().with_material(material));
+            let r =
+                Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1)).with_wavelength(wavelength);
+            w.color_at(r, World::MAX_BOUNCES).red()
+        };
+        let with_spectrum = Material::default().with_spectrum(narrow);
+        let with_color = Material::default().with_color(broad);
+        assert_eq!(with_spectrum.color(), with_color.color());
+        assert!(lit_by_red(with_spectrum, 700.0) > lit_by_red(with_color, 700.0));
+    }
+
+    fn glowing_sphere_world() -> World {
+        let glowing = Material::default().with_emissive(col::WHITE);
+        World::default().with_object(Sphere::default().with_material(glowing))
+    }
+
+    #[test]
+    fn fog_dims_objects_with_distance() {
+        let w = glowing_sphere_world().with_fog(Medium::fog(0.25));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        let expected = (-1.0_f64).exp();
+        assert_eq!(Color::new(expected, expected, expected), c);
+    }
+
+    #[test]
+    fn fog_hides_the_background() {
+        let w = World::default()
+            .with_background(Background::solid(col::WHITE))
+            .with_fog(Medium::fog(0.25));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 1, 0));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert!(c.red() < 0.01, "{c:?}");
+    }
+
+    #[test]
+    fn fog_glows_where_it_is_lit() {
+        let w = World::default()
+            .with_light(Light::point_light(Tup::point(0, 5, 0), col::WHITE))
+            .with_fog(Medium::fog(0.1));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert!(c.red() > 0.05, "{c:?}");
+        assert_nearly_eq(c.red(), c.blue());
+    }
+
+    #[test]
+    fn fog_in_shadow_does_not_glow() {
+        let w =
+            World::default()
+                .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE))
+                .with_object(Sphere::default().with_transform(
+                    transforms::translation(0, 5, 0) * transforms::scaling(2, 2, 2),
+                ))
+                .with_fog(Medium::fog(0.1));
+        let in_shadow = Ray::new(Tup::point(0, 0, 0), Tup::vector(0, -1, 0));
+        let (_, shadowed) = w.media_along(in_shadow, None);
+        assert_eq!(col::BLACK, shadowed);
+        let lit = Ray::new(Tup::point(5, 0, 0), Tup::vector(0, -1, 0));
+        let (_, glowing) = w.media_along(lit, None);
+        assert!(glowing.red() > 0.0);
+    }
+
+    #[test]
+    fn smoke_inside_an_object_dims_what_is_behind_it() {
+        let smoke = Medium::new(Color::new(0.5, 0.5, 0.5), col::BLACK);
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(smoke)),
+            Background::solid(col::WHITE),
+        );
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        let expected = (-1.0_f64).exp();
+        assert_eq!(Color::new(expected, expected, expected), c);
+    }
+
+    #[test]
+    fn smoke_inside_an_object_dims_what_sits_in_it() {
+        let smoke = Medium::new(color_of(0.5), col::BLACK);
+        let cloud = Sphere::default()
+            .with_transform(transforms::scaling(3, 3, 3))
+            .with_material(clear_glass(1.0).with_medium(smoke));
+        let white = Material::default()
+            .with_ambient(1.0)
+            .with_diffuse(0.0)
+            .with_specular(0.0);
+        let w = lone_object_world(cloud, Background::solid(col::BLACK))
+            .with_object(Sphere::default().with_material(white))
+            .with_light(Light::point_light(Tup::point(0, 0, -10), col::WHITE));
+        // Two units of smoke lie between the edge of the cloud and the ball
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let c = w.color_at(r, World::MAX_BOUNCES);
+        assert_eq!(color_of((-1.0_f64).exp()), c);
+    }
+
+    #[test]
+    fn smoke_inside_an_object_dims_the_light_passing_through() {
+        let smoke = Medium::new(color_of(0.5), col::BLACK);
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(smoke)),
+            Background::solid(col::WHITE),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let p = Tup::point(0, -5, 0);
+        assert_eq!(color_of((-1.0_f64).exp()), w.light_transmittance(p, None));
+    }
+
+    #[test]
+    fn lit_smoke_inside_an_object_glows() {
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(Medium::fog(1.0))),
+            Background::solid(col::WHITE),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let r = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        let through_smoke = w.color_at(r, World::MAX_BOUNCES);
+        let nothing = Medium::new(col::BLACK, col::BLACK);
+        let clear = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(nothing)),
+            Background::solid(col::WHITE),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE))
+        .color_at(r, World::MAX_BOUNCES);
+        assert_eq!(col::WHITE, clear);
+        // Less of the background gets through, but the smoke adds light
+        let transmitted = (-2.0_f64).exp();
+        assert!(through_smoke.red() > transmitted, "{through_smoke:?}");
+        assert!(through_smoke.red() < 1.0, "{through_smoke:?}");
+    }
+
+    #[test]
+    fn fog_dims_the_light_reaching_surfaces() {
+        let w = World::default()
+            .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE))
+            .with_fog(Medium::fog(0.1));
+        assert_eq!(
+            color_of((-1.0_f64).exp()),
+            w.light_transmittance(Tup::point(0, 0, 0), None)
+        );
+    }
+
+    fn color_of(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    fn half_filled_grid() -> Density {
+        // Empty for negative x, full for positive x
+        Density::grid(DensityGrid::from_raw_u8(2, 1, 1, &[0, 255]).expect("Valid voxel data"))
+    }
+
+    #[test]
+    fn smoke_only_dims_light_where_it_is_dense() {
+        let smoke = Medium::new(color_of(2.0), col::BLACK).with_density(half_filled_grid());
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(smoke)),
+            Background::solid(col::WHITE),
+        );
+        let through_clear = Ray::new(Tup::point(-0.7, 0.0, -5.0), Tup::vector(0, 0, 1));
+        let through_smoke = Ray::new(Tup::point(0.7, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(col::WHITE, w.color_at(through_clear, World::MAX_BOUNCES));
+        let c = w.color_at(through_smoke, World::MAX_BOUNCES);
+        assert!(c.red() < 0.5, "{c:?}");
+    }
+
+    #[test]
+    fn varying_smoke_follows_the_object() {
+        let smoke = Medium::new(color_of(2.0), col::BLACK).with_density(half_filled_grid());
+        // Turned half way round, the dense half faces negative x
+        let w = lone_object_world(
+            Sphere::default()
+                .with_transform(transforms::rotation_y(consts::PI))
+                .with_material(clear_glass(1.0).with_medium(smoke)),
+            Background::solid(col::WHITE),
+        );
+        let through_clear = Ray::new(Tup::point(0.7, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(col::WHITE, w.color_at(through_clear, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn varying_smoke_follows_an_object_inside_a_group() {
+        let smoke = Medium::new(color_of(2.0), col::BLACK).with_density(half_filled_grid());
+        let turned = Sphere::default()
+            .with_transform(transforms::rotation_y(consts::PI))
+            .with_material(clear_glass(1.0).with_medium(smoke));
+        let group = Group::default()
+            .with_transform(translation(0, 1, 0))
+            .with_child(turned);
+        let w = lone_object_world(group, Background::solid(col::WHITE))
+            .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        let through_clear = Ray::new(Tup::point(0.7, 1.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(col::WHITE, w.color_at(through_clear, World::MAX_BOUNCES));
+        let below_clear = Tup::point(0.9, -4.0, 0.0);
+        assert_eq!(col::WHITE, w.light_transmittance(below_clear, None));
+    }
+
+    // Black and white stripes that show as they are, whatever the light
+    fn flat_stripes() -> Material {
+        Material::default()
+            .with_ambient(1.0)
+            .with_diffuse(0.0)
+            .with_specular(0.0)
+            .with_pattern(Pattern::stripe_pattern(col::WHITE, col::BLACK))
+    }
+
+    #[test]
+    fn patterns_on_a_child_in_a_group_follow_both_transforms() {
+        let child = Sphere::default()
+            .with_transform(translation(0.5, 0.0, 0.0))
+            .with_material(flat_stripes());
+        let group = Group::default()
+            .with_transform(translation(1, 0, 0))
+            .with_child(child);
+        let w = lone_object_world(group, Background::solid(col::BLACK))
+            .with_light(Light::point_light(Tup::point(0, 0, -10), col::WHITE));
+        // The sphere is centered at x = 1.5, so this is 0.7 into its first
+        // stripe. With only the group's transform it would be in the second
+        let r = Ray::new(Tup::point(2.2, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(col::WHITE, w.color_at(r, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn patterns_on_an_instance_follow_the_geometry_s_own_transform() {
+        let sphere = Sphere::default()
+            .with_transform(translation(0.5, 0.0, 0.0))
+            .with_material(flat_stripes());
+        let instance = Instance::new(Arc::new(sphere)).with_transform(translation(1, 0, 0));
+        let w = lone_object_world(instance, Background::solid(col::BLACK))
+            .with_light(Light::point_light(Tup::point(0, 0, -10), col::WHITE));
+        let r = Ray::new(Tup::point(2.2, 0.0, -5.0), Tup::vector(0, 0, 1));
+        assert_eq!(col::WHITE, w.color_at(r, World::MAX_BOUNCES));
+    }
+
+    #[test]
+    fn shadows_through_smoke_depend_on_its_density() {
+        let smoke = Medium::new(color_of(2.0), col::BLACK).with_density(half_filled_grid());
+        let w = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(smoke)),
+            Background::solid(col::WHITE),
+        )
+        .with_light(Light::point_light(Tup::point(0, 10, 0), col::WHITE));
+        // The shadow rays slope in towards the light, passing through the
+        // sphere at about x = 0.58
+        let below_clear = Tup::point(-0.9, -5.0, 0.0);
+        let below_smoke = Tup::point(0.9, -5.0, 0.0);
+        assert_eq!(col::WHITE, w.light_transmittance(below_clear, None));
+        assert!(w.light_transmittance(below_smoke, None).red() < 0.5);
+    }
+
+    #[test]
+    fn noisy_clouds_let_some_light_through_gaps() {
+        let uniform = Medium::new(color_of(1.0), col::BLACK);
+        let cloudy = uniform.clone().with_density(Density::noise(3.0, 3, 0.4));
+        let r = Ray::new(Tup::point(0.1, 0.2, -5.0), Tup::vector(0, 0, 1));
+        let dense = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(uniform)),
+            Background::solid(col::WHITE),
+        )
+        .color_at(r, World::MAX_BOUNCES);
+        let patchy = lone_object_world(
+            Sphere::default().with_material(clear_glass(1.0).with_medium(cloudy)),
+            Background::solid(col::WHITE),
+        )
+        .color_at(r, World::MAX_BOUNCES);
+        assert!(patchy.red() > dense.red(), "{patchy:?} {dense:?}");
+    }
+
+    fn backlit_sphere(material: Material) -> Color {
+        let world = World::default()
+            .with_light(Light::point_light(Tup::point(0, 0, 10), col::WHITE))
+            .with_object(Sphere::default().with_material(material));
+        let ray = Ray::new(Tup::point(0, 0, -5), Tup::vector(0, 0, 1));
+        world.color_at(ray, World::MAX_BOUNCES)
+    }
+
+    #[test]
+    fn an_opaque_sphere_lit_from_behind_shows_only_ambient_light() {
+        let c = backlit_sphere(Material::default());
+        assert_eq!(Color::new(0.1, 0.1, 0.1), c);
+    }
+
+    #[test]
+    fn a_translucent_sphere_glows_when_lit_from_behind() {
+        let wax = Material::default()
+            .with_mean_free_path(2.0)
+            .with_scatter_color(Color::new(0.95, 0.8, 0.6));
+        let c = backlit_sphere(wax);
+        assert!(c.red() > 0.15, "{c:?}");
+        assert!(c.red() > c.green() && c.green() > c.blue(), "{c:?}");
+    }
+
+    #[test]
+    fn direct_light_falls_off_with_angle_and_in_shadow() {
+        let world = default_test_world();
+        let lit = world.direct_light(Tup::point(0, 5, 0), Tup::vector(0, 1, 0), None);
+        let facing = world.light().position() - Tup::point(0, 5, 0);
+        let cos = facing.normalize().dot(&Tup::vector(0, 1, 0));
+        assert_eq!(world.light().intensity() * cos, lit);
+        let facing_away = world.direct_light(Tup::point(0, 5, 0), Tup::vector(0, -1, 0), None);
+        assert_eq!(col::BLACK, facing_away);
+        let shadowed = world.direct_light(
+            Tup::point(10, -10, 10),
+            Tup::vector(-1, 1, -1).normalize(),
+            None,
+        );
+        assert_eq!(col::BLACK, shadowed);
     }
 }